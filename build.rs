@@ -3,37 +3,94 @@ use std::fs;
 use std::io;
 use std::io::Read;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use zip::write::FileOptions;
 use zip::ZipWriter;
 
 fn main() -> io::Result<()> {
     let out_dir =
         env::var_os("OUT_DIR").expect("OUT_DIR should be defined by cargo during compilation");
-    let folder_path = Path::new("default_template");
+    // Forks can bundle their own offline template, relative paths are relative to this package
+    println!("cargo:rerun-if-env-changed=L1X_DEFAULT_TEMPLATE_DIR");
+    // The release pins the default container image to the digest of the pushed image
+    println!("cargo:rerun-if-env-changed=L1X_CONTAINER_IMAGE_DIGEST");
+    let folder_path = env::var_os("L1X_DEFAULT_TEMPLATE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("default_template"));
+    println!("cargo:rerun-if-changed={}", folder_path.display());
+    println!("cargo:rerun-if-changed=build.rs");
     let zip_path = Path::new(&out_dir).join("default_template.zip");
 
     let file = fs::File::create(zip_path)?;
     let mut zip = ZipWriter::new(file);
 
-    zip.add_directory(folder_path.to_string_lossy(), FileOptions::<()>::default())?;
-    zip_folder(&mut zip, folder_path)?;
+    // The entries are always in a `default_template` top-level directory, whatever the source
+    let archive_path = Path::new("default_template");
+    zip.add_directory(archive_path.to_string_lossy(), FileOptions::<()>::default())?;
+    zip_folder(&mut zip, &folder_path, archive_path)?;
 
     zip.finish()?;
 
+    write_host_functions(Path::new(&out_dir))?;
+
     Ok(())
 }
 
-fn zip_folder(writer: &mut ZipWriter<fs::File>, folder_path: &Path) -> io::Result<()> {
+/// A copy of the `lib.rs` of l1x-sys, its `extern "C"` block declares the host functions
+/// of the runtime. Replace it with the one of a newer l1x-sys to update them
+const L1X_SYS_LIB: &str = "host_functions/l1x-sys-0.2.2.rs";
+
+/// Writes `KNOWN_HOST_FUNCTIONS` with the functions of the `extern "C"` block of
+/// [`L1X_SYS_LIB`], included by `src/imports.rs`
+fn write_host_functions(out_dir: &Path) -> io::Result<()> {
+    println!("cargo:rerun-if-changed={}", L1X_SYS_LIB);
+    let source = fs::read_to_string(L1X_SYS_LIB)?;
+    let block = source
+        .split_once("extern \"C\" {")
+        .and_then(|(_, rest)| rest.split_once("\n}"))
+        .map(|(block, _)| block)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no extern \"C\" block", L1X_SYS_LIB),
+            )
+        })?;
+    let names: Vec<&str> = block
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("pub fn "))
+        .filter_map(|declaration| declaration.split('(').next())
+        .map(str::trim)
+        .collect();
+
+    let mut code = format!(
+        "/// Host functions of the runtime, the `extern \"C\"` functions of `{}`.\n\
+         /// Newer runtimes can add some with `host-functions` in `l1x.toml`\n\
+         pub const KNOWN_HOST_FUNCTIONS: [&str; {}] = [\n",
+        L1X_SYS_LIB,
+        names.len()
+    );
+    for name in names {
+        code.push_str(&format!("    {:?},\n", name));
+    }
+    code.push_str("];\n");
+    fs::write(out_dir.join("host_functions.rs"), code)
+}
+
+fn zip_folder(
+    writer: &mut ZipWriter<fs::File>,
+    folder_path: &Path,
+    archive_path: &Path,
+) -> io::Result<()> {
     for entry in fs::read_dir(folder_path)? {
         let entry = entry?;
         let path = entry.path();
+        let entry_path = archive_path.join(entry.file_name());
         if path.is_dir() {
             if path.file_name() == Some("target".as_ref()) {
                 continue; // ignore target directory
             }
-            writer.add_directory(path.to_string_lossy(), FileOptions::<()>::default())?;
-            zip_folder(writer, &path)?;
+            writer.add_directory(entry_path.to_string_lossy(), FileOptions::<()>::default())?;
+            zip_folder(writer, &path, &entry_path)?;
             continue;
         }
 
@@ -45,7 +102,7 @@ fn zip_folder(writer: &mut ZipWriter<fs::File>, folder_path: &Path) -> io::Resul
             .compression_method(zip::CompressionMethod::Deflated)
             .unix_permissions(0o755);
 
-        writer.start_file(path.to_string_lossy(), options)?;
+        writer.start_file(entry_path.to_string_lossy(), options)?;
         writer.write_all(&contents)?;
     }
 
@@ -0,0 +1,648 @@
+use super::exit_code;
+use object::{Object, ObjectSymbol, SymbolKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AbiError {
+    #[error("Failed to read ABI file {0}: {1}")]
+    IoError(PathBuf, std::io::Error),
+    #[error("Invalid ABI file {0}: {1}")]
+    ParseError(PathBuf, serde_json::Error),
+    #[error("The new ABI has {0} breaking change(s)")]
+    BreakingChanges(usize),
+    #[error("Could not parse {0}: {1}")]
+    SourceParseError(PathBuf, syn::Error),
+    #[error("No #[contract] impl found in {0}")]
+    NoContract(PathBuf),
+    #[error("Could not read the exports of {0}: {1}")]
+    ModuleParseError(PathBuf, String),
+    #[error("Several #[contract] impls in {}, select one with --contract-type: {}", .0.display(), .1.join(", "))]
+    AmbiguousContract(PathBuf, Vec<String>),
+    #[error("No #[contract] impl of '{name}' in {}, found: {}", path.display(), available.join(", "))]
+    UnknownContractType {
+        path: PathBuf,
+        name: String,
+        available: Vec<String>,
+    },
+}
+
+impl AbiError {
+    /// Stable identifier of the error for scripts, see `--json`
+    pub fn code(&self) -> &'static str {
+        match self {
+            AbiError::IoError(..) => "abi-io",
+            AbiError::ParseError(..) => "abi-parse",
+            AbiError::BreakingChanges(_) => "abi-breaking-changes",
+            AbiError::SourceParseError(..) => "abi-source-parse",
+            AbiError::NoContract(_) => "abi-no-contract",
+            AbiError::ModuleParseError(..) => "abi-module-parse",
+            AbiError::AmbiguousContract(..) => "abi-ambiguous-contract",
+            AbiError::UnknownContractType { .. } => "abi-unknown-contract-type",
+        }
+    }
+
+    /// The exit code of `cargo l1x` for the error, see [`exit_code`]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AbiError::ParseError(..)
+            | AbiError::AmbiguousContract(..)
+            | AbiError::UnknownContractType { .. } => exit_code::USAGE,
+            AbiError::SourceParseError(..)
+            | AbiError::NoContract(_)
+            | AbiError::ModuleParseError(..) => exit_code::OBJECT,
+            AbiError::IoError(..) | AbiError::BreakingChanges(_) => exit_code::FAILURE,
+        }
+    }
+
+    /// The data of the error as JSON, see `--json`
+    pub fn context(&self) -> serde_json::Value {
+        use serde_json::json;
+        match self {
+            AbiError::IoError(path, _)
+            | AbiError::ParseError(path, _)
+            | AbiError::SourceParseError(path, _)
+            | AbiError::NoContract(path)
+            | AbiError::ModuleParseError(path, _) => json!({ "path": path }),
+            AbiError::BreakingChanges(count) => json!({ "breaking_changes": count }),
+            AbiError::AmbiguousContract(path, contracts) => {
+                json!({ "path": path, "contracts": contracts })
+            }
+            AbiError::UnknownContractType {
+                path,
+                name,
+                available,
+            } => json!({ "path": path, "contract_type": name, "contracts": available }),
+        }
+    }
+}
+
+/// Argument of a contract method
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AbiArg {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+/// Public method of a contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AbiMethod {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<AbiArg>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub returns: Option<String>,
+}
+
+/// Description of the public interface of a contract, stored as `<name>.abi.json`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Abi {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<String>,
+    pub methods: Vec<AbiMethod>,
+}
+
+impl Abi {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, AbiError> {
+        let path = path.as_ref();
+        let content =
+            fs::read_to_string(path).map_err(|e| AbiError::IoError(path.to_path_buf(), e))?;
+        serde_json::from_str(&content).map_err(|e| AbiError::ParseError(path.to_path_buf(), e))
+    }
+
+    /// Extracts the public methods of the `#[contract]` impl from the sources in `src_dir`,
+    /// there must be only one, see [`Abi::from_source_of`]
+    pub fn from_source<P: AsRef<Path>>(src_dir: P) -> Result<Self, AbiError> {
+        Self::from_source_of(src_dir, None)
+    }
+
+    /// Extracts the public methods of the `#[contract]` impls of `contract_type` from the
+    /// sources in `src_dir`, e.g. `Counter` or `crate::Counter`. Without a type the sources
+    /// must have the impls of a single type. Several impls of a type are merged
+    pub fn from_source_of<P: AsRef<Path>>(
+        src_dir: P,
+        contract_type: Option<&str>,
+    ) -> Result<Self, AbiError> {
+        let src_dir = src_dir.as_ref();
+        let mut contracts: Vec<Abi> = Vec::new();
+        for path in source_files(src_dir)? {
+            let content =
+                fs::read_to_string(&path).map_err(|e| AbiError::IoError(path.clone(), e))?;
+            let file =
+                syn::parse_file(&content).map_err(|e| AbiError::SourceParseError(path, e))?;
+            for abi in find_contracts(&file.items) {
+                match contracts
+                    .iter_mut()
+                    .find(|contract| contract.contract == abi.contract)
+                {
+                    Some(contract) => contract.methods.extend(abi.methods),
+                    None => contracts.push(abi),
+                }
+            }
+        }
+        let names = || {
+            contracts
+                .iter()
+                .filter_map(|contract| contract.contract.clone())
+                .collect::<Vec<_>>()
+        };
+        match (contract_type, contracts.len()) {
+            (_, 0) => Err(AbiError::NoContract(src_dir.to_path_buf())),
+            (None, 1) => Ok(contracts.remove(0)),
+            (None, _) => Err(AbiError::AmbiguousContract(src_dir.to_path_buf(), names())),
+            (Some(name), _) => match contracts.iter().position(|contract| {
+                contract
+                    .contract
+                    .as_deref()
+                    .is_some_and(|ty| is_type(ty, name))
+            }) {
+                Some(index) => Ok(contracts.remove(index)),
+                None => Err(AbiError::UnknownContractType {
+                    path: src_dir.to_path_buf(),
+                    name: name.to_string(),
+                    available: names(),
+                }),
+            },
+        }
+    }
+
+    /// The name-level ABI of a compiled contract: its exported functions, as methods
+    /// without arguments or return type. `module` is a wasm module or an object file
+    pub fn from_exports<P: AsRef<Path>>(module: P) -> Result<Self, AbiError> {
+        let path = module.as_ref();
+        let data = fs::read(path).map_err(|e| AbiError::IoError(path.to_path_buf(), e))?;
+        let mut names = if data.starts_with(b"\0asm") {
+            wasm_exports(&data).map_err(|e| e.to_string())
+        } else {
+            object_exports(&data).map_err(|e| e.to_string())
+        }
+        .map_err(|e| AbiError::ModuleParseError(path.to_path_buf(), e))?;
+        // `__heap_base` and the like, the version globals and the mangled helpers
+        names.retain(|name| !name.starts_with('_'));
+        names.sort();
+        names.dedup();
+        Ok(Self {
+            contract: None,
+            methods: names
+                .into_iter()
+                .map(|name| AbiMethod {
+                    name,
+                    args: Vec::new(),
+                    returns: None,
+                })
+                .collect(),
+        })
+    }
+
+    fn methods_by_name(&self) -> BTreeMap<&str, &AbiMethod> {
+        self.methods
+            .iter()
+            .map(|method| (method.name.as_str(), method))
+            .collect()
+    }
+}
+
+/// Method level differences between two versions of a contract ABI
+#[derive(Debug, Default, PartialEq)]
+pub struct AbiDiff {
+    pub added: Vec<AbiMethod>,
+    /// Removed methods break existing callers
+    pub removed: Vec<AbiMethod>,
+    /// Methods whose signature changed, as `(old, new)`. Break existing callers
+    pub changed: Vec<(AbiMethod, AbiMethod)>,
+}
+
+impl AbiDiff {
+    pub fn between(old: &Abi, new: &Abi) -> Self {
+        let old_methods = old.methods_by_name();
+        let new_methods = new.methods_by_name();
+        let mut diff = AbiDiff::default();
+
+        for (name, new_method) in &new_methods {
+            match old_methods.get(name) {
+                None => diff.added.push((*new_method).clone()),
+                Some(old_method) if old_method != new_method => diff
+                    .changed
+                    .push(((*old_method).clone(), (*new_method).clone())),
+                Some(_) => {}
+            }
+        }
+        for (name, old_method) in &old_methods {
+            if !new_methods.contains_key(name) {
+                diff.removed.push((*old_method).clone());
+            }
+        }
+        diff
+    }
+
+    pub fn breaking_changes(&self) -> usize {
+        self.removed.len() + self.changed.len()
+    }
+
+    pub fn print(&self) {
+        if self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() {
+            println!("No ABI changes");
+            return;
+        }
+        for method in &self.added {
+            println!("+ {}", signature(method));
+        }
+        for method in &self.removed {
+            println!("- {} (breaking)", signature(method));
+        }
+        for (old, new) in &self.changed {
+            println!("~ {} -> {} (breaking)", signature(old), signature(new));
+        }
+    }
+}
+
+fn signature(method: &AbiMethod) -> String {
+    let args = method
+        .args
+        .iter()
+        .map(|arg| format!("{}: {}", arg.name, arg.ty))
+        .collect::<Vec<_>>()
+        .join(", ");
+    match &method.returns {
+        Some(returns) => format!("{}({}) -> {}", method.name, args, returns),
+        None => format!("{}({})", method.name, args),
+    }
+}
+
+fn source_files(dir: &Path) -> Result<Vec<PathBuf>, AbiError> {
+    let mut files = Vec::new();
+    let entries = fs::read_dir(dir).map_err(|e| AbiError::IoError(dir.to_path_buf(), e))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| AbiError::IoError(dir.to_path_buf(), e))?
+            .path();
+        if path.is_dir() {
+            files.extend(source_files(&path)?);
+        } else if path.extension() == Some("rs".as_ref()) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The names of the functions exported by a wasm module
+fn wasm_exports(wasm: &[u8]) -> Result<Vec<String>, wasmparser::BinaryReaderError> {
+    let mut names = Vec::new();
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        if let wasmparser::Payload::ExportSection(reader) = payload? {
+            for export in reader {
+                let export = export?;
+                if export.kind == wasmparser::ExternalKind::Func {
+                    names.push(export.name.to_string());
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The names of the global functions an object file defines
+fn object_exports(data: &[u8]) -> Result<Vec<String>, object::Error> {
+    let file = object::File::parse(data)?;
+    let names = file
+        .symbols()
+        .filter(|symbol| {
+            symbol.is_global() && symbol.is_definition() && symbol.kind() == SymbolKind::Text
+        })
+        .filter_map(|symbol| symbol.name().ok().map(str::to_string))
+        .collect();
+    Ok(names)
+}
+
+/// The impls annotated with `#[contract]`, looking into inline modules
+fn find_contracts(items: &[syn::Item]) -> Vec<Abi> {
+    items
+        .iter()
+        .flat_map(|item| match item {
+            syn::Item::Impl(item_impl) if is_contract(item_impl) => vec![contract_abi(item_impl)],
+            syn::Item::Mod(item_mod) => item_mod
+                .content
+                .as_ref()
+                .map_or_else(Vec::new, |(_, items)| find_contracts(items)),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// Whether the type `ty` of an impl is `name`, which can leave out the path of the type
+fn is_type(ty: &str, name: &str) -> bool {
+    ty == name || ty.rsplit("::").next() == Some(name)
+}
+
+fn is_contract(item_impl: &syn::ItemImpl) -> bool {
+    item_impl.attrs.iter().any(|attr| {
+        attr.path()
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "contract")
+    })
+}
+
+fn contract_abi(item_impl: &syn::ItemImpl) -> Abi {
+    let methods = item_impl
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::ImplItem::Fn(method) if matches!(method.vis, syn::Visibility::Public(_)) => {
+                Some(method)
+            }
+            _ => None,
+        })
+        .map(|method| AbiMethod {
+            name: method.sig.ident.to_string(),
+            args: method
+                .sig
+                .inputs
+                .iter()
+                .filter_map(|input| match input {
+                    syn::FnArg::Typed(arg) => Some(AbiArg {
+                        name: match &*arg.pat {
+                            syn::Pat::Ident(pat) => pat.ident.to_string(),
+                            _ => "_".to_string(),
+                        },
+                        ty: type_name(&arg.ty),
+                    }),
+                    syn::FnArg::Receiver(_) => None,
+                })
+                .collect(),
+            returns: match &method.sig.output {
+                syn::ReturnType::Default => None,
+                syn::ReturnType::Type(_, ty) => Some(type_name(ty)),
+            },
+        })
+        .collect();
+    Abi {
+        contract: Some(type_name(&item_impl.self_ty)),
+        methods,
+    }
+}
+
+/// Renders a type the way it's written in the source, e.g. `Vec<(String, u64)>`
+pub(crate) fn type_name(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .iter()
+            .map(|segment| match &segment.arguments {
+                syn::PathArguments::AngleBracketed(arguments) => {
+                    let arguments = arguments
+                        .args
+                        .iter()
+                        .filter_map(|argument| match argument {
+                            syn::GenericArgument::Type(ty) => Some(type_name(ty)),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!("{}<{}>", segment.ident, arguments)
+                }
+                _ => segment.ident.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("::"),
+        syn::Type::Reference(reference) => format!("&{}", type_name(&reference.elem)),
+        syn::Type::Slice(slice) => format!("[{}]", type_name(&slice.elem)),
+        syn::Type::Array(array) => match &array.len {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(len),
+                ..
+            }) => format!("[{}; {}]", type_name(&array.elem), len.base10_digits()),
+            _ => format!("[{}; _]", type_name(&array.elem)),
+        },
+        syn::Type::Tuple(tuple) => format!(
+            "({})",
+            tuple
+                .elems
+                .iter()
+                .map(type_name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        syn::Type::Paren(paren) => type_name(&paren.elem),
+        syn::Type::Group(group) => type_name(&group.elem),
+        _ => "_".to_string(),
+    }
+}
+
+/// Compares two ABI files, fails if the new one breaks the old one
+pub fn abi_diff<P: AsRef<Path>>(old: P, new: P) -> Result<AbiDiff, AbiError> {
+    let diff = AbiDiff::between(&Abi::from_file(old)?, &Abi::from_file(new)?);
+    diff.print();
+    match diff.breaking_changes() {
+        0 => Ok(diff),
+        n => Err(AbiError::BreakingChanges(n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn method(name: &str, args: &[(&str, &str)], returns: Option<&str>) -> AbiMethod {
+        AbiMethod {
+            name: name.to_string(),
+            args: args
+                .iter()
+                .map(|(name, ty)| AbiArg {
+                    name: name.to_string(),
+                    ty: ty.to_string(),
+                })
+                .collect(),
+            returns: returns.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_abi_from_source() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("nested/mod.rs"), "pub fn helper(x: u8) {}").unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            r#"
+            impl Other { pub fn ignored() {} }
+
+            #[l1x_sdk::contract]
+            impl Contract {
+                fn load() -> Self { todo!() }
+                pub fn new() {}
+                pub fn set_counter(value: U64) -> U64 { value }
+                pub fn transfer(to: Address, amounts: Vec<(String, u128)>, memo: Option<[u8; 32]>) {}
+            }
+            "#,
+        )
+        .unwrap();
+
+        let abi = Abi::from_source(dir.path()).unwrap();
+        assert_eq!(abi.contract.as_deref(), Some("Contract"));
+        assert_eq!(
+            abi.methods,
+            vec![
+                method("new", &[], None),
+                method("set_counter", &[("value", "U64")], Some("U64")),
+                method(
+                    "transfer",
+                    &[
+                        ("to", "Address"),
+                        ("amounts", "Vec<(String, u128)>"),
+                        ("memo", "Option<[u8; 32]>")
+                    ],
+                    None
+                ),
+            ]
+        );
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            Abi::from_source(empty.path()),
+            Err(AbiError::NoContract(_))
+        ));
+    }
+
+    #[test]
+    fn test_abi_from_source_of() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            r#"
+            #[contract]
+            impl Token { pub fn name() -> String { todo!() } }
+
+            mod admin {
+                #[contract]
+                impl crate::Registry { pub fn register(id: u64) {} }
+            }
+
+            #[contract]
+            impl Token { pub fn symbol() -> String { todo!() } }
+            "#,
+        )
+        .unwrap();
+
+        let Err(AbiError::AmbiguousContract(_, contracts)) = Abi::from_source(dir.path()) else {
+            panic!("Two contract types should be ambiguous");
+        };
+        assert_eq!(contracts, ["Token", "crate::Registry"]);
+
+        let token = Abi::from_source_of(dir.path(), Some("Token")).unwrap();
+        assert_eq!(
+            token.methods,
+            vec![
+                method("name", &[], Some("String")),
+                method("symbol", &[], Some("String"))
+            ]
+        );
+        let registry = Abi::from_source_of(dir.path(), Some("Registry")).unwrap();
+        assert_eq!(registry.contract.as_deref(), Some("crate::Registry"));
+        assert!(matches!(
+            Abi::from_source_of(dir.path(), Some("Vault")),
+            Err(AbiError::UnknownContractType { name, .. }) if name == "Vault"
+        ));
+    }
+
+    #[test]
+    fn test_abi_from_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // two `() -> ()` functions
+        wasm.extend([1, 4, 1, 0x60, 0, 0, 3, 3, 2, 0, 0]);
+        let mut exports = vec![4];
+        for (name, kind, index) in [
+            ("inc", 0, 1),
+            ("get", 0, 0),
+            ("_start", 0, 0),
+            ("memory", 2, 0),
+        ] {
+            exports.push(name.len() as u8);
+            exports.extend(name.as_bytes());
+            exports.extend([kind, index]);
+        }
+        wasm.extend([7, exports.len() as u8]);
+        wasm.extend(exports);
+        wasm.extend([10, 7, 2, 2, 0, 0x0b, 2, 0, 0x0b]);
+        let path = dir.path().join("counter.wasm");
+        fs::write(&path, &wasm).unwrap();
+        assert_eq!(
+            Abi::from_exports(&path).unwrap(),
+            Abi {
+                contract: None,
+                methods: vec![method("get", &[], None), method("inc", &[], None)],
+            }
+        );
+
+        let mut object = object::write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Bpf,
+            object::Endianness::Little,
+        );
+        let text = object.add_section(Vec::new(), b".text".to_vec(), object::SectionKind::Text);
+        for (name, scope) in [
+            ("transfer", object::SymbolScope::Linkage),
+            ("helper", object::SymbolScope::Compilation),
+        ] {
+            let symbol = object.add_symbol(object::write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 8,
+                kind: SymbolKind::Text,
+                scope,
+                weak: false,
+                section: object::write::SymbolSection::Undefined,
+                flags: object::SymbolFlags::None,
+            });
+            object.add_symbol_data(symbol, text, &[0x95, 0, 0, 0, 0, 0, 0, 0], 8);
+        }
+        let path = dir.path().join("counter.o");
+        fs::write(&path, object.write().unwrap()).unwrap();
+        assert_eq!(
+            Abi::from_exports(&path).unwrap().methods,
+            vec![method("transfer", &[], None)]
+        );
+
+        fs::write(&path, b"not a module").unwrap();
+        assert!(matches!(
+            Abi::from_exports(&path),
+            Err(AbiError::ModuleParseError(..))
+        ));
+    }
+
+    #[test]
+    fn test_abi_diff() {
+        let old = Abi {
+            contract: Some("Contract".to_string()),
+            methods: vec![
+                method("new", &[], None),
+                method("set_counter", &[("value", "U64")], Some("U64")),
+                method("get_counter", &[], Some("U64")),
+            ],
+        };
+        let new = Abi {
+            contract: Some("Contract".to_string()),
+            methods: vec![
+                method("new", &[], None),
+                method("set_counter", &[("value", "u64")], Some("U64")),
+                method("inc_counter", &[], Some("U64")),
+            ],
+        };
+
+        let diff = AbiDiff::between(&old, &new);
+        assert_eq!(diff.added, vec![method("inc_counter", &[], Some("U64"))]);
+        assert_eq!(diff.removed, vec![method("get_counter", &[], Some("U64"))]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].0.name, "set_counter");
+        assert_eq!(diff.breaking_changes(), 2);
+
+        assert_eq!(AbiDiff::between(&old, &old).breaking_changes(), 0);
+    }
+}
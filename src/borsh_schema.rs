@@ -0,0 +1,119 @@
+use super::abi::{type_name, Abi};
+use serde_json::{json, Value};
+
+/// Describes how the arguments and return values of the contract methods are encoded with borsh
+///
+/// Types are resolved from their names only: primitives, `String`, the `l1x_sdk` integer
+/// wrappers and the std containers get a layout, other types are reported as `defined`
+pub fn borsh_schema(abi: &Abi) -> Value {
+    let methods: Vec<Value> = abi
+        .methods
+        .iter()
+        .map(|method| {
+            json!({
+                "name": method.name,
+                "args": method
+                    .args
+                    .iter()
+                    .map(|arg| json!({ "name": arg.name, "type": arg.ty, "layout": layout_of(&arg.ty) }))
+                    .collect::<Vec<_>>(),
+                "returns": method.returns.as_deref().map(layout_of),
+            })
+        })
+        .collect();
+    json!({
+        "contract": abi.contract,
+        "methods": methods,
+    })
+}
+
+fn layout_of(ty: &str) -> Value {
+    match syn::parse_str::<syn::Type>(ty) {
+        Ok(ty) => layout(&ty),
+        Err(_) => json!({ "defined": ty }),
+    }
+}
+
+fn layout(ty: &syn::Type) -> Value {
+    match ty {
+        syn::Type::Path(path) => {
+            let Some(segment) = path.path.segments.last() else {
+                return json!({ "defined": type_name(ty) });
+            };
+            let arguments: Vec<&syn::Type> = match &segment.arguments {
+                syn::PathArguments::AngleBracketed(arguments) => arguments
+                    .args
+                    .iter()
+                    .filter_map(|argument| match argument {
+                        syn::GenericArgument::Type(ty) => Some(ty),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            match (segment.ident.to_string().as_str(), arguments.as_slice()) {
+                (
+                    primitive @ ("u8" | "u16" | "u32" | "u64" | "u128" | "i8" | "i16" | "i32"
+                    | "i64" | "i128" | "f32" | "f64" | "bool" | "String"),
+                    [],
+                ) => json!(primitive.to_lowercase()),
+                // l1x_sdk::types wrappers are newtypes, borsh encodes the inner integer
+                ("U64", []) => json!("u64"),
+                ("U128", []) => json!("u128"),
+                ("I64", []) => json!("i64"),
+                ("I128", []) => json!("i128"),
+                ("Box", [inner]) => layout(inner),
+                ("Vec" | "VecDeque", [inner]) => json!({ "vec": layout(inner) }),
+                ("Option", [inner]) => json!({ "option": layout(inner) }),
+                ("Result", [ok, err]) => json!({ "result": [layout(ok), layout(err)] }),
+                ("HashSet" | "BTreeSet", [inner]) => json!({ "set": layout(inner) }),
+                ("HashMap" | "BTreeMap", [key, value]) => {
+                    json!({ "map": [layout(key), layout(value)] })
+                }
+                _ => json!({ "defined": type_name(ty) }),
+            }
+        }
+        syn::Type::Reference(reference) => match &*reference.elem {
+            syn::Type::Path(path) if path.path.is_ident("str") => json!("string"),
+            syn::Type::Slice(slice) => json!({ "vec": layout(&slice.elem) }),
+            elem => layout(elem),
+        },
+        syn::Type::Array(array) => match &array.len {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(len),
+                ..
+            }) => json!({ "array": layout(&array.elem), "len": len.base10_parse::<u64>().ok() }),
+            _ => json!({ "defined": type_name(ty) }),
+        },
+        syn::Type::Tuple(tuple) if tuple.elems.is_empty() => json!("unit"),
+        syn::Type::Tuple(tuple) => {
+            json!({ "tuple": tuple.elems.iter().map(layout).collect::<Vec<_>>() })
+        }
+        syn::Type::Paren(paren) => layout(&paren.elem),
+        _ => json!({ "defined": type_name(ty) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(layout_of("U64"), json!("u64"));
+        assert_eq!(layout_of("String"), json!("string"));
+        assert_eq!(layout_of("&str"), json!("string"));
+        assert_eq!(
+            layout_of("Vec<(String, u128)>"),
+            json!({ "vec": { "tuple": ["string", "u128"] } })
+        );
+        assert_eq!(
+            layout_of("Option<[u8; 32]>"),
+            json!({ "option": { "array": "u8", "len": 32 } })
+        );
+        assert_eq!(
+            layout_of("BTreeMap<Address, u64>"),
+            json!({ "map": [{ "defined": "Address" }, "u64"] })
+        );
+    }
+}
@@ -1,18 +1,36 @@
-use super::which::which;
-use anyhow::anyhow;
+use super::abi::{Abi, AbiError};
+use super::borsh_schema::borsh_schema;
+use super::build_events::{BuildEvent, EventSink};
+use super::build_log::BuildLog;
+use super::config::{is_profile_name, load_profiles, BuildConfig, CONFIG_FILE_NAME};
+use super::container::{build_in_container, default_container_image};
+use super::exit_code;
+use super::imports::{foreign_imports, unknown_imports};
+use super::object_diff::object_diff;
+use super::script::write_build_script;
+use super::stale::{find_stale_objects, warn_stale_objects};
+use super::symbol_sizes::{symbol_sizes, SymbolSize};
+use super::wasm_features::wasm_features;
+use super::which::{which, which_optional};
+use cargo_metadata::diagnostic::DiagnosticLevel;
+use cargo_metadata::{Metadata, MetadataCommand, Target};
 use l1x_wasm_llvmir::translate_module_to_file_by_path;
+use object::{Object, ObjectSection, ObjectSymbol};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{BufRead, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process;
 use std::process::Command;
 
 use thiserror::Error;
 
-const OBJECT_FILE_VERSION: i64 = 1;
-const EXPECTED_RUNTIME_VERSION: i64 = 3;
-const EBPF_STACK_FRAME_SIZE: u32 = 8192;
+/// Compiled-in defaults of [`BuildConfig`]
+pub const OBJECT_FILE_VERSION: i64 = 1;
+pub const EXPECTED_RUNTIME_VERSION: i64 = 3;
+pub const EBPF_STACK_FRAME_SIZE: u32 = 8192;
 
 #[derive(Error, Debug)]
 pub enum BuildError {
@@ -20,330 +38,5820 @@ pub enum BuildError {
     TargetDirError,
     #[error("Failed to execute cargo: {0}")]
     CargoBuildError(std::io::Error),
+    #[error("Failed to run cargo metadata: {0}")]
+    MetadataError(#[from] cargo_metadata::Error),
     #[error("Failed to build wasm")]
-    WasmBuildError,
-    #[error("Could not build ll file: {0}")]
-    LlBuildError(anyhow::Error),
-    #[error("filesystem error")]
-    IoError(anyhow::Error, std::io::Error),
+    WasmBuildError { exit_code: Option<i32> },
+    #[error("Could not build ll file from {}: {message}", wasm.display())]
+    LlBuildError { wasm: PathBuf, message: String },
+    #[error("filesystem error: {context} '{}': {source}", path.display())]
+    IoError {
+        context: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
     #[error("Failed to run llc command. Please ensure that your version of llc is > 17, or you have llc-17, 18 or 19 installed")]
-    LlcRunError(anyhow::Error),
+    LlcRunError {
+        /// The resolved llc command, `None` if no suitable llc was found
+        tool: Option<String>,
+        source: Option<std::io::Error>,
+    },
     #[error("Failed to build object file")]
-    ObjectBuildError,
+    ObjectBuildError {
+        exit_code: Option<i32>,
+        stderr: String,
+    },
     #[error(
-        "Failed to run llvm strip on object file. Please ensure that you have llvm-strip installed"
+        "Failed to run llvm strip on object file. Please ensure that you have llvm-strip (or GNU strip) installed"
     )]
-    LlvmStripRunError(anyhow::Error),
+    LlvmStripRunError {
+        /// The resolved llvm-strip command, `None` if no suitable llvm-strip was found
+        tool: Option<String>,
+        source: Option<std::io::Error>,
+    },
     #[error("Failed to strip object file")]
-    LlvmStripError,
+    LlvmStripError {
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    #[error("Invalid value '{1}' for '{0}'")]
+    InvalidOption(String, String),
+    #[error("'{0}' can't be combined with '{1}'")]
+    ConflictingOptions(String, String),
+    #[error("'{0}' requires '{1}'")]
+    MissingOption(String, String),
+    #[error("Unknown BPF feature '{0}', expected one of: alu32, dwarfris")]
+    UnknownBpfFeature(String),
+    #[error("No workspace member matches: {0}")]
+    NoMatchingMembers(String),
+    #[error("Neither docker nor podman is installed, --container needs one of them")]
+    ContainerRuntimeNotFound,
+    #[error("Failed to run {runtime}: {source}")]
+    ContainerRunError {
+        runtime: String,
+        source: std::io::Error,
+    },
+    #[error("The containerized build failed")]
+    ContainerBuildError { exit_code: Option<i32> },
+    #[error("Runtime version {runtime_version} supports the BPF processors up to '{supported}', the processor '{cpu}' or the alu32 feature is too new")]
+    CpuNotSupportedByRuntime {
+        cpu: String,
+        runtime_version: i64,
+        supported: &'static str,
+    },
+    #[error("Could not extract the contract ABI: {0}")]
+    AbiError(#[from] AbiError),
+    #[error("Failed to build contracts: {}", .0.join(", "))]
+    ContractsFailed(Vec<String>),
+    #[error(
+        "The build emitted {} warning(s): {}",
+        .0.values().sum::<usize>(),
+        .0.iter().map(|(name, count)| format!("{} ({})", name, count)).collect::<Vec<_>>().join(", ")
+    )]
+    CompilerWarnings(BTreeMap<String, usize>),
+    #[error(
+        "This build produces objects for runtime version {1}, but runtime version {0} is expected"
+    )]
+    RuntimeVersionMismatch(i64, i64),
+    #[error("Invalid {}: {message}", path.display())]
+    ConfigError { path: PathBuf, message: String },
+    #[error("{} imports functions the runtime doesn't provide: {}", object.display(), imports.join(", "))]
+    UnknownImports {
+        object: PathBuf,
+        imports: Vec<String>,
+    },
+    #[error("Invalid object file {}: {}", object.display(), problems.join(", "))]
+    InvalidObject {
+        object: PathBuf,
+        problems: Vec<String>,
+    },
+    #[error("The working tree has uncommitted changes, commit them or pass --allow-dirty: {}", .0.join(", "))]
+    DirtyWorkingTree(Vec<String>),
+    #[error("No profile '{name}' in the [profiles] of {CONFIG_FILE_NAME}, available: {}", available.join(", "))]
+    UnknownProfile {
+        name: String,
+        available: Vec<String>,
+    },
+    #[error("The build produced {count} wasm modules, more than --max-artifacts {max}")]
+    TooManyArtifacts { count: usize, max: usize },
+    #[error("'{0}' has no #[contract] impl, it doesn't look like an L1X contract")]
+    NotAContract(String),
+    #[error("Two builds produced different object files for: {}", .0.join(", "))]
+    NotDeterministic(Vec<String>),
+    #[error("{} differs from the reference {}: {}", object.display(), reference.display(), differences.join(", "))]
+    ObjectDiffers {
+        object: PathBuf,
+        reference: PathBuf,
+        differences: Vec<String>,
+    },
 }
 
-pub fn build(mut args: Vec<String>, target_dir: PathBuf) -> Result<(), BuildError> {
-    let mut command = process::Command::new("cargo");
+impl BuildError {
+    /// Stable identifier of the error for scripts, see `--json`
+    pub fn code(&self) -> &'static str {
+        match self {
+            BuildError::TargetDirError => "target-dir",
+            BuildError::CargoBuildError(_) => "cargo-run",
+            BuildError::MetadataError(_) => "cargo-metadata",
+            BuildError::WasmBuildError { .. } => "wasm-build",
+            BuildError::LlBuildError { .. } => "ll-build",
+            BuildError::IoError { .. } => "io",
+            BuildError::LlcRunError { .. } => "llc-run",
+            BuildError::ObjectBuildError { .. } => "object-build",
+            BuildError::LlvmStripRunError { .. } => "llvm-strip-run",
+            BuildError::LlvmStripError { .. } => "llvm-strip",
+            BuildError::InvalidOption(..) => "invalid-option",
+            BuildError::ConflictingOptions(..) => "conflicting-options",
+            BuildError::MissingOption(..) => "missing-option",
+            BuildError::UnknownBpfFeature(_) => "unknown-bpf-feature",
+            BuildError::NoMatchingMembers(_) => "no-matching-members",
+            BuildError::ContainerRuntimeNotFound => "container-runtime-not-found",
+            BuildError::ContainerRunError { .. } => "container-run",
+            BuildError::ContainerBuildError { .. } => "container-build",
+            BuildError::CpuNotSupportedByRuntime { .. } => "cpu-not-supported-by-runtime",
+            BuildError::AbiError(e) => e.code(),
+            BuildError::ContractsFailed(_) => "contracts-failed",
+            BuildError::CompilerWarnings(_) => "compiler-warnings",
+            BuildError::RuntimeVersionMismatch(..) => "runtime-version-mismatch",
+            BuildError::ConfigError { .. } => "config",
+            BuildError::TooManyArtifacts { .. } => "too-many-artifacts",
+            BuildError::UnknownProfile { .. } => "unknown-profile",
+            BuildError::UnknownImports { .. } => "unknown-imports",
+            BuildError::InvalidObject { .. } => "invalid-object",
+            BuildError::DirtyWorkingTree(_) => "dirty-working-tree",
+            BuildError::NotAContract(_) => "not-a-contract",
+            BuildError::NotDeterministic(_) => "not-deterministic",
+            BuildError::ObjectDiffers { .. } => "object-differs",
+        }
+    }
 
-    let mut no_strip = false;
-    if args.contains(&"--no-strip".to_string()) {
-        args = args.into_iter().filter(|x| x != "--no-strip").collect();
-        no_strip = true;
-    } else {
-        command.env("RUSTFLAGS", "-C link-arg=-s");
+    /// The exit code of `cargo l1x` for the error, see [`exit_code`]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BuildError::InvalidOption(..)
+            | BuildError::ConflictingOptions(..)
+            | BuildError::MissingOption(..)
+            | BuildError::UnknownBpfFeature(_)
+            | BuildError::NoMatchingMembers(_)
+            | BuildError::CpuNotSupportedByRuntime { .. }
+            | BuildError::RuntimeVersionMismatch(..)
+            | BuildError::ConfigError { .. }
+            | BuildError::UnknownProfile { .. }
+            | BuildError::DirtyWorkingTree(_)
+            | BuildError::NotAContract(_) => exit_code::USAGE,
+            BuildError::CargoBuildError(_)
+            | BuildError::LlcRunError { .. }
+            | BuildError::LlvmStripRunError { .. }
+            | BuildError::ContainerRuntimeNotFound
+            | BuildError::ContainerRunError { .. } => exit_code::TOOLCHAIN,
+            BuildError::MetadataError(_)
+            | BuildError::WasmBuildError { .. }
+            | BuildError::ContainerBuildError { .. }
+            | BuildError::TooManyArtifacts { .. }
+            | BuildError::CompilerWarnings(_) => exit_code::CARGO_BUILD,
+            BuildError::LlBuildError { .. }
+            | BuildError::ObjectBuildError { .. }
+            | BuildError::LlvmStripError { .. }
+            | BuildError::UnknownImports { .. }
+            | BuildError::InvalidObject { .. }
+            | BuildError::ContractsFailed(_) => exit_code::OBJECT,
+            BuildError::NotDeterministic(_) | BuildError::ObjectDiffers { .. } => {
+                exit_code::FAILURE
+            }
+            BuildError::AbiError(e) => e.exit_code(),
+            BuildError::TargetDirError | BuildError::IoError { .. } => exit_code::FAILURE,
+        }
     }
 
-    command
-        .arg("build")
-        .arg("--target")
-        .arg("wasm32-unknown-unknown")
-        .args(&args);
+    /// The data of the error as JSON, see `--json`
+    pub fn context(&self) -> serde_json::Value {
+        use serde_json::json;
+        match self {
+            BuildError::WasmBuildError { exit_code }
+            | BuildError::ContainerBuildError { exit_code } => json!({ "exit_code": exit_code }),
+            BuildError::LlBuildError { wasm, message } => {
+                json!({ "wasm": wasm, "message": message })
+            }
+            BuildError::IoError { context, path, .. } => {
+                json!({ "context": context, "path": path })
+            }
+            BuildError::LlcRunError { tool, .. } | BuildError::LlvmStripRunError { tool, .. } => {
+                json!({ "tool": tool })
+            }
+            BuildError::ObjectBuildError { exit_code, stderr }
+            | BuildError::LlvmStripError { exit_code, stderr } => {
+                json!({ "exit_code": exit_code, "stderr": stderr })
+            }
+            BuildError::InvalidOption(option, value) => {
+                json!({ "option": option, "value": value })
+            }
+            BuildError::ConflictingOptions(option, other) => {
+                json!({ "option": option, "conflicts_with": other })
+            }
+            BuildError::MissingOption(option, required) => {
+                json!({ "option": option, "requires": required })
+            }
+            BuildError::UnknownBpfFeature(feature) => json!({ "feature": feature }),
+            BuildError::NoMatchingMembers(patterns) => json!({ "patterns": patterns }),
+            BuildError::ContainerRunError { runtime, .. } => json!({ "runtime": runtime }),
+            BuildError::CpuNotSupportedByRuntime {
+                cpu,
+                runtime_version,
+                supported,
+            } => json!({
+                "cpu": cpu,
+                "runtime_version": runtime_version,
+                "supported": supported,
+            }),
+            BuildError::AbiError(e) => e.context(),
+            BuildError::ContractsFailed(contracts) => json!({ "contracts": contracts }),
+            BuildError::CompilerWarnings(warnings) => json!({ "warnings": warnings }),
+            BuildError::RuntimeVersionMismatch(expected, supported) => json!({
+                "expected": expected,
+                "supported": supported,
+            }),
+            BuildError::ConfigError { path, message } => {
+                json!({ "path": path, "message": message })
+            }
+            BuildError::TooManyArtifacts { count, max } => json!({ "count": count, "max": max }),
+            BuildError::UnknownProfile { name, available } => {
+                json!({ "name": name, "available": available })
+            }
+            BuildError::DirtyWorkingTree(files) => json!({ "files": files }),
+            BuildError::NotAContract(target) => json!({ "target": target }),
+            BuildError::NotDeterministic(targets) => json!({ "targets": targets }),
+            BuildError::ObjectDiffers {
+                object,
+                reference,
+                differences,
+            } => json!({ "object": object, "reference": reference, "differences": differences }),
+            BuildError::UnknownImports { object, imports } => {
+                json!({ "object": object, "imports": imports })
+            }
+            BuildError::InvalidObject { object, problems } => {
+                json!({ "object": object, "problems": problems })
+            }
+            BuildError::TargetDirError
+            | BuildError::CargoBuildError(_)
+            | BuildError::MetadataError(_)
+            | BuildError::ContainerRuntimeNotFound => json!({}),
+        }
+    }
+}
 
-    if !args.contains(&"--release".to_string()) {
-        // avoid double --release
-        command.arg("--release");
+/// Returns a `map_err` adapter creating a [`BuildError::IoError`] for `path`
+pub(crate) fn io_error<P: AsRef<Path>>(
+    context: &'static str,
+    path: P,
+) -> impl FnOnce(std::io::Error) -> BuildError {
+    let path = path.as_ref().to_path_buf();
+    move |source| BuildError::IoError {
+        context,
+        path,
+        source,
     }
+}
 
-    let mut output = command
-        .spawn()
-        .map_err(|e| BuildError::CargoBuildError(e))?;
+/// Cargo flags that are forwarded to `cargo metadata` so it never touches the network
+/// when the build itself must not
+const NETWORK_FLAGS: [&str; 3] = ["--offline", "--frozen", "--locked"];
 
-    let status = output.wait().map_err(|e| BuildError::CargoBuildError(e))?;
+/// Runs `cargo metadata` once for the manifest selected by the cargo `args`
+/// (or the current directory)
+pub fn load_metadata(args: &[String]) -> Result<Metadata, BuildError> {
+    let mut command = MetadataCommand::new();
+    if let Some(manifest_path) = manifest_path_from_args(args) {
+        command.manifest_path(manifest_path);
+    }
+    command.other_options(
+        args.iter()
+            .filter(|arg| NETWORK_FLAGS.contains(&arg.as_str()))
+            .cloned()
+            .collect::<Vec<_>>(),
+    );
+    Ok(command.exec()?)
+}
 
-    if !status.success() {
-        println!("Failed to build wasm");
-        return Err(BuildError::WasmBuildError);
+fn manifest_path_from_args(args: &[String]) -> Option<PathBuf> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--manifest-path" {
+            return iter.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--manifest-path=") {
+            return Some(PathBuf::from(path));
+        }
     }
+    None
+}
 
-    let bin_dir = target_dir.join("l1x/release");
+/// Subdirectory of the target directory where the generated files are written by default
+pub const DEFAULT_OUTPUT_SUBDIR: &str = "l1x/release";
 
-    fs::create_dir_all(bin_dir.clone())
-        .map_err(|e| BuildError::IoError(anyhow!("Could not create target directory"), e))?;
+pub fn build(args: Vec<String>, target_dir: PathBuf) -> Result<(), BuildError> {
+    let metadata = load_metadata(&args)?;
+    build_impl(args, target_dir, &metadata, None, None)
+}
 
-    let output = command
-        .arg("--message-format")
-        .arg("json")
-        .output()
-        .map_err(|e| BuildError::CargoBuildError(e))?;
+/// Like [`build`], reporting the steps of the pipeline to `sink` as they happen, for
+/// IDEs and build servers showing live progress. The terminal output is rendered from
+/// the same events
+pub fn build_with_events(
+    args: Vec<String>,
+    target_dir: PathBuf,
+    sink: EventSink,
+) -> Result<(), BuildError> {
+    match load_metadata(&args) {
+        Ok(metadata) => build_impl(args, target_dir, &metadata, None, Some(sink)),
+        Err(e) => {
+            sink.emit(BuildEvent::Failed {
+                contract: None,
+                error: e.to_string(),
+            });
+            Err(e)
+        }
+    }
+}
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    let lines: Vec<&str> = output_str.split("\n").collect();
+/// Like [`build`], with the generated files in `<target_dir>/<output_subdir>` instead of
+/// [`DEFAULT_OUTPUT_SUBDIR`], e.g. to keep the outputs of parallel builds apart
+pub fn build_into<P: AsRef<Path>>(
+    args: Vec<String>,
+    target_dir: PathBuf,
+    output_subdir: P,
+) -> Result<(), BuildError> {
+    let metadata = load_metadata(&args)?;
+    build_impl(
+        args,
+        target_dir,
+        &metadata,
+        Some(output_subdir.as_ref()),
+        None,
+    )
+}
 
-    for line in lines {
-        if let Ok(cargo_metadata::Message::CompilerArtifact(artifact)) =
-            serde_json::from_str::<cargo_metadata::Message>(line)
-        {
-            let wasm_file_path = artifact.filenames[0].clone();
-            if wasm_file_path.extension() == Some("wasm") {
-                let ll_file_path = wasm_file_path.with_extension("ll");
-                let ll_file_path = bin_dir.join(
-                    &ll_file_path
-                        .file_name()
-                        .expect("Generated .ll file should have a file name"),
-                );
-                translate_module_to_file_by_path(
-                    &wasm_file_path.clone().into(),
-                    &ll_file_path.clone().into(),
-                )
-                .map_err(|e| BuildError::LlBuildError(e))?;
+/// Builds the contracts reusing already fetched `cargo metadata` output
+pub fn build_with_metadata(args: Vec<String>, metadata: &Metadata) -> Result<(), BuildError> {
+    build_impl(
+        args,
+        metadata.target_directory.clone().into(),
+        metadata,
+        None,
+        None,
+    )
+}
+
+/// Splits `args` at the first `--`. The arguments after it are passed to `cargo build`
+/// as they are, without looking for the options of the tool
+pub fn split_cargo_args(mut args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    match args.iter().position(|arg| arg == "--") {
+        Some(separator) => {
+            let cargo_args = args.split_off(separator + 1);
+            args.pop();
+            (args, cargo_args)
+        }
+        None => (args, Vec::new()),
+    }
+}
 
-                build_ebpf(&ll_file_path, no_strip)?;
+fn build_impl(
+    args: Vec<String>,
+    target_dir: PathBuf,
+    metadata: &Metadata,
+    output_subdir: Option<&Path>,
+    events: Option<EventSink>,
+) -> Result<(), BuildError> {
+    let result = build_from_args(args, target_dir, metadata, output_subdir, events.clone());
+    if let (Err(e), Some(events)) = (&result, events) {
+        events.emit(BuildEvent::Failed {
+            contract: None,
+            error: e.to_string(),
+        });
+    }
+    result
+}
 
-                let object_file_path = wasm_file_path.with_extension("o");
-                println!(
-                    "✅ Contract object file '{:?}' has been built",
-                    object_file_path
-                        .file_name()
-                        .expect("Generated .o file should have a file name")
-                );
-            }
+fn build_from_args(
+    args: Vec<String>,
+    target_dir: PathBuf,
+    metadata: &Metadata,
+    output_subdir: Option<&Path>,
+    events: Option<EventSink>,
+) -> Result<(), BuildError> {
+    let started = std::time::Instant::now();
+    let host_args = args.clone();
+    let (mut args, cargo_args) = split_cargo_args(args);
+    let mut options = BuildOptions::from_args(&mut args)?;
+    args.extend(cargo_args);
+    options.output_subdir = output_subdir.map(Path::to_path_buf);
+    // the terminal output of the contracts is rendered from the events, the modes
+    // comparing builds print their own report instead
+    let format = options.message_format.unwrap_or_default();
+    let reports = !(options.summary_only || options.profile_size || options.assert_deterministic);
+    let sink = EventSink::new(move |event| {
+        render_event(&event, format, reports);
+        if let Some(events) = &events {
+            events.emit(event);
+        }
+    });
+    options.events = Some(sink);
+    if let Some(log_file) = &options.log_file {
+        options.log = Some(BuildLog::create(log_file, &host_args)?);
+    }
+    let log = options.log.clone();
+    let result = build_with_options(args, host_args, options, target_dir, metadata, started);
+    if let Some(log) = log {
+        match &result {
+            Ok(()) => log.record("build finished"),
+            Err(e) => log.record(&format!("build failed: {}", e)),
         }
     }
+    result
+}
 
-    Ok(())
+/// Prints an event of the build to the terminal in the `format` of the user, the built
+/// contracts and the summary only with `reports`
+fn render_event(event: &BuildEvent, format: MessageFormat, reports: bool) {
+    match event {
+        BuildEvent::ContractBuilt { report, .. } if reports => report.print(format),
+        BuildEvent::Failed {
+            contract: Some(name),
+            error,
+        } => eprintln!("❌ Contract '{}' failed: {}", name, error),
+        BuildEvent::Finished { built, failed } if reports => print_summary(built, failed, format),
+        _ => {}
+    }
 }
 
-pub fn build_ebpf<P: AsRef<Path> + Clone>(path: P, no_strip: bool) -> Result<(), BuildError> {
-    let source_file = path.clone();
-    let versioned_file = path.as_ref().with_extension("versioned.ll");
-    let target_file = path.as_ref().with_extension("o");
+fn build_with_options(
+    mut args: Vec<String>,
+    mut host_args: Vec<String>,
+    mut options: BuildOptions,
+    target_dir: PathBuf,
+    metadata: &Metadata,
+    started: std::time::Instant,
+) -> Result<(), BuildError> {
+    options.cargo_profile = normalize_profile_args(&mut args)?;
+    let config = BuildConfig::load(metadata.workspace_root.as_std_path())?;
+    options.validate(&config)?;
+    let variants = profile_variants(&options, &config, metadata.workspace_root.as_std_path())?;
+    if options.require_clean && !options.allow_dirty && !options.skips_build() {
+        if let Some(files) = dirty_files(metadata.workspace_root.as_std_path()) {
+            if !files.is_empty() {
+                return Err(BuildError::DirtyWorkingTree(files));
+            }
+        }
+    }
+    if !KNOWN_WASM_TARGETS.contains(&options.wasm_target()) {
+        options.warn(&format!(
+            "'{}' is an unusual wasm target, the contracts are usually built for '{}'",
+            options.wasm_target(),
+            DEFAULT_WASM_TARGET
+        ));
+    }
 
-    // Copy the source file to the versioned file
-    std::fs::copy(source_file, &versioned_file)
-        .map_err(|e| BuildError::IoError(anyhow!("Failed to copy source file"), e))?;
+    if options.container {
+        take_flag(&mut host_args, "--container")?;
+        take_value(&mut host_args, "--container-image")?;
+        let image = options
+            .container_image
+            .clone()
+            .unwrap_or_else(default_container_image);
+        options.emit(BuildEvent::ContainerStarted {
+            image: image.clone(),
+        });
+        build_in_container(&host_args, metadata, &image)?;
+        // the contracts are reported by the output of the build in the container
+        options.emit(BuildEvent::Finished {
+            built: Vec::new(),
+            failed: Vec::new(),
+        });
+        return Ok(());
+    }
 
-    // Add the version information to the versioned file
-    add_version_info(&versioned_file)?;
+    if let Some(input) = &options.print_llc_command {
+        let command = llc_command(input, &input.with_extension("o"), &options, &config)?;
+        println!("{}", format_command(&command));
+        return Ok(());
+    }
 
-    // Fix the versioned file for mac os compatibility
-    fix_version_file(&versioned_file)?;
+    if options.dump_config {
+        let message_format = options.message_format.unwrap_or_default();
+        for (options, config) in &variants {
+            print_config(
+                &effective_config(options, config, &target_dir),
+                message_format,
+            );
+        }
+        return Ok(());
+    }
 
-    // Compile the versioned file to the target file
-    compile_to_object(&versioned_file, &target_file)?;
+    if options.list_artifacts {
+        let artifacts = list_artifacts(&options.bin_dir(&target_dir))?;
+        if artifacts.is_empty() && options.message_format != Some(MessageFormat::Json) {
+            println!("No contract artifacts, run `cargo l1x build` first");
+        }
+        for (path, size) in artifacts {
+            match options.message_format.unwrap_or_default() {
+                MessageFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({ "reason": "l1x-artifact", "path": path, "size": size })
+                ),
+                _ => println!("{:>10}  {}", size, path.display()),
+            }
+        }
+        return Ok(());
+    }
 
-    if !no_strip {
-        // Strip the target file
-        strip_object_file(&target_file)?;
+    if options.check_stale {
+        let stale = find_stale_objects(metadata, &options.bin_dir(&target_dir))?;
+        if stale.is_empty() {
+            println!("All contract object files are up to date");
+        }
+        warn_stale_objects(&stale);
+        return Ok(());
     }
 
-    Ok(())
-}
+    let mut packages = if options.members.is_empty() {
+        None
+    } else {
+        Some(select_members(metadata, &options.members)?)
+    };
+    if let Some(since) = &options.since {
+        if let Some(changed) = changed_since(metadata, since) {
+            let changed: Vec<String> = packages
+                .unwrap_or_else(|| {
+                    metadata
+                        .workspace_packages()
+                        .iter()
+                        .map(|package| package.name.clone())
+                        .collect()
+                })
+                .into_iter()
+                .filter(|package| changed.contains(package))
+                .collect();
+            if changed.is_empty() {
+                options.status(&format!("No workspace member changed since '{}'", since));
+                return Ok(());
+            }
+            packages = Some(changed);
+        }
+    }
+    for package in packages.unwrap_or_default() {
+        args.push("-p".to_string());
+        args.push(package);
+    }
 
-fn add_version_info<P: AsRef<Path>>(versioned_file: P) -> Result<(), BuildError> {
-    let mut file = OpenOptions::new()
-        .append(true)
-        .open(versioned_file)
-        .map_err(|e| BuildError::IoError(anyhow!("Failed to open versioned file"), e))?;
+    if options.emit_abi_only {
+        for (name, path) in write_abis(&args, metadata, &options, &target_dir)? {
+            match options.message_format.unwrap_or_default() {
+                MessageFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({ "reason": "l1x-abi", "target": name, "path": path })
+                ),
+                _ => println!("Wrote the ABI of '{}' to '{}'", name, path.display()),
+            }
+        }
+        return Ok(());
+    }
 
-    writeln!(
-        file,
-        "@_OBJECT_VERSION = global i64 {}, section \"_version\", align 1",
-        OBJECT_FILE_VERSION
-    )
-    .map_err(|e| BuildError::IoError(anyhow!("Failed to write version info"), e))?;
-    writeln!(
-        file,
-        "@_EXPECTED_RUNTIME_VERSION = global i64 {}, section \"_version\", align 1",
-        EXPECTED_RUNTIME_VERSION
-    )
-    .map_err(|e| BuildError::IoError(anyhow!("Failed to write version info"), e))?;
-    Ok(())
-}
+    if let Some(script) = &options.emit_script {
+        write_build_script(script, &args, &options, &variants, &target_dir, metadata)?;
+        println!("Wrote the build script to '{}'", script.display());
+        return Ok(());
+    }
 
-pub fn fix_version_file<P: AsRef<Path>>(versioned_file: P) -> Result<(), BuildError> {
-    let mut content = fs::read_to_string(versioned_file.as_ref())
-        .map_err(|e| BuildError::IoError(anyhow::anyhow!("Failed to read version file"), e))?;
+    if options.clean_first || options.clean_cargo {
+        clean_outputs(&args, metadata, &variants, &target_dir)?;
+        if options.clean_cargo {
+            cargo_clean(&args, &options)?;
+        }
+    }
 
-    // MAC OS: wasm-llvmir tool adds a comma before a section name for unknown reason.
-    // This is a workaround until it's fixed in wasm-llvmir
-    content = content.replace("section \",_memory\"", "section \"_memory\"");
-    content = content.replace("section \",_init_memory\"", "section \"_init_memory\"");
+    check_contracts(&args, metadata, &options)?;
 
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(versioned_file.as_ref())
-        .map_err(|e| BuildError::IoError(anyhow::anyhow!("Failed to open version file"), e))?;
+    if options.assert_deterministic {
+        return assert_deterministic(
+            &args,
+            metadata,
+            &target_dir,
+            &options.bin_dir(&target_dir),
+            &options,
+            &config,
+        );
+    }
 
-    file.write_all(content.as_bytes())
-        .map_err(|e| BuildError::IoError(anyhow::anyhow!("Failed to write to version file"), e))?;
+    if options.profile_size {
+        return profile_size(
+            &args,
+            metadata,
+            &options.bin_dir(&target_dir),
+            &options,
+            &config,
+        );
+    }
 
-    Ok(())
-}
+    let CargoOutput {
+        artifacts,
+        warnings,
+        failed: wasm_failed,
+    } = cargo_build(&args, &options, metadata)?;
 
-///  $ llc-17 -march=bpf -mattr=help
-///  Available CPUs for this target:
-///
-///    generic - Select the generic processor.
-///    probe   - Select the probe processor.
-///    v1      - Select the v1 processor.
-///    v2      - Select the v2 processor.
-///    v3      - Select the v3 processor.
-///
-///  Available features for this target:
-///
-///    alu32    - Enable ALU32 instructions.
-///    dummy    - unused feature.
-///    dwarfris - Disable MCAsmInfo DwarfUsesRelocationsAcrossSections.
-///
-/// Use +feature to enable a feature, or -feature to disable it.
-/// For example, llc -mcpu=mycpu -mattr=+feature1,-feature2
-///
-/// https://chromium.googlesource.com/external/github.com/llvm/llvm-project/+/refs/heads/upstream/release/17.x/llvm/lib/Target/BPF/BPFSubtarget.cpp
-///   if (CPU == "v3") {
-///    HasJmpExt = true;
-///    HasJmp32 = true;
-///    HasAlu32 = true;
-///    return;
-///  }
-fn compile_to_object<P: AsRef<Path>>(input_file: P, output_file: P) -> Result<(), BuildError> {
-    let command = get_llc_command()?.to_string();
-
-    let output = Command::new(command)
-        .args(&[
-            "-march=bpf",
-            "-mcpu=v3",
-            "-filetype=obj",
-            "--nozero-initialized-in-bss",
-            "--bpf-stack-size",
-            EBPF_STACK_FRAME_SIZE.to_string().as_str(),
-            input_file
-                .as_ref()
-                .to_str()
-                .expect("Path should be valid unicode"),
-            "-o",
-            output_file
-                .as_ref()
-                .to_str()
-                .expect("Path should be valid unicode"),
-        ])
-        .output()
-        .map_err(|e| BuildError::LlcRunError(e.into()))?;
+    let bin_dir = options.bin_dir(&target_dir);
 
-    if !output.status.success() {
-        eprintln!(
-            "Error compiling to object file: {}",
-            String::from_utf8_lossy(&output.stderr)
+    fs::create_dir_all(&bin_dir)
+        .map_err(io_error("Could not create target directory", &bin_dir))?;
+
+    if let Some(max) = options.max_artifacts {
+        let count = artifacts
+            .iter()
+            .filter(|artifact| wasm_file(artifact).is_some())
+            .count();
+        if count > max {
+            return Err(BuildError::TooManyArtifacts { count, max });
+        }
+    }
+
+    let mut built = Vec::new();
+    let mut failed = Vec::new();
+    let mut objects = Vec::new();
+    let mut commands = Vec::new();
+    let mut summary = Vec::new();
+    for artifact in artifacts {
+        // the wasm is the same for all profiles, only the backend settings differ
+        for (options, config) in &variants {
+            let name = match &options.profile_name {
+                Some(profile) => format!("{} ({})", artifact.target.name, profile),
+                None => artifact.target.name.clone(),
+            };
+            match build_contract(
+                &artifact,
+                &bin_dir,
+                options,
+                config,
+                metadata.workspace_root.as_std_path(),
+            ) {
+                Ok(Some(report)) => {
+                    if let Some(log) = &options.log {
+                        log.record(&format!("contract '{}' built", name));
+                    }
+                    if options.summary_only {
+                        summary.push(SummaryEntry::new(&name, &report, config)?);
+                    }
+                    options.emit(BuildEvent::ContractBuilt {
+                        name: name.clone(),
+                        report: Box::new(report.clone()),
+                    });
+                    built.push(name);
+                    objects.extend(report.object);
+                    commands.extend(report.commands);
+                }
+                Ok(None) => {}
+                Err(e) if options.keep_going => {
+                    if let Some(log) = &options.log {
+                        log.record(&format!("contract '{}' failed: {}", name, e));
+                    }
+                    options.emit(BuildEvent::Failed {
+                        contract: Some(name.clone()),
+                        error: e.to_string(),
+                    });
+                    failed.push(name);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    if options.emit_commands {
+        let commands_file = bin_dir.join(COMMANDS_FILE_NAME);
+        let content = serde_json::to_string_pretty(&commands).expect("Commands should serialize");
+        fs::write(&commands_file, content + "\n")
+            .map_err(io_error("Could not write", &commands_file))?;
+    }
+    if options.summary_only {
+        print_report(
+            &summary,
+            &failed,
+            started.elapsed(),
+            options.message_format.unwrap_or_default(),
         );
-        return Err(BuildError::ObjectBuildError);
     }
+    options.emit(BuildEvent::Finished {
+        built,
+        failed: failed.clone(),
+    });
+    if !failed.is_empty() {
+        return Err(BuildError::ContractsFailed(failed));
+    }
+    if let Some(e) = wasm_failed {
+        return Err(e);
+    }
+    if let Some(reference) = &options.compare_to {
+        let [object] = &objects[..] else {
+            return Err(BuildError::ConflictingOptions(
+                "--compare-to".to_string(),
+                format!(
+                    "{} ({} object files built, select a contract with -p)",
+                    reference.display(),
+                    objects.len()
+                ),
+            ));
+        };
+        compare_to_reference(
+            object,
+            reference,
+            options.message_format.unwrap_or_default(),
+        )?;
+    }
+    if options.fail_on_warning && !warnings.is_empty() {
+        return Err(BuildError::CompilerWarnings(warnings));
+    }
+
     Ok(())
 }
 
-fn strip_object_file<P: AsRef<Path>>(target_file: P) -> Result<(), BuildError> {
-    let command = get_llvm_command()?.to_string();
+/// The options and settings of each `--config` profile, or only the given ones without
+/// `--config`. The profiles are checked before anything is built
+fn profile_variants(
+    options: &BuildOptions,
+    config: &BuildConfig,
+    workspace_root: &Path,
+) -> Result<Vec<(BuildOptions, BuildConfig)>, BuildError> {
+    if options.configs.is_empty() {
+        return Ok(vec![(options.clone(), config.clone())]);
+    }
+    let profiles = load_profiles(workspace_root)?;
+    options
+        .configs
+        .iter()
+        .map(|name| {
+            let profile = profiles
+                .get(name)
+                .ok_or_else(|| BuildError::UnknownProfile {
+                    name: name.clone(),
+                    available: profiles.keys().cloned().collect(),
+                })?;
+            let mut options = options.clone();
+            let mut config = config.clone();
+            profile.apply(&mut config, &mut options);
+            options.profile_name = Some(name.clone());
+            options.validate(&config)?;
+            Ok((options, config))
+        })
+        .collect()
+}
 
-    let output = Command::new(command)
-        .arg("-x")
-        .arg(
-            target_file
-                .as_ref()
-                .to_str()
-                .expect("Path should be valid unicode"),
-        )
-        .output()
-        .map_err(|e| BuildError::LlvmStripRunError(e.into()))?;
+/// Removes the output directories of the `variants` for `--clean-first`, only the ones
+/// in `target_dir`: an `--out-dir` elsewhere may have other files. When `args` select
+/// packages with `-p`, only the output files of their contracts are removed, the other
+/// contracts aren't built again
+fn clean_outputs(
+    args: &[String],
+    metadata: &Metadata,
+    variants: &[(BuildOptions, BuildConfig)],
+    target_dir: &Path,
+) -> Result<(), BuildError> {
+    let mut cargo_args = args.to_vec();
+    let mut packages = take_values(&mut cargo_args, "-p")?;
+    packages.extend(take_values(&mut cargo_args, "--package")?);
+    let targets = contract_targets(args, metadata, true)?;
+    let mut cleaned: Vec<PathBuf> = Vec::new();
+    for (options, _) in variants {
+        let bin_dir = options.bin_dir(target_dir);
+        if !bin_dir.starts_with(target_dir) || bin_dir == target_dir {
+            if !cleaned.contains(&bin_dir) {
+                options.warn(&format!(
+                    "'{}' is not inside the target directory, --clean-first doesn't remove it",
+                    bin_dir.display()
+                ));
+            }
+        } else if packages.is_empty() {
+            if bin_dir.exists() {
+                fs::remove_dir_all(&bin_dir).map_err(io_error("Could not remove", &bin_dir))?;
+                options.status(&format!("Removed '{}'", bin_dir.display()));
+            }
+        } else {
+            let mut removed = 0;
+            for (target, is_example) in &targets {
+                let dir = if *is_example {
+                    bin_dir.join("examples")
+                } else {
+                    bin_dir.clone()
+                };
+                let stem = target.name.replace('-', "_");
+                let prefix = match &options.profile_name {
+                    Some(profile) => format!("{}-{}.", stem, profile),
+                    None => format!("{}.", stem),
+                };
+                let Ok(entries) = fs::read_dir(&dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if entry.file_name().to_string_lossy().starts_with(&prefix) && path.is_file() {
+                        fs::remove_file(&path).map_err(io_error("Could not remove", &path))?;
+                        removed += 1;
+                    }
+                }
+            }
+            if removed > 0 {
+                options.status(&format!(
+                    "Removed {} output file(s) of {} from '{}'",
+                    removed,
+                    packages.join(", "),
+                    bin_dir.display()
+                ));
+            }
+        }
+        cleaned.push(bin_dir);
+    }
+    Ok(())
+}
 
-    if !output.status.success() {
-        eprintln!(
-            "Error stripping object file: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-        return Err(BuildError::LlvmStripError);
+/// Runs `cargo clean` for the wasm target and profile of the build, on the packages
+/// selected by `args`
+fn cargo_clean(args: &[String], options: &BuildOptions) -> Result<(), BuildError> {
+    let mut command = cargo_clean_command(args, options)?;
+    if let Some(log) = &options.log {
+        log.record(&format!("$ {}", format_command(&command)));
+    }
+    let status = command.status().map_err(BuildError::CargoBuildError)?;
+    if let Some(log) = &options.log {
+        log.record(&format!("cargo clean {}", status));
+    }
+    if !status.success() {
+        return Err(BuildError::WasmBuildError {
+            exit_code: status.code(),
+        });
     }
     Ok(())
 }
 
-fn get_llc_command() -> Result<String, BuildError> {
-    if let Ok(path_str) = std::env::var("LLVM_BIN_PATH") {
-        let path = format!("{}/llc", path_str);
-        if std::path::Path::new(&path).exists() {
-            return Ok(path);
+/// The `cargo clean` command of `--clean-cargo`, with the package, manifest, target
+/// directory and network flags of `args`
+fn cargo_clean_command(args: &[String], options: &BuildOptions) -> Result<Command, BuildError> {
+    let mut cargo_args = args.to_vec();
+    let mut command = Command::new("cargo");
+    command
+        .arg("clean")
+        .arg("--target")
+        .arg(options.wasm_target());
+    match &options.cargo_profile {
+        Some(profile) => command.arg("--profile").arg(profile),
+        None => command.arg("--release"),
+    };
+    for option in ["-p", "--package", "--manifest-path", "--target-dir"] {
+        for value in take_values(&mut cargo_args, option)? {
+            command.arg(option).arg(value);
         }
     }
-    if which("llc-17".to_string()).is_some() {
-        return Ok("llc-17".into());
-    } else if which("llc-18".to_string()).is_some() {
-        return Ok("llc-18".into());
-    } else if which("llc-19".to_string()).is_some() {
-        return Ok("llc-19".into());
-    } else if which("llc".to_string()).is_some() {
-        let output = Command::new("llc").arg("--version").output();
+    command.args(
+        cargo_args
+            .iter()
+            .filter(|arg| NETWORK_FLAGS.contains(&arg.as_str())),
+    );
+    Ok(command)
+}
 
-        if let Ok(output) = output {
-            let version_str = String::from_utf8_lossy(&output.stdout);
-            if version_str.contains("version 17.")
-                || version_str.contains("version 18.")
-                || version_str.contains("version 19.")
-            {
-                return Ok("llc".into());
-            } else {
-                return Err(BuildError::LlcRunError(anyhow!("")));
-            }
-        } else {
-            return Err(BuildError::LlcRunError(anyhow!("")));
+/// What the JSON messages of a cargo build report
+#[derive(Debug, Default)]
+struct CargoOutput {
+    /// The artifacts of the workspace members, only contracts of the workspace are processed
+    artifacts: Vec<cargo_metadata::Artifact>,
+    /// The number of compiler warnings of each crate that warned
+    warnings: BTreeMap<String, usize>,
+    /// The failure of the build when cargo went on with the other crates, `--keep-going`
+    failed: Option<BuildError>,
+}
+
+impl CargoOutput {
+    /// The artifacts, when cargo built everything
+    fn into_artifacts(self) -> Result<Vec<cargo_metadata::Artifact>, BuildError> {
+        match self.failed {
+            Some(e) => Err(e),
+            None => Ok(self.artifacts),
         }
-    } else {
-        return Err(BuildError::LlcRunError(anyhow!("")));
     }
 }
 
-fn get_llvm_command() -> Result<String, BuildError> {
-    if std::env::var("LLVM_BIN_PATH").is_ok() {
-        let path = format!(
-            "{}/llvm-strip",
-            std::env::var("LLVM_BIN_PATH").expect("checked")
-        );
-        if std::path::Path::new(&path).exists() {
-            return Ok(path);
+/// Runs the wasm build once, with JSON messages. The diagnostics are shown in the
+/// `--message-format` of the user while the build runs
+fn cargo_build(
+    args: &[String],
+    options: &BuildOptions,
+    metadata: &Metadata,
+) -> Result<CargoOutput, BuildError> {
+    let format = options.message_format.unwrap_or_default();
+    let color = std::io::stderr().is_terminal()
+        && std::env::var("CARGO_TERM_COLOR").map_or(true, |color| color != "never");
+    let mut command = cargo_build_command(args, options);
+    command
+        .arg("--message-format")
+        .arg(format.cargo_json_format(color))
+        .stdout(process::Stdio::piped());
+    if options.summary_only {
+        // no progress lines, the errors are still rendered from the messages
+        command.arg("--quiet");
+    }
+    if let Some(log) = &options.log {
+        log.record(&format!("$ {}", format_command(&command)));
+        // cargo sees a pipe instead of the terminal, keep its colors and progress bar
+        if color && !args.iter().any(|arg| arg.starts_with("--color")) {
+            command.env("CARGO_TERM_COLOR", "always");
         }
+        let width = std::io::stderr()
+            .is_terminal()
+            .then(|| dialoguer::console::Term::stderr().size_checked())
+            .flatten();
+        if let Some((_, width)) = width {
+            if std::env::var_os("CARGO_TERM_PROGRESS_WHEN").is_none() {
+                command
+                    .env("CARGO_TERM_PROGRESS_WHEN", "always")
+                    .env("CARGO_TERM_PROGRESS_WIDTH", width.to_string());
+            }
+        }
+        command.stderr(process::Stdio::piped());
     }
-    if which("llvm-strip-17".to_string()).is_some() {
-        return Ok("llvm-strip-17".into());
-    } else if which("llvm-strip-18".to_string()).is_some() {
-        return Ok("llvm-strip-18".into());
-    } else if which("llvm-strip-19".to_string()).is_some() {
-        return Ok("llvm-strip-19".into());
-    } else if which("llvm-strip".to_string()).is_some() {
-        let output = Command::new("llvm-strip").arg("--version").output();
+    options.emit(BuildEvent::CargoStarted);
+    let mut child = command.spawn().map_err(BuildError::CargoBuildError)?;
 
-        if let Ok(output) = output {
-            let version_str = String::from_utf8_lossy(&output.stdout);
-            if version_str.contains("version 17.")
-                || version_str.contains("version 18.")
-                || version_str.contains("version 19.")
+    // the progress lines and build script errors of cargo go to stderr, passed through
+    // as they come and logged by line
+    let stderr = child.stderr.take().map(|stderr| {
+        let log = options.log.clone();
+        std::thread::spawn(move || {
+            let mut reader = std::io::BufReader::new(TeeStderr(stderr));
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        if let Some(log) = &log {
+                            log.output(&line);
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(_) => break,
+                }
+            }
+        })
+    });
+    let stdout = child.stdout.take().expect("Cargo stdout should be piped");
+    let output = read_messages(
+        std::io::BufReader::new(stdout),
+        format,
+        options.summary_only,
+        &metadata.workspace_members,
+        options.log.as_ref(),
+        options.events.as_ref(),
+    );
+    let status = child.wait().map_err(BuildError::CargoBuildError)?;
+    if let Some(stderr) = stderr {
+        let _ = stderr.join();
+    }
+    if let Some(log) = &options.log {
+        log.record(&format!("cargo {}", status));
+    }
+    if !status.success() {
+        options.status("Failed to build wasm");
+        let error = BuildError::WasmBuildError {
+            exit_code: status.code(),
+        };
+        if !options.keep_going {
+            return Err(error);
+        }
+        // cargo built the crates that don't depend on the failed ones
+        return Ok(CargoOutput {
+            failed: Some(error),
+            ..output
+        });
+    }
+    Ok(output)
+}
+
+/// Copies what is read to the stderr of the process. A failed write doesn't stop the
+/// reading, cargo must never block on a full pipe
+struct TeeStderr<R>(R);
+
+impl<R: Read> Read for TeeStderr<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.0.read(buf)?;
+        let mut stderr = std::io::stderr().lock();
+        let _ = stderr.write_all(&buf[..read]).and_then(|()| stderr.flush());
+        Ok(read)
+    }
+}
+
+/// Prints the cargo messages in the user's `format`, collects the artifacts of the
+/// `members` and counts the warnings. Lines that aren't messages, e.g. from build scripts or truncated, are printed
+/// as they are. Invalid UTF-8 is replaced, the whole output is read so cargo never blocks
+/// on a full pipe. With `errors_only` the warnings aren't rendered. The `log` gets the
+/// rendered diagnostics and the other lines, the `events` each wasm module of the `members`
+fn read_messages(
+    mut reader: impl BufRead,
+    format: MessageFormat,
+    errors_only: bool,
+    members: &[cargo_metadata::PackageId],
+    log: Option<&BuildLog>,
+    events: Option<&EventSink>,
+) -> CargoOutput {
+    let mut output = CargoOutput::default();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        let message = serde_json::from_str::<cargo_metadata::Message>(line);
+        if format == MessageFormat::Json || message.is_err() {
+            println!("{}", line);
+        }
+        if let (Some(log), Err(_)) = (log, &message) {
+            log.record(line);
+        }
+        match message {
+            Ok(cargo_metadata::Message::CompilerArtifact(artifact))
+                if members.contains(&artifact.package_id) =>
             {
-                return Ok("llvm-strip".into());
-            } else {
-                return Err(BuildError::LlvmStripRunError(anyhow!("")));
+                if let (Some(events), Some(wasm)) = (events, wasm_file(&artifact)) {
+                    events.emit(BuildEvent::ArtifactCompiled {
+                        crate_name: artifact.target.name.clone(),
+                        wasm: wasm.into(),
+                    });
+                }
+                output.artifacts.push(artifact)
             }
-        } else {
-            return Err(BuildError::LlvmStripRunError(anyhow!("")));
+            Ok(cargo_metadata::Message::CompilerMessage(message)) => {
+                let is_error = message.message.level == DiagnosticLevel::Error;
+                if is_warning(&message.message) {
+                    *output.warnings.entry(message.target.name).or_default() += 1;
+                }
+                if let (Some(log), Some(rendered)) = (log, &message.message.rendered) {
+                    log.record(rendered.trim_end());
+                }
+                if format != MessageFormat::Json && (is_error || !errors_only) {
+                    if let Some(rendered) = message.message.rendered {
+                        eprint!("{}", rendered);
+                    }
+                }
+            }
+            _ => {}
         }
-    } else {
-        return Err(BuildError::LlvmStripRunError(anyhow!("")));
     }
+    output
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// `true` for a compiler warning, but not for the "N warnings emitted" summary of rustc
+fn is_warning(diagnostic: &cargo_metadata::diagnostic::Diagnostic) -> bool {
+    diagnostic.level == DiagnosticLevel::Warning
+        && !(diagnostic.spans.is_empty()
+            && diagnostic.code.is_none()
+            && (diagnostic.message.ends_with(" warning emitted")
+                || diagnostic.message.ends_with(" warnings emitted")))
+}
+
+/// Builds the contracts with each of the [`SIZE_PROFILES`] in `<bin_dir>/size-profiles/<name>`,
+/// prints the object sizes and copies the `--size-profile` build, or the smallest one,
+/// to `bin_dir`
+fn profile_size(
+    args: &[String],
+    metadata: &Metadata,
+    bin_dir: &Path,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<(), BuildError> {
+    let mut sizes: Vec<(SizeProfile, Vec<(String, u64)>)> = Vec::new();
+    for profile in SIZE_PROFILES {
+        options.status(&format!("Building with {}", profile));
+        let profile_options = BuildOptions {
+            size_profile: Some(profile),
+            ..options.clone()
+        };
+        let artifacts = cargo_build(args, &profile_options, metadata)?.into_artifacts()?;
+
+        let profile_dir = profile.dir(bin_dir);
+        let mut objects = Vec::new();
+        for artifact in artifacts {
+            let report = build_contract(
+                &artifact,
+                &profile_dir,
+                &profile_options,
+                config,
+                metadata.workspace_root.as_std_path(),
+            )?;
+            let Some(report) = report else {
+                continue;
+            };
+            if let Some(object) = &report.object {
+                let size = fs::metadata(object)
+                    .map_err(io_error("Could not read", object))?
+                    .len();
+                objects.push((artifact.target.name.clone(), size));
+            }
+            options.emit(BuildEvent::ContractBuilt {
+                name: format!("{} ({})", artifact.target.name, profile.name),
+                report: Box::new(report),
+            });
+        }
+        sizes.push((profile, objects));
+    }
+
+    let total = |objects: &[(String, u64)]| objects.iter().map(|(_, size)| size).sum::<u64>();
+    let kept = match options.size_profile {
+        Some(profile) => profile,
+        None => {
+            sizes
+                .iter()
+                .min_by_key(|(_, objects)| total(objects))
+                .expect("There should be size profiles")
+                .0
+        }
+    };
+    copy_dir(&kept.dir(bin_dir), bin_dir)?;
+
+    match options.message_format.unwrap_or_default() {
+        MessageFormat::Json => {
+            for (profile, objects) in &sizes {
+                let object_sizes: serde_json::Map<_, _> = objects
+                    .iter()
+                    .map(|(name, size)| (name.clone(), (*size).into()))
+                    .collect();
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "reason": "l1x-size-profile",
+                        "profile": profile.name,
+                        "objects": object_sizes,
+                        "total": total(objects),
+                        "kept": *profile == kept,
+                    })
+                );
+            }
+        }
+        _ => {
+            print_size_table(&sizes, kept);
+            options.status(&format!(
+                "✅ Kept the {} build in '{}'",
+                kept,
+                bin_dir.display()
+            ));
+        }
+    }
+    let (_, objects) = sizes
+        .iter()
+        .find(|(profile, _)| *profile == kept)
+        .expect("The kept profile should be built");
+    options.emit(BuildEvent::Finished {
+        built: objects.iter().map(|(name, _)| name.clone()).collect(),
+        failed: Vec::new(),
+    });
+    Ok(())
+}
+
+/// One row per contract, one column per profile, the kept profile is marked with `*`
+fn print_size_table(sizes: &[(SizeProfile, Vec<(String, u64)>)], kept: SizeProfile) {
+    let mut contracts: Vec<&str> = sizes
+        .iter()
+        .flat_map(|(_, objects)| objects.iter().map(|(name, _)| name.as_str()))
+        .collect();
+    contracts.sort();
+    contracts.dedup();
+    let width = contracts
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(8);
+
+    print!("{:<width$}", "contract");
+    for (profile, _) in sizes {
+        let marker = if *profile == kept { "*" } else { "" };
+        print!(" {:>10}", format!("{}{}", marker, profile.name));
+    }
+    println!();
+    for contract in contracts.iter().copied().chain(["total"]) {
+        print!("{:<width$}", contract);
+        for (_, objects) in sizes {
+            let size: u64 = objects
+                .iter()
+                .filter(|(name, _)| contract == "total" || name == contract)
+                .map(|(_, size)| size)
+                .sum();
+            print!(" {:>10}", size);
+        }
+        println!();
+    }
+}
+
+/// The files each stage of the build of a contract produced
+#[derive(Debug)]
+struct StageOutputs {
+    target: String,
+    wasm: PathBuf,
+    ll: PathBuf,
+    /// `None` with `--skip-llc`
+    object: Option<PathBuf>,
+}
+
+impl StageOutputs {
+    /// The stages with their output, in the order they run
+    fn stages(&self) -> Vec<(&'static str, &Path)> {
+        let mut stages = vec![
+            ("the wasm build by cargo and rustc", self.wasm.as_path()),
+            ("the translation to LLVM IR", self.ll.as_path()),
+        ];
+        if let Some(object) = &self.object {
+            stages.push(("llc or the strip", object.as_path()));
+        }
+        stages
+    }
+}
+
+/// Directory of the target directory with the two builds of `--assert-deterministic`
+const DETERMINISM_DIR: &str = "l1x-deterministic";
+
+/// Builds the contracts twice, each from scratch in a target directory of its own in
+/// [`DETERMINISM_DIR`], and fails when the two builds of a contract differ, with the first
+/// stage whose output differs. The two builds are kept for inspection when they differ,
+/// otherwise the outputs of the first one are copied to `bin_dir`
+fn assert_deterministic(
+    args: &[String],
+    metadata: &Metadata,
+    target_dir: &Path,
+    bin_dir: &Path,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<(), BuildError> {
+    let determinism_dir = target_dir.join(DETERMINISM_DIR);
+    let mut builds = Vec::new();
+    for run in 1..=2 {
+        options.status(&format!("Building #{} for --assert-deterministic", run));
+        let dir = determinism_dir.join(format!("build-{}", run));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(io_error("Could not remove", &dir))?;
+        }
+        let mut run_args = args.to_vec();
+        run_args.push("--target-dir".to_string());
+        run_args.push(dir.join("target").to_string_lossy().into_owned());
+        let run_bin_dir = dir.join("l1x");
+        let mut outputs = BTreeMap::new();
+        // the IR is compared without the paths of the build and the numbering of the
+        // unnamed values, written next to the build to be kept out of `bin_dir`
+        let mut replacements = path_replacements(metadata.workspace_root.as_std_path());
+        replacements.push((dir.to_string_lossy().into_owned(), "<build>"));
+        let stable_dir = dir.join("stable");
+        for artifact in cargo_build(&run_args, options, metadata)?.into_artifacts()? {
+            let Some(wasm) = wasm_file(&artifact) else {
+                continue;
+            };
+            let report = build_contract(
+                &artifact,
+                &run_bin_dir,
+                options,
+                config,
+                metadata.workspace_root.as_std_path(),
+            )?;
+            if let Some(report) = report {
+                options.emit(BuildEvent::ContractBuilt {
+                    name: format!("{} (build {})", artifact.target.name, run),
+                    report: Box::new(report.clone()),
+                });
+                // examples may share a name with the library, their files don't
+                let key = report
+                    .ll
+                    .strip_prefix(&run_bin_dir)
+                    .unwrap_or(&report.ll)
+                    .to_path_buf();
+                let stable_ll = stable_dir.join(&key);
+                if let Some(parent) = stable_ll.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(io_error("Could not create directory", parent))?;
+                }
+                write_stabilized_ll(&report.ll, &stable_ll, &replacements)?;
+                outputs.insert(
+                    key,
+                    StageOutputs {
+                        target: artifact.target.name.clone(),
+                        wasm: wasm.into(),
+                        ll: stable_ll,
+                        object: report.object,
+                    },
+                );
+            }
+        }
+        builds.push((run_bin_dir, outputs));
+    }
+
+    let format = options.message_format.unwrap_or_default();
+    let mut different = Vec::new();
+    for (key, first) in &builds[0].1 {
+        let Some(second) = builds[1].1.get(key) else {
+            continue;
+        };
+        let stage = differing_stage(first, second)?;
+        let final_output = |outputs: &StageOutputs| {
+            file_sha256(outputs.stages().last().expect("There should be stages").1)
+        };
+        let checksums = [final_output(first)?, final_output(second)?];
+        let differences = match stage {
+            Some((_, first_file, second_file)) => file_differences(first_file, second_file)?,
+            None => Vec::new(),
+        };
+        match format {
+            MessageFormat::Json => println!(
+                "{}",
+                serde_json::json!({
+                    "reason": "l1x-determinism",
+                    "target": first.target,
+                    "deterministic": stage.is_none(),
+                    "stage": stage.map(|(stage, _, _)| stage),
+                    "sha256": checksums,
+                    "differences": differences,
+                })
+            ),
+            _ => match stage {
+                None => println!(
+                    "✅ The two builds of '{}' are identical, sha256 {}",
+                    first.target, checksums[0]
+                ),
+                Some((stage, first_file, _)) => {
+                    println!(
+                        "❌ The two builds of '{}' differ, starting with {}: {}",
+                        first.target,
+                        stage,
+                        first_file.display()
+                    );
+                    for difference in &differences {
+                        println!("   {}", difference);
+                    }
+                }
+            },
+        }
+        if stage.is_some() {
+            different.push(first.target.clone());
+        }
+    }
+    options.emit(BuildEvent::Finished {
+        built: builds[0]
+            .1
+            .values()
+            .map(|outputs| outputs.target.clone())
+            .filter(|target| !different.contains(target))
+            .collect(),
+        failed: different.clone(),
+    });
+
+    if !different.is_empty() {
+        eprintln!(
+            "The two builds were kept in '{}'",
+            determinism_dir.display()
+        );
+        return Err(BuildError::NotDeterministic(different));
+    }
+    copy_dir(&builds[0].0, bin_dir)?;
+    fs::remove_dir_all(&determinism_dir).map_err(io_error("Could not remove", &determinism_dir))?;
+    if format != MessageFormat::Json {
+        options.status(&format!(
+            "✅ Kept the first build in '{}'",
+            bin_dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// The first stage whose output differs between two builds, with the two files
+fn differing_stage<'a>(
+    first: &'a StageOutputs,
+    second: &'a StageOutputs,
+) -> Result<Option<(&'static str, &'a Path, &'a Path)>, BuildError> {
+    for ((stage, first_file), (_, second_file)) in first.stages().into_iter().zip(second.stages()) {
+        if file_sha256(first_file)? != file_sha256(second_file)? {
+            return Ok(Some((stage, first_file, second_file)));
+        }
+    }
+    Ok(None)
+}
+
+fn file_sha256(path: &Path) -> Result<String, BuildError> {
+    let content = fs::read(path).map_err(io_error("Could not read", path))?;
+    Ok(format!("{:x}", Sha256::digest(&content)))
+}
+
+/// What differs between two versions of an output: the size, and the sections of object
+/// files and wasm modules or the first different line of text files
+fn file_differences(first: &Path, second: &Path) -> Result<Vec<String>, BuildError> {
+    let first = fs::read(first).map_err(io_error("Could not read", first))?;
+    let second = fs::read(second).map_err(io_error("Could not read", second))?;
+    let mut differences = Vec::new();
+    if first.len() != second.len() {
+        differences.push(format!("size: {} != {} bytes", first.len(), second.len()));
+    }
+    match (object::File::parse(&*first), object::File::parse(&*second)) {
+        (Ok(first), Ok(second)) => {
+            let sections = |file: &object::File| -> BTreeMap<String, Vec<u8>> {
+                file.sections()
+                    .filter_map(|section| {
+                        Some((
+                            section.name().ok()?.to_string(),
+                            section.data().ok()?.to_vec(),
+                        ))
+                    })
+                    .collect()
+            };
+            let (first, second) = (sections(&first), sections(&second));
+            for (name, data) in &first {
+                match second.get(name) {
+                    None => differences.push(format!("section {}: only in the first build", name)),
+                    Some(other) if other.len() != data.len() => differences.push(format!(
+                        "section {}: {} != {} bytes",
+                        name,
+                        data.len(),
+                        other.len()
+                    )),
+                    Some(other) if other != data => {
+                        differences.push(format!("section {}: different content", name))
+                    }
+                    Some(_) => {}
+                }
+            }
+            for name in second.keys().filter(|name| !first.contains_key(*name)) {
+                differences.push(format!("section {}: only in the second build", name));
+            }
+        }
+        _ => {
+            let (first, second) = (
+                String::from_utf8_lossy(&first),
+                String::from_utf8_lossy(&second),
+            );
+            let line = first
+                .lines()
+                .zip(second.lines())
+                .position(|(first, second)| first != second)
+                .unwrap_or_else(|| first.lines().count().min(second.lines().count()));
+            if first != second {
+                differences.push(format!("first different line: {}", line + 1));
+            }
+        }
+    }
+    Ok(differences)
+}
+
+/// Compares the built `object` to the `reference` object file of `--compare-to` and prints
+/// what differs: the size, the sections and the defined symbols
+fn compare_to_reference(
+    object: &Path,
+    reference: &Path,
+    format: MessageFormat,
+) -> Result<(), BuildError> {
+    let data = fs::read(object).map_err(io_error("Could not read object file", object))?;
+    let reference_data =
+        fs::read(reference).map_err(io_error("Could not read the reference object", reference))?;
+    let diff = object_diff(&reference_data, &data).map_err(|e| BuildError::InvalidObject {
+        object: reference.to_path_buf(),
+        problems: vec![e.to_string()],
+    })?;
+    let differences = diff.lines();
+    match format {
+        MessageFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "reason": "l1x-object-diff",
+                "object": object,
+                "reference": reference,
+                "diff": diff,
+            })
+        ),
+        _ if diff.identical => println!(
+            "✅ '{}' is identical to the reference '{}'",
+            object.display(),
+            reference.display()
+        ),
+        _ => {
+            println!(
+                "❌ '{}' differs from the reference '{}':",
+                object.display(),
+                reference.display()
+            );
+            for difference in &differences {
+                println!("   {}", difference);
+            }
+        }
+    }
+    if !diff.identical {
+        return Err(BuildError::ObjectDiffers {
+            object: object.to_path_buf(),
+            reference: reference.to_path_buf(),
+            differences,
+        });
+    }
+    Ok(())
+}
+
+/// Copies the files of `from` into `to`, recursively
+fn copy_dir(from: &Path, to: &Path) -> Result<(), BuildError> {
+    fs::create_dir_all(to).map_err(io_error("Could not create target directory", to))?;
+    for entry in fs::read_dir(from).map_err(io_error("Could not read", from))? {
+        let path = entry.map_err(io_error("Could not read", from))?.path();
+        let destination = to.join(path.file_name().expect("Entry should have a file name"));
+        if path.is_dir() {
+            copy_dir(&path, &destination)?;
+        } else {
+            fs::copy(&path, &destination).map_err(io_error("Could not copy", &path))?;
+        }
+    }
+    Ok(())
+}
+
+/// The wasm module of a cargo artifact, `None` for the other crates. Only `cdylib` and
+/// `bin` targets are modules. The file named after the target is preferred over other
+/// wasm files, e.g. the hashed copies in `deps`, and existing files over missing ones
+fn wasm_file(artifact: &cargo_metadata::Artifact) -> Option<cargo_metadata::camino::Utf8PathBuf> {
+    let is_module = artifact
+        .target
+        .crate_types
+        .iter()
+        .any(|crate_type| crate_type == "cdylib" || crate_type == "bin");
+    if !is_module {
+        return None;
+    }
+    let name = artifact.target.name.replace('-', "_");
+    // a `["cdylib", "rlib"]` crate lists its rlib too, in no particular order
+    artifact
+        .filenames
+        .iter()
+        .filter(|filename| filename.extension() == Some("wasm"))
+        .min_by_key(|filename| {
+            let named_after_target =
+                filename.file_stem().map(|stem| stem.replace('-', "_")) == Some(name.clone());
+            // `is_file` follows symlinks
+            (!filename.is_file(), !named_after_target)
+        })
+        .cloned()
+}
+
+/// Warns about the contracts selected by `args` that have no `#[contract]` impl, e.g. a
+/// library built from the wrong directory, before anything is compiled. An error with
+/// `--strict`. The examples aren't checked
+fn check_contracts(
+    args: &[String],
+    metadata: &Metadata,
+    options: &BuildOptions,
+) -> Result<(), BuildError> {
+    for (target, _) in contract_targets(args, metadata, false)? {
+        let src_dir = target
+            .src_path
+            .parent()
+            .expect("Target source path should have a parent directory");
+        if has_contract_impl(src_dir.as_std_path()) {
+            continue;
+        }
+        if options.strict {
+            return Err(BuildError::NotAContract(target.name.clone()));
+        }
+        let depends_on_sdk = metadata
+            .workspace_packages()
+            .into_iter()
+            .find(|package| package.targets.contains(target))
+            .is_some_and(|package| package.dependencies.iter().any(|dep| dep.name == "l1x-sdk"));
+        options.warn(&format!(
+            "'{}' has no #[contract] impl{}, its object file won't be an L1X contract. Is this the right directory?",
+            target.name,
+            if depends_on_sdk {
+                ""
+            } else {
+                " and doesn't depend on l1x-sdk"
+            }
+        ));
+    }
+    Ok(())
+}
+
+/// `false` only when the sources in `src_dir` parse and have no `#[contract]` impl,
+/// sources `syn` can't read are given the benefit of the doubt
+fn has_contract_impl(src_dir: &Path) -> bool {
+    !matches!(Abi::from_source(src_dir), Err(AbiError::NoContract(_)))
+}
+
+/// Translates the wasm of a cargo artifact and compiles it to an eBPF object,
+/// returns `None` if the artifact is not a wasm module
+fn build_contract(
+    artifact: &cargo_metadata::Artifact,
+    bin_dir: &Path,
+    options: &BuildOptions,
+    config: &BuildConfig,
+    workspace_root: &Path,
+) -> Result<Option<ContractReport>, BuildError> {
+    let Some(wasm_file_path) = wasm_file(artifact) else {
+        return Ok(None);
+    };
+    let is_example = artifact.target.kind.iter().any(|kind| kind == "example");
+    let out_dir = if is_example {
+        // examples may share a name with the library, keep them apart
+        bin_dir.join("examples")
+    } else {
+        bin_dir.to_path_buf()
+    };
+    fs::create_dir_all(&out_dir)
+        .map_err(io_error("Could not create target directory", &out_dir))?;
+    // the module may be a symlink, or a copy with a hashed name
+    let wasm_file_path = fs::canonicalize(&wasm_file_path)
+        .map_err(io_error("Could not find the wasm module", &wasm_file_path))?;
+    let stem = artifact.target.name.replace('-', "_");
+    // only informational, the translation reports the modules it can't read
+    let wasm_features = fs::read(&wasm_file_path)
+        .ok()
+        .and_then(|wasm| wasm_features(&wasm).ok())
+        .map(|features| features.into_iter().map(str::to_string).collect());
+    let ll_file_path = out_dir.join(match &options.profile_name {
+        Some(profile) => format!("{}-{}.ll", stem, profile),
+        None => format!("{}.ll", stem),
+    });
+    options.emit(BuildEvent::TranslatingIr {
+        wasm: wasm_file_path.clone(),
+    });
+    translate_module(&wasm_file_path, &ll_file_path)?;
+
+    if options.emit_ll_stable {
+        write_stable_ll(&ll_file_path, workspace_root)?;
+    }
+
+    let mut commands = Vec::new();
+    let BackendOutput {
+        padded,
+        symbol_sizes,
+    } = build_ebpf(&ll_file_path, options, config, &mut commands)?;
+
+    let object = ll_file_path.with_extension("o");
+    if !options.skip_llc {
+        check_imports(&object, options, config)?;
+        if !options.no_check_elf {
+            check_elf(&object, options, config)?;
+        }
+        if options.deny_unknown_sections {
+            check_sections(&object)?;
+        }
+    }
+    let raw = match options.output_format {
+        OutputFormat::Raw if !options.skip_llc => Some(write_raw_binary(&object)?),
+        _ => None,
+    };
+    let compressed = match options.compress {
+        Some(compression) if !options.skip_llc => {
+            let compressed = compress_object(&object, compression)?;
+            if options.compress_only {
+                fs::remove_file(&object).map_err(io_error("Could not remove", &object))?;
+            }
+            Some(compressed)
+        }
+        _ => None,
+    };
+
+    let borsh_schema = if options.borsh_schema && !is_example {
+        let src_dir = artifact
+            .target
+            .src_path
+            .parent()
+            .expect("Target source path should have a parent directory");
+        let abi = Abi::from_source_of(src_dir, options.contract_type.as_deref())?;
+        let schema_path = ll_file_path.with_extension("borsh.json");
+        fs::write(&schema_path, format!("{:#}\n", borsh_schema(&abi)))
+            .map_err(io_error("Could not write borsh schema", &schema_path))?;
+        Some(schema_path)
+    } else {
+        None
+    };
+
+    Ok(Some(ContractReport {
+        package_id: artifact.package_id.repr.clone(),
+        ll: ll_file_path.clone(),
+        object: (!options.skip_llc && !options.compress_only).then_some(object),
+        debug_object: (options.split_debug && !options.no_strip && !options.skip_llc)
+            .then(|| ll_file_path.with_extension("debug.o")),
+        padded,
+        compressed,
+        raw,
+        borsh_schema,
+        commands,
+        profile: options.profile_name.clone(),
+        wasm_features,
+        symbol_sizes,
+    }))
+}
+
+/// Writes `<name>.stable.ll`, a copy of the translated module without the machine
+/// specific paths, so that the IR of two builds can be diffed
+fn write_stable_ll(ll_file: &Path, workspace_root: &Path) -> Result<PathBuf, BuildError> {
+    let stable_file = ll_file.with_extension("stable.ll");
+    write_stabilized_ll(ll_file, &stable_file, &path_replacements(workspace_root))?;
+    Ok(stable_file)
+}
+
+/// The machine specific paths of a build and their placeholders
+fn path_replacements(workspace_root: &Path) -> Vec<(String, &'static str)> {
+    let mut replacements = vec![(workspace_root.to_string_lossy().into_owned(), "<workspace>")];
+    let home = std::env::var("HOME").ok();
+    let cargo_home = std::env::var("CARGO_HOME")
+        .ok()
+        .or_else(|| home.as_ref().map(|home| format!("{}/.cargo", home)));
+    if let Some(cargo_home) = cargo_home {
+        replacements.push((cargo_home, "<cargo-home>"));
+    }
+    if let Some(home) = home {
+        replacements.push((home, "<home>"));
+    }
+    replacements
+}
+
+fn write_stabilized_ll(
+    ll_file: &Path,
+    stable_file: &Path,
+    replacements: &[(String, &str)],
+) -> Result<(), BuildError> {
+    let content = fs::read_to_string(ll_file).map_err(io_error("Could not read", ll_file))?;
+    fs::write(stable_file, stabilize_ll(&content, replacements))
+        .map_err(io_error("Could not write", stable_file))
+}
+
+/// Replaces the module identifiers and the `replacements` paths, longest first, and
+/// renumbers the unnamed values, see [`renumber_unnamed`]
+fn stabilize_ll(content: &str, replacements: &[(String, &str)]) -> String {
+    let mut replacements: Vec<_> = replacements
+        .iter()
+        .filter(|(path, _)| !path.is_empty())
+        .collect();
+    replacements.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+
+    let mut stable = String::with_capacity(content.len());
+    for line in content.lines() {
+        if line.starts_with("; ModuleID = ") {
+            stable.push_str("; ModuleID = '<module>'");
+        } else if line.starts_with("source_filename = ") {
+            stable.push_str("source_filename = \"<module>\"");
+        } else {
+            let mut line = line.to_string();
+            for (path, placeholder) in &replacements {
+                line = line.replace(path.as_str(), placeholder);
+            }
+            stable.push_str(&line);
+        }
+        stable.push('\n');
+    }
+    renumber_unnamed(&stable)
+}
+
+/// Numbers the unnamed globals `@N` in the order they first appear in the module, and the
+/// unnamed locals `%N` and blocks `N:` in the order they first appear in their function,
+/// so that the numbering doesn't depend on the order the translation created them in.
+/// String constants are left as they are
+fn renumber_unnamed(content: &str) -> String {
+    let mut globals: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut locals: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut renumbered = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        if line.starts_with("define ") {
+            locals.clear();
+        }
+        let mut rest = line;
+        // an unnamed block starts with its number, e.g. `12:` or `12:  ; preds = %3`
+        let label_len = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if label_len > 0 && rest[label_len..].starts_with(':') {
+            let next = locals.len();
+            let number = *locals.entry(&rest[..label_len]).or_insert(next);
+            renumbered.push_str(&number.to_string());
+            rest = &rest[label_len..];
+        }
+        let mut in_string = false;
+        let mut previous = ' ';
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            renumbered.push(c);
+            if c == '"' {
+                in_string = !in_string;
+            }
+            let is_sigil = (c == '%' || c == '@')
+                && !in_string
+                && !(previous.is_ascii_alphanumeric() || matches!(previous, '_' | '.' | '$'));
+            previous = c;
+            if !is_sigil {
+                continue;
+            }
+            let len = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if len == 0 {
+                continue;
+            }
+            let names = if c == '@' { &mut globals } else { &mut locals };
+            let next = names.len();
+            let number = *names.entry(&rest[..len]).or_insert(next);
+            renumbered.push_str(&number.to_string());
+            previous = '0';
+            rest = &rest[len..];
+        }
+    }
+    renumbered
+}
+
+/// Writes the code and data sections of `object` one after the other, each aligned, to
+/// `<name>.bin` next to it for `--output-format raw`. Returns its path and size. The
+/// relocations aren't applied, a warning tells when the sections have some
+fn write_raw_binary(object: &Path) -> Result<RawBinary, BuildError> {
+    let invalid = |problem: String| BuildError::InvalidObject {
+        object: object.to_path_buf(),
+        problems: vec![problem],
+    };
+    let data = fs::read(object).map_err(io_error("Could not read object file", object))?;
+    let file = object::File::parse(&*data).map_err(|e| invalid(e.to_string()))?;
+    let mut raw = Vec::new();
+    let mut sections = Vec::new();
+    let mut relocated = Vec::new();
+    for section in file.sections() {
+        if !matches!(
+            section.kind(),
+            object::SectionKind::Text
+                | object::SectionKind::Data
+                | object::SectionKind::ReadOnlyData
+                | object::SectionKind::ReadOnlyString
+        ) {
+            continue;
+        }
+        let content = section.data().map_err(|e| invalid(e.to_string()))?;
+        let align = section.align().max(1) as usize;
+        raw.resize(raw.len().div_ceil(align) * align, 0);
+        let name = section.name().unwrap_or_default().to_string();
+        if section.relocations().next().is_some() {
+            relocated.push(format!(
+                "{} has relocations, a raw binary can't have them applied",
+                name
+            ));
+        }
+        sections.push(RawSection {
+            name,
+            offset: raw.len() as u64,
+            size: content.len() as u64,
+        });
+        raw.extend_from_slice(content);
+    }
+    if !relocated.is_empty() {
+        return Err(BuildError::InvalidObject {
+            object: object.to_path_buf(),
+            problems: relocated,
+        });
+    }
+    if raw.is_empty() {
+        return Err(invalid(
+            "no code or data to write as raw binary".to_string(),
+        ));
+    }
+    let path = object.with_extension("bin");
+    fs::write(&path, &raw).map_err(io_error("Could not write", &path))?;
+    Ok(RawBinary {
+        path,
+        size: raw.len() as u64,
+        sections,
+    })
+}
+
+/// Writes a compressed copy of `object` next to it, returns its path and size
+fn compress_object(object: &Path, compression: Compression) -> Result<(PathBuf, u64), BuildError> {
+    let compressed = object.with_extension(compression.extension());
+    let mut input = fs::File::open(object).map_err(io_error("Could not open", object))?;
+    let output =
+        fs::File::create(&compressed).map_err(io_error("Could not create", &compressed))?;
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::best());
+            std::io::copy(&mut input, &mut encoder)
+                .and_then(|_| encoder.finish())
+                .map_err(io_error("Could not compress to", &compressed))?;
+        }
+        Compression::Zstd => {
+            zstd::stream::copy_encode(input, output, zstd::DEFAULT_COMPRESSION_LEVEL)
+                .map_err(io_error("Could not compress to", &compressed))?;
+        }
+    }
+    let size = fs::metadata(&compressed)
+        .map_err(io_error("Could not read metadata of", &compressed))?
+        .len();
+    Ok((compressed, size))
+}
+
+/// Files left by previous builds in `bin_dir` and its `examples` directory, with their size
+fn list_artifacts(bin_dir: &Path) -> Result<Vec<(PathBuf, u64)>, BuildError> {
+    let mut artifacts = Vec::new();
+    for dir in [bin_dir.to_path_buf(), bin_dir.join("examples")] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue; // nothing was built there
+        };
+        for entry in entries {
+            let entry = entry.map_err(io_error("Could not read directory", &dir))?;
+            let path = entry.path();
+            if !matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("o" | "ll" | "json" | "gz" | "zst" | "bin")
+            ) {
+                continue;
+            }
+            let size = entry
+                .metadata()
+                .map_err(io_error("Could not read metadata of", &path))?
+                .len();
+            artifacts.push((path, size));
+        }
+    }
+    artifacts.sort();
+    Ok(artifacts)
+}
+
+/// A contract of the `--summary-only` report
+#[derive(Debug, Clone, PartialEq)]
+struct SummaryEntry {
+    name: String,
+    /// The object file, or the compressed or LLVM IR file when there's no object file
+    file: PathBuf,
+    size: u64,
+    sha256: String,
+    object_file_version: i64,
+    expected_runtime_version: i64,
+    wasm_features: Option<Vec<String>>,
+}
+
+impl SummaryEntry {
+    fn new(name: &str, report: &ContractReport, config: &BuildConfig) -> Result<Self, BuildError> {
+        let file = report
+            .object
+            .clone()
+            .or_else(|| report.compressed.as_ref().map(|(path, _)| path.clone()))
+            .unwrap_or_else(|| report.ll.clone());
+        let content = fs::read(&file).map_err(io_error("Could not read", &file))?;
+        Ok(Self {
+            name: name.to_string(),
+            size: content.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(&content)),
+            file,
+            object_file_version: config.object_file_version,
+            expected_runtime_version: config.expected_runtime_version,
+            wasm_features: report.wasm_features.clone(),
+        })
+    }
+}
+
+/// The single report of `--summary-only`: the built contracts with their size, checksum
+/// and versions, the failed ones and the build time
+fn print_report(
+    entries: &[SummaryEntry],
+    failed: &[String],
+    duration: std::time::Duration,
+    format: MessageFormat,
+) {
+    if format == MessageFormat::Json {
+        let contracts: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "name": entry.name,
+                    "file": entry.file,
+                    "size": entry.size,
+                    "sha256": entry.sha256,
+                    "object_file_version": entry.object_file_version,
+                    "expected_runtime_version": entry.expected_runtime_version,
+                    "wasm_features": entry.wasm_features,
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::json!({
+                "reason": "l1x-build-report",
+                "contracts": contracts,
+                "failed": failed,
+                "duration_secs": duration.as_secs_f64(),
+            })
+        );
+        return;
+    }
+
+    println!(
+        "Built {} contract(s) in {:.1}s{}",
+        entries.len(),
+        duration.as_secs_f64(),
+        match failed.len() {
+            0 => String::new(),
+            count => format!(", {} failed", count),
+        }
+    );
+    let width = entries
+        .iter()
+        .map(|entry| entry.name.len())
+        .max()
+        .unwrap_or(0);
+    for entry in entries {
+        println!(
+            "  {:<width$}  {:>8} bytes  sha256 {}  object v{}  runtime v{}  wasm {}  {}",
+            entry.name,
+            entry.size,
+            entry.sha256,
+            entry.object_file_version,
+            entry.expected_runtime_version,
+            entry
+                .wasm_features
+                .as_deref()
+                .map_or_else(|| "?".to_string(), format_wasm_features),
+            entry.file.display()
+        );
+    }
+    for name in failed {
+        println!("  ❌ {}", name);
+    }
+}
+
+/// Lists the contracts that were built and the ones that failed with `--keep-going`
+fn print_summary(built: &[String], failed: &[String], format: MessageFormat) {
+    match format {
+        MessageFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "reason": "l1x-build-finished",
+                "built": built,
+                "failed": failed,
+            })
+        ),
+        _ if failed.is_empty() => println!("Built {} contract(s)", built.len()),
+        _ => {
+            println!("{} contract(s) built, {} failed", built.len(), failed.len());
+            for name in failed {
+                println!("  ❌ {}", name);
+            }
+        }
+    }
+}
+
+/// Returns the names of the workspace members whose directory, relative to the
+/// workspace root, matches any of the glob `patterns`
+fn select_members(metadata: &Metadata, patterns: &[String]) -> Result<Vec<String>, BuildError> {
+    let patterns = patterns
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern)
+                .map_err(|_| BuildError::InvalidOption("--members".to_string(), pattern.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let selected: Vec<String> = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter(|package| {
+            let dir = package
+                .manifest_path
+                .parent()
+                .expect("Manifest path should have a parent directory");
+            let dir = dir.strip_prefix(&metadata.workspace_root).unwrap_or(dir);
+            patterns.iter().any(|pattern| pattern.matches(dir.as_str()))
+        })
+        .map(|package| package.name.clone())
+        .collect();
+
+    if selected.is_empty() {
+        return Err(BuildError::NoMatchingMembers(
+            patterns
+                .iter()
+                .map(|p| p.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ));
+    }
+    Ok(selected)
+}
+
+/// The settings a build with `options` and `config` uses once the command line, the
+/// environment, `l1x.toml` and the `--config` profile are merged, see `--dump-config`.
+/// The tools are `null` when they aren't installed
+fn effective_config(
+    options: &BuildOptions,
+    config: &BuildConfig,
+    target_dir: &Path,
+) -> Vec<(&'static str, serde_json::Value)> {
+    use serde_json::json;
+    let strip_mode = if options.no_strip { "none" } else { "full" };
+    let strip = match &options.strip_binary {
+        Some(strip_binary) => Some(strip_binary.clone()),
+        None if options.no_strip => None,
+        None => get_strip_command().ok(),
+    };
+    vec![
+        ("profile", json!(options.profile_name)),
+        (
+            "cargo_profile",
+            json!(options.cargo_profile.as_deref().unwrap_or("release")),
+        ),
+        ("wasm_target", json!(options.wasm_target())),
+        ("output_dir", json!(options.bin_dir(target_dir))),
+        ("llc", json!(get_llc_command().ok())),
+        ("cpu", json!(options.cpu(config))),
+        ("mattr", json!(options.mattr)),
+        (
+            "stack_size",
+            json!(options.stack_size.unwrap_or(config.stack_frame_size)),
+        ),
+        ("runtime_version", json!(options.runtime_version)),
+        ("version_info", json!(!options.no_version_info)),
+        ("object_file_version", json!(config.object_file_version)),
+        (
+            "expected_runtime_version",
+            json!(config.expected_runtime_version),
+        ),
+        ("host_functions", json!(config.host_functions)),
+        ("strip_mode", json!(strip_mode)),
+        ("strip", json!(strip)),
+        ("check_elf", json!(!options.no_check_elf)),
+        ("output_format", json!(options.output_format.name())),
+        (
+            "deny_unknown_sections",
+            json!(options.deny_unknown_sections),
+        ),
+    ]
+}
+
+fn print_config(settings: &[(&'static str, serde_json::Value)], format: MessageFormat) {
+    match format {
+        MessageFormat::Json => {
+            let mut object = serde_json::Map::new();
+            object.insert("reason".to_string(), "l1x-config".into());
+            for (key, value) in settings {
+                object.insert(key.to_string(), value.clone());
+            }
+            println!("{}", serde_json::Value::Object(object));
+        }
+        _ => {
+            for (key, value) in settings {
+                let value = match value {
+                    serde_json::Value::Null => "-".to_string(),
+                    serde_json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                println!("{:<26}{}", format!("{}:", key), value);
+            }
+        }
+    }
+}
+
+/// The cdylib targets of the workspace packages selected by the `-p` arguments of `args`,
+/// of all of them without `-p`, and whether each is an example
+pub(crate) fn contract_targets<'a>(
+    args: &[String],
+    metadata: &'a Metadata,
+    examples: bool,
+) -> Result<Vec<(&'a cargo_metadata::Target, bool)>, BuildError> {
+    let mut cargo_args = args.to_vec();
+    let mut selected = take_values(&mut cargo_args, "-p")?;
+    selected.extend(take_values(&mut cargo_args, "--package")?);
+
+    let mut targets = Vec::new();
+    for package in metadata.workspace_packages() {
+        if !selected.is_empty() && !selected.contains(&package.name) {
+            continue;
+        }
+        for target in &package.targets {
+            let is_example = target.kind.iter().any(|kind| kind == "example");
+            if target.crate_types.iter().any(|t| t == "cdylib") && (examples || !is_example) {
+                targets.push((target, is_example));
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Writes the ABI of each contract selected by `args` to `<name>.abi.json` in the bin
+/// directory, read from the `#[contract]` impl in the sources without compiling anything.
+/// Returns the contracts and their files
+fn write_abis(
+    args: &[String],
+    metadata: &Metadata,
+    options: &BuildOptions,
+    target_dir: &Path,
+) -> Result<Vec<(String, PathBuf)>, BuildError> {
+    let bin_dir = options.bin_dir(target_dir);
+    fs::create_dir_all(&bin_dir)
+        .map_err(io_error("Could not create target directory", &bin_dir))?;
+    let mut written = Vec::new();
+    for (target, _) in contract_targets(args, metadata, false)? {
+        let src_dir = target
+            .src_path
+            .parent()
+            .expect("Target source path should have a parent directory");
+        let abi = Abi::from_source_of(src_dir, options.contract_type.as_deref())?;
+        let path = bin_dir.join(format!("{}.abi.json", target.name.replace('-', "_")));
+        let content = serde_json::to_string_pretty(&abi).expect("ABI should serialize");
+        fs::write(&path, content + "\n").map_err(io_error("Could not write", &path))?;
+        written.push((target.name.clone(), path));
+    }
+    Ok(written)
+}
+
+/// The wasm module the cargo build with `options` produces for `target`
+pub(crate) fn wasm_module_path(
+    target: &Target,
+    is_example: bool,
+    options: &BuildOptions,
+    target_dir: &Path,
+) -> PathBuf {
+    let profile_dir = match options.cargo_profile.as_deref() {
+        None | Some("release" | "bench") => "release",
+        Some("dev" | "test") => "debug",
+        Some(profile) => profile,
+    };
+    let wasm_dir = target_dir.join(options.wasm_target()).join(profile_dir);
+    let file_name = format!("{}.wasm", target.name.replace('-', "_"));
+    if is_example {
+        wasm_dir.join("examples").join(file_name)
+    } else {
+        wasm_dir.join(file_name)
+    }
+}
+
+/// Names of the workspace members with files changed since the git ref `since`,
+/// `None` with a warning if git can't tell, then everything is built
+fn changed_since(metadata: &Metadata, since: &str) -> Option<Vec<String>> {
+    let git = which_optional("git", "--since, building all workspace members")?;
+    // the changes since `since`, committed or not, and the new files not ignored
+    let outputs = [
+        vec!["diff", "--name-only", "--relative", since, "--"],
+        vec!["ls-files", "--others", "--exclude-standard"],
+    ]
+    .map(|args| {
+        Command::new(&git)
+            .args(args)
+            .current_dir(&metadata.workspace_root)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+    });
+    match outputs {
+        [Some(changed), Some(untracked)] => {
+            let changed = String::from_utf8_lossy(&changed.stdout);
+            let untracked = String::from_utf8_lossy(&untracked.stdout);
+            Some(changed_members(
+                metadata,
+                changed.lines().chain(untracked.lines()),
+            ))
+        }
+        _ => {
+            eprintln!(
+                "⚠️  Could not list the files changed since '{}' with git, building all workspace members",
+                since
+            );
+            None
+        }
+    }
+}
+
+/// The uncommitted and untracked files of the git working tree, `None` when git isn't
+/// installed or `dir` is not in a git repository
+fn dirty_files(dir: &Path) -> Option<Vec<String>> {
+    let git = which_optional("git", "the --require-clean check")?;
+    let output = Command::new(git)
+        .args(["status", "--porcelain"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.get(3..))
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Files of the workspace root that change the build of every member
+const WORKSPACE_FILES: [&str; 7] = [
+    "Cargo.toml",
+    "Cargo.lock",
+    CONFIG_FILE_NAME,
+    "rust-toolchain",
+    "rust-toolchain.toml",
+    ".cargo/config",
+    ".cargo/config.toml",
+];
+
+/// Maps files, relative to the workspace root, to the workspace members whose build they
+/// change: the local package containing a file and the members depending on it, through
+/// path dependencies. A change of one of the [`WORKSPACE_FILES`] changes all members
+fn changed_members<'a>(metadata: &Metadata, files: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let members = metadata.workspace_packages();
+    // the members and the path dependencies
+    let packages: Vec<_> = metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_none())
+        .collect();
+    let dirs: Vec<_> = packages
+        .iter()
+        .map(|package| {
+            let dir = package
+                .manifest_path
+                .parent()
+                .expect("Manifest path should have a parent directory");
+            dir.strip_prefix(&metadata.workspace_root).unwrap_or(dir)
+        })
+        .collect();
+    let mut changed = BTreeSet::new();
+    for file in files {
+        if WORKSPACE_FILES.contains(&file) {
+            return members.iter().map(|package| package.name.clone()).collect();
+        }
+        // the innermost package owns the file, the root package contains the others
+        let owner = dirs
+            .iter()
+            .enumerate()
+            .filter(|(_, dir)| Path::new(file).starts_with(dir))
+            .max_by_key(|(_, dir)| dir.as_str().len());
+        if let Some((i, _)) = owner {
+            changed.insert(&packages[i].id);
+        }
+    }
+
+    // the packages depending on a changed one changed too
+    let mut dependents: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for node in metadata.resolve.iter().flat_map(|resolve| &resolve.nodes) {
+        for dependency in &node.deps {
+            dependents
+                .entry(&dependency.pkg)
+                .or_default()
+                .push(&node.id);
+        }
+    }
+    let mut pending: Vec<_> = changed.iter().copied().collect();
+    while let Some(id) = pending.pop() {
+        for dependent in dependents.get(id).into_iter().flatten() {
+            if changed.insert(dependent) {
+                pending.push(dependent);
+            }
+        }
+    }
+    members
+        .iter()
+        .filter(|package| changed.contains(&package.id))
+        .map(|package| package.name.clone())
+        .collect()
+}
+
+pub(crate) fn cargo_build_command(args: &[String], options: &BuildOptions) -> Command {
+    let mut command = process::Command::new("cargo");
+    let ambient_rustflags = if options.hermetic {
+        for (name, _) in std::env::vars_os() {
+            if name.to_str().is_some_and(is_hermetic_cleared) {
+                command.env_remove(name);
+            }
+        }
+        None
+    } else {
+        std::env::var("RUSTFLAGS").ok()
+    };
+    if let Some(rustflags) = rustflags(ambient_rustflags, options) {
+        command.env("RUSTFLAGS", rustflags);
+    }
+
+    let cargo_profile = options.cargo_profile.as_deref().unwrap_or("release");
+    if let Some(profile) = options.size_profile {
+        // the settings of the cargo profile are overridden through the environment
+        let prefix = format!(
+            "CARGO_PROFILE_{}",
+            cargo_profile.replace('-', "_").to_uppercase()
+        );
+        command
+            .env(format!("{}_OPT_LEVEL", prefix), profile.opt_level)
+            .env(format!("{}_LTO", prefix), profile.lto.to_string());
+    }
+
+    command
+        .arg("build")
+        .arg("--target")
+        .arg(options.wasm_target())
+        .args(args);
+
+    if options.examples {
+        // build the contract library together with the example contracts
+        command.arg("--lib").arg("--examples");
+    }
+    if options.keep_going {
+        // the contracts that don't depend on a failed crate are still built
+        command.arg("--keep-going");
+    }
+
+    // the profile flags were removed by `normalize_profile_args`
+    match &options.cargo_profile {
+        Some(profile) => command.arg("--profile").arg(profile),
+        None => command.arg("--release"),
+    };
+    command
+}
+
+/// Variables removed from the environment of cargo by `--hermetic`, the variables set by
+/// the tool itself, `RUSTFLAGS` and the size profile settings, are set afterwards
+pub const HERMETIC_CLEARED_ENV: &[&str] = &[
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_BUILD_RUSTFLAGS",
+    "RUSTDOCFLAGS",
+    "CARGO_ENCODED_RUSTDOCFLAGS",
+    "CARGO_BUILD_RUSTDOCFLAGS",
+    "RUSTC",
+    "CARGO_BUILD_RUSTC",
+    "RUSTC_WRAPPER",
+    "CARGO_BUILD_RUSTC_WRAPPER",
+    "RUSTC_WORKSPACE_WRAPPER",
+    "CARGO_BUILD_RUSTC_WORKSPACE_WRAPPER",
+    "RUSTC_BOOTSTRAP",
+    "CARGO_INCREMENTAL",
+    "CARGO_BUILD_INCREMENTAL",
+    "CARGO_BUILD_JOBS",
+    "CARGO_BUILD_TARGET",
+];
+
+/// Prefixes of the variables removed by `--hermetic`: `CARGO_PROFILE_<NAME>_*`,
+/// `CARGO_UNSTABLE_*` and `CARGO_TARGET_<TRIPLE>_*`, but not `CARGO_TARGET_DIR`
+pub const HERMETIC_CLEARED_ENV_PREFIXES: &[&str] =
+    &["CARGO_PROFILE_", "CARGO_UNSTABLE_", "CARGO_TARGET_"];
+
+/// `true` if `--hermetic` removes the variable `name` from the environment of cargo
+fn is_hermetic_cleared(name: &str) -> bool {
+    HERMETIC_CLEARED_ENV.contains(&name)
+        || (name != "CARGO_TARGET_DIR"
+            && HERMETIC_CLEARED_ENV_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix)))
+}
+
+/// The user's `RUSTFLAGS` followed by the flags the options need
+fn rustflags(ambient: Option<String>, options: &BuildOptions) -> Option<String> {
+    let mut flags: Vec<&str> = ambient.iter().map(|flags| flags.trim()).collect();
+    if !options.no_link_strip {
+        flags.push("-C link-arg=-s");
+    }
+    if options.deny_warnings {
+        flags.push("-D warnings");
+    }
+    flags.retain(|flags| !flags.is_empty());
+    (!flags.is_empty()).then(|| flags.join(" "))
+}
+
+/// Removes the `--release`, `-r` and `--profile` forms from the cargo args and returns the
+/// `--profile`, `None` for the release profile the contracts are built with by default.
+/// `--release` together with another profile is rejected, like cargo does
+fn normalize_profile_args(args: &mut Vec<String>) -> Result<Option<String>, BuildError> {
+    let profiles = take_values(args, "--profile")?;
+    let len = args.len();
+    args.retain(|arg| arg != "-r" && !arg.eq_ignore_ascii_case("--release"));
+    let release = args.len() != len;
+
+    let Some(profile) = profiles.last() else {
+        return Ok(None);
+    };
+    if let Some(other) = profiles.iter().find(|other| *other != profile) {
+        return Err(BuildError::InvalidOption(
+            "--profile".to_string(),
+            other.clone(),
+        ));
+    }
+    if release && profile != "release" {
+        return Err(BuildError::InvalidOption(
+            "--profile".to_string(),
+            profile.clone(),
+        ));
+    }
+    Ok(Some(profile.clone()))
+}
+
+/// What [`build_ebpf`] reports besides the object file
+#[derive(Debug, Default)]
+pub struct BackendOutput {
+    pub padded: Option<PaddedObject>,
+    /// The symbols of the unstripped object file by size, with `--explain-size`
+    pub symbol_sizes: Option<Vec<SymbolSize>>,
+}
+
+/// Size and checksum of an object file padded with `--pad-to`
+#[derive(Debug, Clone)]
+pub struct PaddedObject {
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// The raw binary of `--output-format raw`
+#[derive(Debug, Clone)]
+pub struct RawBinary {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Where the sections of the object file are in the binary
+    pub sections: Vec<RawSection>,
+}
+
+/// A section of the object file in a [`RawBinary`]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct RawSection {
+    pub name: String,
+    /// Offset in the binary, in bytes
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Outcome of building a single contract
+#[derive(Debug, Clone)]
+pub struct ContractReport {
+    pub package_id: String,
+    pub ll: PathBuf,
+    /// `None` when llc was skipped
+    pub object: Option<PathBuf>,
+    pub debug_object: Option<PathBuf>,
+    pub padded: Option<PaddedObject>,
+    /// The compressed copy and its size
+    pub compressed: Option<(PathBuf, u64)>,
+    pub raw: Option<RawBinary>,
+    pub borsh_schema: Option<PathBuf>,
+    /// The llc and strip commands run for the contract
+    pub commands: Vec<CommandRecord>,
+    /// The `--config` profile the contract was built with
+    pub profile: Option<String>,
+    /// The post-MVP features the wasm module uses, `None` if it couldn't be parsed
+    pub wasm_features: Option<Vec<String>>,
+    /// The symbols of the object file by size, with `--explain-size`
+    pub symbol_sizes: Option<Vec<SymbolSize>>,
+}
+
+impl ContractReport {
+    fn print(&self, format: MessageFormat) {
+        let file_name = |path: &Path| {
+            path.file_name()
+                .expect("Generated object file should have a file name")
+                .to_owned()
+        };
+        match format {
+            MessageFormat::Human => {
+                match (&self.object, &self.compressed) {
+                    (Some(object), _) => println!(
+                        "✅ Contract object file '{:?}' has been built",
+                        file_name(object)
+                    ),
+                    // with --compress-only, the compressed object file is reported below
+                    (None, Some(_)) => {}
+                    (None, None) => println!(
+                        "✅ Contract LLVM IR file '{:?}' has been generated, llc was skipped",
+                        file_name(&self.ll)
+                    ),
+                }
+                if let Some(padded) = &self.padded {
+                    println!(
+                        "   padded to {} bytes, sha256: {}",
+                        padded.size, padded.sha256
+                    );
+                }
+                if let Some((compressed, size)) = &self.compressed {
+                    println!(
+                        "✅ Compressed object file '{:?}' has been written ({} bytes)",
+                        file_name(compressed),
+                        size
+                    );
+                }
+                if let Some(raw) = &self.raw {
+                    println!(
+                        "✅ Raw binary '{:?}' has been written ({} bytes)",
+                        file_name(&raw.path),
+                        raw.size
+                    );
+                    for section in &raw.sections {
+                        println!(
+                            "   {} at offset {:#x}, {} bytes",
+                            section.name, section.offset, section.size
+                        );
+                    }
+                }
+                if let Some(debug_object) = &self.debug_object {
+                    println!(
+                        "✅ Debug object file '{:?}' has been kept",
+                        file_name(debug_object)
+                    );
+                }
+                if let Some(borsh_schema) = &self.borsh_schema {
+                    println!(
+                        "✅ Borsh schema '{:?}' has been written",
+                        file_name(borsh_schema)
+                    );
+                }
+                if let Some(features) = &self.wasm_features {
+                    println!("   wasm features: {}", format_wasm_features(features));
+                }
+                if let Some(symbol_sizes) = &self.symbol_sizes {
+                    print_symbol_sizes(symbol_sizes);
+                }
+            }
+            MessageFormat::Short => println!(
+                "built {}",
+                self.object
+                    .as_ref()
+                    .or(self.compressed.as_ref().map(|(path, _)| path))
+                    .unwrap_or(&self.ll)
+                    .display()
+            ),
+            MessageFormat::Json => println!(
+                "{}",
+                serde_json::json!({
+                    "reason": "l1x-contract-built",
+                    "package_id": self.package_id,
+                    "profile": self.profile,
+                    "ll": self.ll,
+                    "object": self.object,
+                    "debug_object": self.debug_object,
+                    "borsh_schema": self.borsh_schema,
+                    "padded_size": self.padded.as_ref().map(|p| p.size),
+                    "sha256": self.padded.as_ref().map(|p| &p.sha256),
+                    "compressed": self.compressed.as_ref().map(|(path, _)| path),
+                    "compressed_size": self.compressed.as_ref().map(|(_, size)| size),
+                    "raw": self.raw.as_ref().map(|raw| &raw.path),
+                    "raw_size": self.raw.as_ref().map(|raw| raw.size),
+                    "raw_sections": self.raw.as_ref().map(|raw| &raw.sections),
+                    "wasm_features": self.wasm_features,
+                    "symbol_sizes": self.symbol_sizes,
+                })
+            ),
+        }
+    }
+}
+
+/// Number of symbols listed by `--explain-size`
+const EXPLAIN_SIZE_SYMBOLS: usize = 20;
+
+/// Prints the [`EXPLAIN_SIZE_SYMBOLS`] largest symbols, and the total of the others
+fn print_symbol_sizes(symbol_sizes: &[SymbolSize]) {
+    let total: u64 = symbol_sizes.iter().map(|symbol| symbol.size).sum();
+    println!(
+        "   largest symbols of the unstripped object ({} bytes):",
+        total
+    );
+    for symbol in symbol_sizes.iter().take(EXPLAIN_SIZE_SYMBOLS) {
+        println!(
+            "   {:>8} {:>5.1}%  {:<8}  {}",
+            symbol.size,
+            symbol.size as f64 * 100.0 / total as f64,
+            symbol.kind,
+            symbol.name
+        );
+    }
+    if let Some(others) = symbol_sizes.get(EXPLAIN_SIZE_SYMBOLS..) {
+        if !others.is_empty() {
+            println!(
+                "   {:>8}         {} other symbols",
+                others.iter().map(|symbol| symbol.size).sum::<u64>(),
+                others.len()
+            );
+        }
+    }
+}
+
+fn format_wasm_features(features: &[String]) -> String {
+    if features.is_empty() {
+        "mvp".to_string()
+    } else {
+        features.join(",")
+    }
+}
+
+/// How cargo diagnostics and the tool's own messages are rendered
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    Short,
+    Json,
+}
+
+impl MessageFormat {
+    /// The cargo `--message-format` whose JSON messages render the diagnostics in this format
+    fn cargo_json_format(&self, color: bool) -> &'static str {
+        match (self, color) {
+            (MessageFormat::Human, true) => "json-diagnostic-rendered-ansi",
+            (MessageFormat::Short, true) => "json-diagnostic-short,json-diagnostic-rendered-ansi",
+            (MessageFormat::Short, false) => "json-diagnostic-short",
+            (MessageFormat::Human | MessageFormat::Json, _) => "json",
+        }
+    }
+}
+
+impl std::str::FromStr for MessageFormat {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "short" => Ok(MessageFormat::Short),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(BuildError::InvalidOption(
+                "--message-format".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// Whether llvm-strip runs on the object file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StripMode {
+    /// Strip debug information and symbols, the default
+    Full,
+    /// Keep the object file as llc wrote it
+    None,
+}
+
+impl std::str::FromStr for StripMode {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(StripMode::Full),
+            "none" => Ok(StripMode::None),
+            _ => Err(BuildError::InvalidOption(
+                "--strip-mode".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// The format of the final artifact, see `--output-format`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// The stripped ELF object file, the default
+    #[default]
+    Elf,
+    /// Also the code and data sections as a flat `<name>.bin`
+    Raw,
+}
+
+impl OutputFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Elf => "elf",
+            OutputFormat::Raw => "raw",
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "elf" => Ok(OutputFormat::Elf),
+            "raw" => Ok(OutputFormat::Raw),
+            _ => Err(BuildError::InvalidOption(
+                "--output-format".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// Compression of the extra copy of the object file written by `--compress`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "o.gz",
+            Compression::Zstd => "o.zst",
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(BuildError::InvalidOption(
+                "--compress".to_string(),
+                s.to_string(),
+            )),
+        }
+    }
+}
+
+/// Optimization settings of the cargo profile tried by `--profile-size`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeProfile {
+    pub name: &'static str,
+    pub opt_level: &'static str,
+    pub lto: bool,
+}
+
+pub const SIZE_PROFILES: [SizeProfile; 4] = [
+    SizeProfile {
+        name: "z",
+        opt_level: "z",
+        lto: false,
+    },
+    SizeProfile {
+        name: "z-lto",
+        opt_level: "z",
+        lto: true,
+    },
+    SizeProfile {
+        name: "s",
+        opt_level: "s",
+        lto: false,
+    },
+    SizeProfile {
+        name: "s-lto",
+        opt_level: "s",
+        lto: true,
+    },
+];
+
+impl SizeProfile {
+    /// Where `--profile-size` writes the files built with this profile
+    fn dir(&self, bin_dir: &Path) -> PathBuf {
+        bin_dir.join("size-profiles").join(self.name)
+    }
+}
+
+impl std::fmt::Display for SizeProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "opt-level={}", self.opt_level)?;
+        if self.lto {
+            write!(f, ", lto")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for SizeProfile {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SIZE_PROFILES
+            .into_iter()
+            .find(|profile| profile.name == s)
+            .ok_or_else(|| BuildError::InvalidOption("--size-profile".to_string(), s.to_string()))
+    }
+}
+
+/// Options of `cargo l1x build` that are consumed by the tool and not forwarded to cargo
+#[derive(Debug, Default, Clone)]
+pub struct BuildOptions {
+    /// Do not run llvm-strip on the object file
+    pub no_strip: bool,
+    /// Do not pass `-s` to the linker when building the wasm
+    pub no_link_strip: bool,
+    /// Build the example contracts together with the library
+    pub examples: bool,
+    /// Keep an unstripped copy of the object file as `<name>.debug.o`
+    pub split_debug: bool,
+    /// Fail if the objects would be built for a different runtime version
+    pub expect_runtime: Option<i64>,
+    /// Only check whether the object files are older than the sources, don't build
+    pub check_stale: bool,
+    /// Rendering of cargo diagnostics and of the tool messages
+    pub message_format: Option<MessageFormat>,
+    /// Glob patterns selecting the workspace members to build by their directory
+    pub members: Vec<String>,
+    /// Pad the object file with zeros up to a multiple of this many bytes
+    pub pad_to: Option<u64>,
+    /// BPF target features passed to llc as `-mattr`, e.g. `+alu32,-dwarfris`
+    pub mattr: Option<String>,
+    /// eBPF stack size in bytes, [`BuildConfig::stack_frame_size`] by default
+    pub stack_size: Option<u32>,
+    /// BPF processor passed to llc as `-mcpu`, see [`BuildOptions::cpu`] for the default
+    pub cpu: Option<String>,
+    /// Directory for the generated files, `<target-dir>/<output_subdir>` by default
+    pub out_dir: Option<PathBuf>,
+    /// Subdirectory of the target directory for the generated files, [`DEFAULT_OUTPUT_SUBDIR`]
+    /// by default. Not a command line option, set by [`build_into`]
+    pub output_subdir: Option<PathBuf>,
+    /// The cargo `--profile` of the wasm build, `--release` when `None`. Taken from the
+    /// cargo args by [`normalize_profile_args`]
+    pub cargo_profile: Option<String>,
+    /// Stop after generating the `.versioned.ll` file, llc and llvm-strip are not needed
+    pub skip_llc: bool,
+    /// Write the borsh layout of the contract method arguments and return values to `<name>.borsh.json`
+    pub borsh_schema: bool,
+    /// Run the whole pipeline in a container with a pinned toolchain
+    pub container: bool,
+    /// The image used by `--container`, defaults to [`default_container_image`]
+    pub container_image: Option<String>,
+    /// Also write a compressed copy of the object file
+    pub compress: Option<Compression>,
+    /// Only keep the compressed copy of the object file
+    pub compress_only: bool,
+    /// Only build the workspace members with files changed since this git ref
+    pub since: Option<String>,
+    /// The target triple of the wasm build, defaults to [`DEFAULT_WASM_TARGET`]
+    pub wasm_target: Option<String>,
+    /// Compile the translated `.ll` file without the `_version` section
+    pub no_version_info: bool,
+    /// Skip the validation of the final object file with the `object` crate, see `--check-elf`
+    pub no_check_elf: bool,
+    /// The runtime version of the nodes the contracts are deployed to, selects the BPF processor
+    pub runtime_version: Option<i64>,
+    /// Fail the wasm build on compiler warnings
+    pub deny_warnings: bool,
+    /// Key/value pairs written to the `_meta` section of the object file
+    pub meta: Vec<(String, String)>,
+    /// The strip tool used instead of `llvm-strip`
+    pub strip_binary: Option<String>,
+    /// Write the llc and strip commands run by the build to `l1x-commands.json`
+    pub emit_commands: bool,
+    /// Also write `<name>.stable.ll` without machine specific paths, for diffing
+    pub emit_ll_stable: bool,
+    /// Build with each of the [`SIZE_PROFILES`] and compare the object sizes
+    pub profile_size: bool,
+    /// Build with these optimization settings, or keep this build with `--profile-size`
+    pub size_profile: Option<SizeProfile>,
+    /// Fail when an object imports a function that isn't a known host function
+    pub strict_imports: bool,
+    /// Names of the `l1x.toml` profiles to build, each produces its own suffixed files
+    pub configs: Vec<String>,
+    /// The profile these options were resolved from, suffixes the generated files.
+    /// Not a command line option, set for each of the `configs`
+    pub profile_name: Option<String>,
+    /// Refuse to build when the git working tree has uncommitted changes
+    pub require_clean: bool,
+    /// Build anyway when `--require-clean` finds uncommitted changes
+    pub allow_dirty: bool,
+    /// Run cargo without the rust and cargo variables of the environment, see
+    /// [`HERMETIC_CLEARED_ENV`]
+    pub hermetic: bool,
+    /// Fail before processing when the build produces more wasm modules
+    pub max_artifacts: Option<usize>,
+    /// Build the remaining contracts when one fails, and report all failures at the end
+    pub keep_going: bool,
+    /// Don't print the progress of the build, only a final report of the contracts
+    pub summary_only: bool,
+    /// Fail after the build when the compiler emitted warnings, counted from its messages
+    pub fail_on_warning: bool,
+    /// Only list the artifacts of previous builds, don't build
+    pub list_artifacts: bool,
+    /// Only print the llc command line that would compile this `.ll` file, don't build
+    pub print_llc_command: Option<PathBuf>,
+    /// Only write a shell script running the steps of the build to this file, don't build
+    pub emit_script: Option<PathBuf>,
+    /// Only write the ABI of the contracts, read from their sources, don't build
+    pub emit_abi_only: bool,
+    /// Only print the settings of the build once all their sources are merged, don't build
+    pub dump_config: bool,
+    /// File receiving a timestamped record of the commands run and of their output
+    pub log_file: Option<PathBuf>,
+    /// Fail instead of warning when a built crate has no `#[contract]` impl
+    pub strict: bool,
+    /// Run llc again up to this many times when it crashes, compile errors aren't retried
+    pub retry_on_llc_crash: Option<u32>,
+    /// List the largest symbols of each object file after building it
+    pub explain_size: bool,
+    /// Fail when an object file has a section outside of [`KNOWN_SECTIONS`]
+    pub deny_unknown_sections: bool,
+    /// Build twice in separate target directories and fail when the outputs differ
+    pub assert_deterministic: bool,
+    /// The type of the `#[contract]` impl the ABI is read from, when a crate has several
+    pub contract_type: Option<String>,
+    /// Remove the output directory before building
+    pub clean_first: bool,
+    /// Also run `cargo clean` for the wasm target before building, implies `clean_first`
+    pub clean_cargo: bool,
+    /// A reference object file the built one is compared to, the build fails when they differ
+    pub compare_to: Option<PathBuf>,
+    /// Also write the sections of the object file as a raw binary with [`OutputFormat::Raw`]
+    pub output_format: OutputFormat,
+    /// The opened `log_file`, set by the build
+    pub(crate) log: Option<BuildLog>,
+    /// Receives the progress of the build, renders it to the terminal and passes it to the
+    /// sink of [`build_with_events`]
+    pub(crate) events: Option<EventSink>,
+}
+
+impl BuildOptions {
+    /// Extracts the tool options from `args`, leaving only the arguments for cargo
+    pub fn from_args(args: &mut Vec<String>) -> Result<Self, BuildError> {
+        Ok(Self {
+            no_strip: take_no_strip(args)?,
+            no_link_strip: take_flag(args, "--no-link-strip")?,
+            examples: take_flag(args, "--examples")?,
+            split_debug: take_flag(args, "--split-debug")?,
+            expect_runtime: parse_value(args, "--expect-runtime")?,
+            check_stale: take_flag(args, "--check-stale")?,
+            message_format: parse_value(args, "--message-format")?,
+            members: take_values(args, "--members")?,
+            pad_to: parse_value(args, "--pad-to")?,
+            mattr: take_value(args, "--mattr")?,
+            stack_size: parse_value(args, "--stack-size")?,
+            cpu: take_value(args, "--cpu")?,
+            out_dir: parse_value(args, "--out-dir")?,
+            output_subdir: None,
+            cargo_profile: None,
+            skip_llc: take_flag(args, "--skip-llc")?,
+            keep_going: take_flag(args, "--keep-going")?,
+            summary_only: take_flag(args, "--summary-only")?,
+            fail_on_warning: take_flag(args, "--fail-on-warning")?,
+            max_artifacts: parse_value(args, "--max-artifacts")?,
+            strict_imports: take_flag(args, "--strict-imports")?,
+            configs: take_profile_names(args),
+            profile_name: None,
+            require_clean: take_flag(args, "--require-clean")?,
+            allow_dirty: take_flag(args, "--allow-dirty")?,
+            hermetic: take_flag(args, "--hermetic")?,
+            emit_ll_stable: take_flag(args, "--emit-ll-stable")?,
+            emit_commands: take_flag(args, "--emit-commands")?,
+            strip_binary: take_value(args, "--strip-binary")?,
+            meta: take_values(args, "--meta")?
+                .iter()
+                .map(|entry| parse_meta(entry))
+                .collect::<Result<_, _>>()?,
+            profile_size: take_flag(args, "--profile-size")?,
+            size_profile: parse_value(args, "--size-profile")?,
+            deny_warnings: take_flag(args, "--deny-warnings")?,
+            runtime_version: parse_value(args, "--runtime-version")?,
+            no_version_info: take_flag(args, "--no-version-info")?,
+            // the check is on by default, `--check-elf` turns it back on
+            no_check_elf: take_flag(args, "--no-check-elf")?,
+            wasm_target: take_value(args, "--wasm-target")?,
+            since: take_value(args, "--since")?,
+            compress: parse_value(args, "--compress")?,
+            compress_only: take_flag(args, "--compress-only")?,
+            container: take_flag(args, "--container")?,
+            container_image: take_value(args, "--container-image")?,
+            borsh_schema: take_flag(args, "--borsh-schema")?,
+            list_artifacts: take_flag(args, "--list-artifacts")?,
+            print_llc_command: take_value(args, "--print-llc-command")?.map(PathBuf::from),
+            emit_script: take_value(args, "--emit-script")?.map(PathBuf::from),
+            emit_abi_only: take_flag(args, "--emit-abi-only")?,
+            dump_config: take_flag(args, "--dump-config")?,
+            log_file: take_value(args, "--log-file")?.map(PathBuf::from),
+            strict: take_flag(args, "--strict")?,
+            retry_on_llc_crash: parse_value(args, "--retry-on-llc-crash")?,
+            explain_size: take_flag(args, "--explain-size")?,
+            deny_unknown_sections: take_flag(args, "--deny-unknown-sections")?,
+            assert_deterministic: take_flag(args, "--assert-deterministic")?,
+            contract_type: take_value(args, "--contract-type")?,
+            clean_first: take_flag(args, "--clean-first")?,
+            clean_cargo: take_flag(args, "--clean-cargo")?,
+            compare_to: take_value(args, "--compare-to")?.map(PathBuf::from),
+            output_format: parse_value(args, "--output-format")?.unwrap_or_default(),
+            log: None,
+            events: None,
+        })
+    }
+
+    pub fn wasm_target(&self) -> &str {
+        self.wasm_target.as_deref().unwrap_or(DEFAULT_WASM_TARGET)
+    }
+
+    /// The runtime the objects are built for: `--runtime-version`, else the expected
+    /// runtime version of the `config`
+    pub fn runtime_version(&self, config: &BuildConfig) -> i64 {
+        self.runtime_version
+            .unwrap_or(config.expected_runtime_version)
+    }
+
+    /// The BPF processor passed to llc: `--cpu`, else the newest one supported by
+    /// [`runtime_version`](Self::runtime_version), else [`DEFAULT_BPF_CPU`]
+    pub fn cpu(&self, config: &BuildConfig) -> &str {
+        match &self.cpu {
+            Some(cpu) => cpu,
+            None => runtime_bpf_cpu(self.runtime_version(config)).unwrap_or(DEFAULT_BPF_CPU),
+        }
+    }
+
+    /// Prints a status `message` and records it in the `--log-file`
+    fn status(&self, message: &str) {
+        println!("{}", message);
+        if let Some(log) = &self.log {
+            log.record(message);
+        }
+    }
+
+    /// Prints a warning and records it in the `--log-file`
+    fn warn(&self, message: &str) {
+        eprintln!("⚠️  {}", message);
+        if let Some(log) = &self.log {
+            log.record(&format!("warning: {}", message));
+        }
+    }
+
+    /// Reports a step of the build to the terminal and the sink of [`build_with_events`]
+    fn emit(&self, event: BuildEvent) {
+        if let Some(events) = &self.events {
+            events.emit(event);
+        }
+    }
+
+    /// `true` for the modes that only report something and don't build the contracts
+    pub fn skips_build(&self) -> bool {
+        self.check_stale
+            || self.list_artifacts
+            || self.print_llc_command.is_some()
+            || self.emit_script.is_some()
+            || self.emit_abi_only
+            || self.dump_config
+    }
+
+    /// Directory where the `.ll` and `.o` files are written, `l1x/<profile>` in the target
+    /// directory by default
+    pub fn bin_dir(&self, target_dir: &Path) -> PathBuf {
+        self.out_dir
+            .clone()
+            .unwrap_or_else(|| match (&self.output_subdir, &self.cargo_profile) {
+                (Some(output_subdir), _) => target_dir.join(output_subdir),
+                (None, Some(profile)) => target_dir.join("l1x").join(profile),
+                (None, None) => target_dir.join(DEFAULT_OUTPUT_SUBDIR),
+            })
+    }
+
+    /// Checks the option values before anything is built
+    pub fn validate(&self, config: &BuildConfig) -> Result<(), BuildError> {
+        self.check_runtime_version(config)?;
+        if self.pad_to == Some(0) {
+            return Err(BuildError::InvalidOption(
+                "--pad-to".to_string(),
+                "0".to_string(),
+            ));
+        }
+        if let Some(mattr) = &self.mattr {
+            validate_mattr(mattr)?;
+        }
+        if self.stack_size == Some(0) {
+            return Err(BuildError::InvalidOption(
+                "--stack-size".to_string(),
+                "0".to_string(),
+            ));
+        }
+        if self.profile_size && (self.skip_llc || self.compress_only) {
+            // the object files are compared
+            let option = if self.skip_llc {
+                "--skip-llc"
+            } else {
+                "--compress-only"
+            };
+            return Err(BuildError::ConflictingOptions(
+                "--profile-size".to_string(),
+                option.to_string(),
+            ));
+        }
+        if self.assert_deterministic && (self.profile_size || !self.configs.is_empty()) {
+            // a single build configuration is compared
+            let option = if self.profile_size {
+                "--profile-size"
+            } else {
+                "--config"
+            };
+            return Err(BuildError::ConflictingOptions(
+                "--assert-deterministic".to_string(),
+                option.to_string(),
+            ));
+        }
+        if self.profile_size && !self.configs.is_empty() {
+            return Err(BuildError::ConflictingOptions(
+                "--profile-size".to_string(),
+                "--config".to_string(),
+            ));
+        }
+        if self.emit_script.is_some() && (self.profile_size || self.container) {
+            // the script runs a single build on the host
+            let option = if self.profile_size {
+                "--profile-size"
+            } else {
+                "--container"
+            };
+            return Err(BuildError::ConflictingOptions(
+                "--emit-script".to_string(),
+                option.to_string(),
+            ));
+        }
+        if self.emit_abi_only && (self.emit_script.is_some() || self.profile_size) {
+            // nothing is compiled
+            let option = if self.profile_size {
+                "--profile-size"
+            } else {
+                "--emit-script"
+            };
+            return Err(BuildError::ConflictingOptions(
+                "--emit-abi-only".to_string(),
+                option.to_string(),
+            ));
+        }
+        self.validate_meta()?;
+        if self.log_file.is_some() && self.container {
+            // the build in the container has no access to the file
+            return Err(BuildError::ConflictingOptions(
+                "--log-file".to_string(),
+                "--container".to_string(),
+            ));
+        }
+        if self.output_format == OutputFormat::Raw && self.skip_llc {
+            // there's no object file to read the sections of
+            return Err(BuildError::ConflictingOptions(
+                "--output-format raw".to_string(),
+                "--skip-llc".to_string(),
+            ));
+        }
+        if self.compare_to.is_some() {
+            // a single object file is compared
+            let option = [
+                (self.skip_llc, "--skip-llc"),
+                (self.compress_only, "--compress-only"),
+                (self.profile_size, "--profile-size"),
+                (self.assert_deterministic, "--assert-deterministic"),
+                (!self.configs.is_empty(), "--config"),
+            ]
+            .into_iter()
+            .find_map(|(set, option)| set.then_some(option));
+            if let Some(option) = option {
+                return Err(BuildError::ConflictingOptions(
+                    "--compare-to".to_string(),
+                    option.to_string(),
+                ));
+            }
+        }
+        if self.explain_size && (self.skip_llc || self.summary_only) {
+            // there's no object file, or no per contract output
+            let option = if self.skip_llc {
+                "--skip-llc"
+            } else {
+                "--summary-only"
+            };
+            return Err(BuildError::ConflictingOptions(
+                "--explain-size".to_string(),
+                option.to_string(),
+            ));
+        }
+        if self.compress_only && self.compress.is_none() {
+            // there's no compression to default to
+            return Err(BuildError::MissingOption(
+                "--compress-only".to_string(),
+                "--compress <gzip|zstd>".to_string(),
+            ));
+        }
+        if let Some(wasm_target) = &self.wasm_target {
+            if !wasm_target.starts_with("wasm32") && !wasm_target.starts_with("wasm64") {
+                return Err(BuildError::InvalidOption(
+                    "--wasm-target".to_string(),
+                    wasm_target.clone(),
+                ));
+            }
+        }
+        if let Some(cpu) = &self.cpu {
+            if !BPF_CPUS.contains(&cpu.as_str()) {
+                return Err(BuildError::InvalidOption("--cpu".to_string(), cpu.clone()));
+            }
+        }
+        if self.cpu(config) == "probe" {
+            // the processor of the build host says nothing about the runtime
+            if self.runtime_version.is_some() {
+                return Err(BuildError::ConflictingOptions(
+                    "--cpu probe".to_string(),
+                    "--runtime-version".to_string(),
+                ));
+            }
+            return Ok(());
+        }
+        let runtime_version = self.runtime_version(config);
+        let supported =
+            runtime_bpf_cpu(runtime_version).ok_or_else(|| match self.runtime_version {
+                Some(_) => BuildError::InvalidOption(
+                    "--runtime-version".to_string(),
+                    runtime_version.to_string(),
+                ),
+                None => BuildError::InvalidOption(
+                    "expected-runtime-version".to_string(),
+                    runtime_version.to_string(),
+                ),
+            })?;
+        let alu32 = self
+            .mattr
+            .as_deref()
+            .is_some_and(|mattr| mattr.split(',').any(|feature| feature == "+alu32"));
+        let required = if alu32 {
+            3
+        } else {
+            bpf_cpu_level(self.cpu(config))
+        };
+        if required > bpf_cpu_level(supported) {
+            return Err(BuildError::CpuNotSupportedByRuntime {
+                cpu: self.cpu(config).to_string(),
+                runtime_version,
+                supported,
+            });
+        }
+        Ok(())
+    }
+
+    /// The `--meta` keys are unique and there's a `_meta` section to write them to
+    fn validate_meta(&self) -> Result<(), BuildError> {
+        if self.meta.is_empty() {
+            return Ok(());
+        }
+        if self.no_version_info {
+            // the translation is compiled as is
+            return Err(BuildError::ConflictingOptions(
+                "--meta".to_string(),
+                "--no-version-info".to_string(),
+            ));
+        }
+        let mut keys = BTreeSet::new();
+        if let Some((key, _)) = self.meta.iter().find(|(key, _)| !keys.insert(key)) {
+            return Err(BuildError::InvalidOption(
+                "--meta".to_string(),
+                format!("{} (duplicate key)", key),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_runtime_version(&self, config: &BuildConfig) -> Result<(), BuildError> {
+        match self.expect_runtime {
+            Some(version) if version != config.expected_runtime_version => Err(
+                BuildError::RuntimeVersionMismatch(version, config.expected_runtime_version),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The wasm target the contracts are built for unless `--wasm-target` is given
+pub const DEFAULT_WASM_TARGET: &str = "wasm32-unknown-unknown";
+
+/// Wasm targets known to produce modules the translation to LLVM IR accepts
+const KNOWN_WASM_TARGETS: [&str; 4] = [
+    DEFAULT_WASM_TARGET,
+    "wasm32-wasip1",
+    "wasm32-wasip2",
+    "wasm32v1-none",
+];
+
+/// BPF processors supported by llc, see the documentation of `compile_to_object`
+const BPF_CPUS: [&str; 5] = ["generic", "probe", "v1", "v2", "v3"];
+pub const DEFAULT_BPF_CPU: &str = "v3";
+
+/// The runtime version from which the runtime executes each BPF processor. The runtime
+/// doesn't document its processors, this is an assumption of cargo-l1x: runtime version
+/// `N` executes `vN`, matching [`EXPECTED_RUNTIME_VERSION`] and [`DEFAULT_BPF_CPU`]. `v2`
+/// adds the extended jumps and `v3` the jmp32 and alu32 instructions
+const RUNTIME_BPF_CPUS: [(i64, &str); 3] = [(1, "v1"), (2, "v2"), (3, "v3")];
+
+/// The newest BPF processor supported by `runtime_version`, `None` for versions older
+/// than the first runtime. Newer runtimes execute the processors of the older ones
+fn runtime_bpf_cpu(runtime_version: i64) -> Option<&'static str> {
+    RUNTIME_BPF_CPUS
+        .iter()
+        .rev()
+        .find(|(version, _)| *version <= runtime_version)
+        .map(|(_, cpu)| *cpu)
+}
+
+/// `generic` is `v1`. `probe` isn't known before llc asks the kernel of the build host,
+/// not of the nodes, so it's never compared
+fn bpf_cpu_level(cpu: &str) -> u8 {
+    match cpu {
+        "v3" => 3,
+        "v2" => 2,
+        _ => 1,
+    }
+}
+
+/// BPF target features supported by llc, see the documentation of `compile_to_object`
+const BPF_FEATURES: [&str; 2] = ["alu32", "dwarfris"];
+
+/// Checks that every feature of a `+feat,-feat` list is a known BPF feature
+fn validate_mattr(mattr: &str) -> Result<(), BuildError> {
+    for feature in mattr.split(',') {
+        let name = feature
+            .strip_prefix('+')
+            .or_else(|| feature.strip_prefix('-'))
+            .ok_or_else(|| BuildError::InvalidOption("--mattr".to_string(), feature.to_string()))?;
+        if !BPF_FEATURES.contains(&name) {
+            return Err(BuildError::UnknownBpfFeature(name.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Removes all occurrences of `option` and their values from `args`.
+/// Both `--option value` and `--option=value` are accepted
+fn take_values(args: &mut Vec<String>, option: &str) -> Result<Vec<String>, BuildError> {
+    let prefix = format!("{}=", option);
+    let mut values = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == option {
+            if i + 1 >= args.len() {
+                return Err(BuildError::InvalidOption(option.to_string(), String::new()));
+            }
+            values.push(args.remove(i + 1));
+            args.remove(i);
+        } else if let Some(v) = args[i].strip_prefix(&prefix) {
+            values.push(v.to_string());
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    Ok(values)
+}
+
+/// Removes the `--config <name>` options naming `l1x.toml` profiles from `args`. Cargo's
+/// own `--config key=value` and `--config file.toml` are left for cargo, with duplicates
+/// of a name only built once
+fn take_profile_names(args: &mut Vec<String>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let name = if args[i] == "--config" && args.get(i + 1).is_some_and(|v| is_profile_name(v)) {
+            args.remove(i);
+            args.remove(i)
+        } else if let Some(name) = args[i]
+            .strip_prefix("--config=")
+            .filter(|name| is_profile_name(name))
+        {
+            let name = name.to_string();
+            args.remove(i);
+            name
+        } else {
+            i += 1;
+            continue;
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// The build flags whose default the CLI reads from their `L1X_*` environment variable,
+/// see [`env_name`]
+pub const ENV_FLAGS: &[&str] = &[
+    "--no-strip",
+    "--no-link-strip",
+    "--examples",
+    "--split-debug",
+    "--skip-llc",
+    "--keep-going",
+    "--summary-only",
+    "--fail-on-warning",
+    "--strict-imports",
+    "--require-clean",
+    "--allow-dirty",
+    "--hermetic",
+    "--emit-ll-stable",
+    "--emit-commands",
+    "--profile-size",
+    "--deny-warnings",
+    "--no-version-info",
+    "--no-check-elf",
+    "--compress-only",
+    "--container",
+    "--borsh-schema",
+    "--emit-abi-only",
+    "--strict",
+    "--explain-size",
+    "--deny-unknown-sections",
+    "--assert-deterministic",
+    "--clean-first",
+    "--clean-cargo",
+];
+
+/// The build options with a value whose default the CLI reads from their `L1X_*`
+/// environment variable, see [`env_name`]
+pub const ENV_OPTIONS: &[&str] = &[
+    "--strip-mode",
+    "--expect-runtime",
+    "--message-format",
+    "--pad-to",
+    "--mattr",
+    "--stack-size",
+    "--cpu",
+    "--out-dir",
+    "--max-artifacts",
+    "--strip-binary",
+    "--size-profile",
+    "--runtime-version",
+    "--wasm-target",
+    "--since",
+    "--compress",
+    "--container-image",
+    "--retry-on-llc-crash",
+    "--output-format",
+];
+
+/// Environment variable providing the default of `option`, e.g. `--no-strip` -> `L1X_NO_STRIP`
+pub fn env_name(option: &str) -> String {
+    format!(
+        "L1X_{}",
+        option
+            .trim_start_matches('-')
+            .replace('-', "_")
+            .to_uppercase()
+    )
+}
+
+/// Same as [`take_value`] but also parses the value
+fn parse_value<T: std::str::FromStr>(
+    args: &mut Vec<String>,
+    option: &str,
+) -> Result<Option<T>, BuildError> {
+    take_value(args, option)?
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| BuildError::InvalidOption(option.to_string(), value))
+        })
+        .transpose()
+}
+
+/// Removes `--strip-mode` and `--no-strip`, its deprecated spelling for `none`, from
+/// `args`. The last of them wins, e.g. `--strip-mode full` overrides `L1X_NO_STRIP`
+fn take_no_strip(args: &mut Vec<String>) -> Result<bool, BuildError> {
+    let is_mode = |arg: &String| arg == "--strip-mode" || arg.starts_with("--strip-mode=");
+    let is_flag =
+        |arg: &String| arg == "--no-strip" || arg == "--strip" || arg.starts_with("--no-strip=");
+    let mode_last = args.iter().rposition(is_mode) > args.iter().rposition(is_flag);
+    let no_strip = take_flag(args, "--no-strip")?;
+    match parse_value::<StripMode>(args, "--strip-mode")? {
+        Some(mode) if mode_last => Ok(mode == StripMode::None),
+        _ => Ok(no_strip),
+    }
+}
+
+/// Same as [`take_values`] but only the last value is kept
+fn take_value(args: &mut Vec<String>, option: &str) -> Result<Option<String>, BuildError> {
+    Ok(take_values(args, option)?.pop())
+}
+
+/// Removes every occurrence of `flag` from `args`, with its `--flag=true|false` and
+/// negated forms: `--no-<flag>`, or `--<flag>` for the flags starting with `--no-`.
+/// The last one wins, `false` without any
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Result<bool, BuildError> {
+    let negated = match flag.strip_prefix("--no-") {
+        Some(name) => format!("--{}", name),
+        None => format!("--no-{}", flag.trim_start_matches('-')),
+    };
+    let prefix = format!("{}=", flag);
+    let mut enabled = false;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == flag {
+            enabled = true;
+        } else if *arg == negated {
+            enabled = false;
+        } else if let Some(value) = arg.strip_prefix(&prefix) {
+            enabled = match value {
+                "true" | "1" => true,
+                "false" | "0" => false,
+                _ => {
+                    return Err(BuildError::InvalidOption(
+                        flag.to_string(),
+                        value.to_string(),
+                    ))
+                }
+            };
+        } else {
+            i += 1;
+            continue;
+        }
+        args.remove(i);
+    }
+    Ok(enabled)
+}
+
+/// Runs the backend on the translated `.ll` file, the llc and strip commands are
+/// appended to `commands`
+pub fn build_ebpf<P: AsRef<Path> + Clone>(
+    path: P,
+    options: &BuildOptions,
+    config: &BuildConfig,
+    commands: &mut Vec<CommandRecord>,
+) -> Result<BackendOutput, BuildError> {
+    let target_file = path.as_ref().with_extension("o");
+
+    if options.skip_llc {
+        // Only the front half of the pipeline runs without LLVM
+        llc_input(path.as_ref(), options, config)?;
+        return Ok(BackendOutput::default());
+    }
+
+    // Compile the LLVM IR to the target file
+    let mut retries = options.retry_on_llc_crash.unwrap_or(0);
+    loop {
+        // the versioned file is written again for each attempt, not appended to
+        let llc_input = llc_input(path.as_ref(), options, config)?;
+        options.emit(BuildEvent::CompilingObject {
+            ll: llc_input.clone(),
+        });
+        match compile_to_object(&llc_input, &target_file, options, config) {
+            Ok(record) => {
+                commands.push(record);
+                break;
+            }
+            Err(BuildError::ObjectBuildError { exit_code, stderr })
+                if retries > 0 && is_llc_crash(exit_code, &stderr) =>
+            {
+                retries -= 1;
+                options.warn(&format!(
+                    "llc crashed compiling {}, retrying ({} retries left)",
+                    llc_input.display(),
+                    retries
+                ));
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    // the strip removes the local functions
+    let symbol_sizes = options
+        .explain_size
+        .then(|| object_symbol_sizes(&target_file))
+        .transpose()?;
+
+    if !options.no_strip {
+        if options.split_debug {
+            // Keep the unstripped object next to the deployable one
+            let debug_file = path.as_ref().with_extension("debug.o");
+            std::fs::copy(&target_file, debug_file)
+                .map_err(io_error("Failed to copy object file", &target_file))?;
+        }
+
+        // Strip the target file
+        options.emit(BuildEvent::Stripping {
+            object: target_file.clone(),
+        });
+        commands.push(strip_object_file(&target_file, options)?);
+    }
+
+    let padded = options
+        .pad_to
+        .map(|block_size| pad_object_file(&target_file, block_size))
+        .transpose()?;
+    Ok(BackendOutput {
+        padded,
+        symbol_sizes,
+    })
+}
+
+/// The symbols of the object file by size, see [`symbol_sizes`]
+fn object_symbol_sizes(object: &Path) -> Result<Vec<SymbolSize>, BuildError> {
+    let data = fs::read(object).map_err(io_error("Could not read object file", object))?;
+    symbol_sizes(&data).map_err(|e| BuildError::ObjectBuildError {
+        exit_code: None,
+        stderr: format!("{:?} is not a valid object file: {}", object, e),
+    })
+}
+
+/// Translates the wasm module `wasm` to the LLVM IR file `ll`
+fn translate_module(wasm: &Path, ll: &Path) -> Result<(), BuildError> {
+    translate_module_to_file_by_path(&wasm.to_path_buf(), &ll.to_path_buf()).map_err(|e| {
+        BuildError::LlBuildError {
+            wasm: wasm.to_path_buf(),
+            message: format!("{:#}", e),
+        }
+    })
+}
+
+/// `cargo l1x translate`: translates `wasm` to the LLVM IR file `ll` and writes the llc
+/// input of a build next to it, with the `_version` globals of `config` and the `--meta`
+/// entries. Without `config` the translation is the llc input, like `--no-version-info`.
+/// Returns the llc input, the scripts of `--emit-script` run it for the translation
+pub fn translate(
+    wasm: &Path,
+    ll: &Path,
+    meta: &[String],
+    config: Option<&BuildConfig>,
+) -> Result<PathBuf, BuildError> {
+    let options = BuildOptions {
+        no_version_info: config.is_none(),
+        meta: meta
+            .iter()
+            .map(|entry| parse_meta(entry))
+            .collect::<Result<_, _>>()?,
+        ..Default::default()
+    };
+    options.validate_meta()?;
+    translate_module(wasm, ll)?;
+    llc_input(ll, &options, config.unwrap_or(&BuildConfig::default()))
+}
+
+/// The file llc compiles for the translated `path`: a copy with the `_version` and `_meta`
+/// globals, or `path` itself with `--no-version-info`
+pub(crate) fn llc_input(
+    path: &Path,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<PathBuf, BuildError> {
+    if options.no_version_info {
+        // The raw translation is compiled as is
+        return Ok(path.to_path_buf());
+    }
+    let versioned_file = path.with_extension("versioned.ll");
+
+    // Copy the source file to the versioned file
+    std::fs::copy(path, &versioned_file).map_err(io_error("Failed to copy source file", path))?;
+
+    // Add the version information to the versioned file
+    add_version_info(&versioned_file, config)?;
+    add_meta_info(&versioned_file, &options.meta)?;
+
+    // Fix the versioned file for mac os compatibility
+    fix_version_file(&versioned_file)?;
+    Ok(versioned_file)
+}
+
+/// Whether llc failed by crashing rather than by rejecting its input: killed by a signal,
+/// a shell's `128 + signal` status, a Windows exception status, or LLVM's crash handler
+/// output
+fn is_llc_crash(exit_code: Option<i32>, stderr: &str) -> bool {
+    match exit_code {
+        None => true,
+        Some(code) if code < 0 || (129..=192).contains(&code) => true,
+        // 0 is a successful run whose object file is unusable, not a crash
+        Some(0) => false,
+        Some(_) => stderr.contains("PLEASE submit a bug report") || stderr.contains("Stack dump:"),
+    }
+}
+
+/// Appends zero bytes to the object file up to the next multiple of `block_size`
+fn pad_object_file<P: AsRef<Path>>(
+    target_file: P,
+    block_size: u64,
+) -> Result<PaddedObject, BuildError> {
+    let file = OpenOptions::new()
+        .write(true)
+        .open(target_file.as_ref())
+        .map_err(io_error("Failed to open object file", target_file.as_ref()))?;
+    let size = file
+        .metadata()
+        .map_err(io_error(
+            "Failed to read object file size",
+            target_file.as_ref(),
+        ))?
+        .len();
+    let padded_size = size.div_ceil(block_size) * block_size;
+    file.set_len(padded_size)
+        .map_err(io_error("Failed to pad object file", target_file.as_ref()))?;
+
+    let content = fs::read(target_file.as_ref())
+        .map_err(io_error("Failed to read object file", target_file.as_ref()))?;
+    Ok(PaddedObject {
+        size: padded_size,
+        sha256: format!("{:x}", Sha256::digest(&content)),
+    })
+}
+
+fn add_version_info<P: AsRef<Path>>(
+    versioned_file: P,
+    config: &BuildConfig,
+) -> Result<(), BuildError> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(versioned_file.as_ref())
+        .map_err(io_error(
+            "Failed to open versioned file",
+            versioned_file.as_ref(),
+        ))?;
+
+    for global in version_globals(config) {
+        writeln!(file, "{}", global).map_err(io_error(
+            "Failed to write version info",
+            versioned_file.as_ref(),
+        ))?;
+    }
+    Ok(())
+}
+
+/// The `_version` section globals appended to the translated module
+pub(crate) fn version_globals(config: &BuildConfig) -> [String; 2] {
+    [
+        format!(
+            "@_OBJECT_VERSION = global i64 {}, section \"_version\", align 1",
+            config.object_file_version
+        ),
+        format!(
+            "@_EXPECTED_RUNTIME_VERSION = global i64 {}, section \"_version\", align 1",
+            config.expected_runtime_version
+        ),
+    ]
+}
+
+/// Prefix of the symbols of the `--meta` entries in the `_meta` section
+pub const META_SYMBOL_PREFIX: &str = "_meta.";
+
+/// Appends a `@"_meta.<key>"` byte string global in the `_meta` section per entry.
+/// The runtime ignores the sections it doesn't know, the entries don't affect execution
+fn add_meta_info<P: AsRef<Path>>(
+    versioned_file: P,
+    meta: &[(String, String)],
+) -> Result<(), BuildError> {
+    if meta.is_empty() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(versioned_file.as_ref())
+        .map_err(io_error(
+            "Failed to open versioned file",
+            versioned_file.as_ref(),
+        ))?;
+
+    for (key, value) in meta {
+        writeln!(file, "{}", meta_global(key, value)).map_err(io_error(
+            "Failed to write meta info",
+            versioned_file.as_ref(),
+        ))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn meta_global(key: &str, value: &str) -> String {
+    let escaped: String = value
+        .bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => (byte as char).to_string(),
+            _ => format!("\\{:02X}", byte),
+        })
+        .collect();
+    format!(
+        "@\"{}{}\" = constant [{} x i8] c\"{}\", section \"_meta\", align 1",
+        META_SYMBOL_PREFIX,
+        key,
+        value.len(),
+        escaped
+    )
+}
+
+/// Parses a `--meta key=value` entry, keys are made of ASCII letters, digits, `_`, `-` and `.`
+fn parse_meta(entry: &str) -> Result<(String, String), BuildError> {
+    match entry.split_once('=') {
+        Some((key, value))
+            if !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) =>
+        {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(BuildError::InvalidOption(
+            "--meta".to_string(),
+            entry.to_string(),
+        )),
+    }
+}
+
+pub fn fix_version_file<P: AsRef<Path>>(versioned_file: P) -> Result<(), BuildError> {
+    let mut content = fs::read_to_string(versioned_file.as_ref()).map_err(io_error(
+        "Failed to read version file",
+        versioned_file.as_ref(),
+    ))?;
+
+    // MAC OS: wasm-llvmir tool adds a comma before a section name for unknown reason.
+    // This is a workaround until it's fixed in wasm-llvmir
+    content = content.replace("section \",_memory\"", "section \"_memory\"");
+    content = content.replace("section \",_init_memory\"", "section \"_init_memory\"");
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(versioned_file.as_ref())
+        .map_err(io_error(
+            "Failed to open version file",
+            versioned_file.as_ref(),
+        ))?;
+
+    file.write_all(content.as_bytes()).map_err(io_error(
+        "Failed to write to version file",
+        versioned_file.as_ref(),
+    ))?;
+
+    Ok(())
+}
+
+///  $ llc-17 -march=bpf -mattr=help
+///  Available CPUs for this target:
+///
+///    generic - Select the generic processor.
+///    probe   - Select the probe processor.
+///    v1      - Select the v1 processor.
+///    v2      - Select the v2 processor.
+///    v3      - Select the v3 processor.
+///
+///  Available features for this target:
+///
+///    alu32    - Enable ALU32 instructions.
+///    dummy    - unused feature.
+///    dwarfris - Disable MCAsmInfo DwarfUsesRelocationsAcrossSections.
+///
+/// Use +feature to enable a feature, or -feature to disable it.
+/// For example, llc -mcpu=mycpu -mattr=+feature1,-feature2
+///
+/// https://chromium.googlesource.com/external/github.com/llvm/llvm-project/+/refs/heads/upstream/release/17.x/llvm/lib/Target/BPF/BPFSubtarget.cpp
+///   if (CPU == "v3") {
+///    HasJmpExt = true;
+///    HasJmp32 = true;
+///    HasAlu32 = true;
+///    return;
+///  }
+fn compile_to_object<P: AsRef<Path>>(
+    input_file: P,
+    output_file: P,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<CommandRecord, BuildError> {
+    let input_path = input_file.as_ref().to_path_buf();
+    let output_path = output_file.as_ref().to_path_buf();
+    let mut llc = llc_command(input_file, output_file, options, config)?;
+    let record = CommandRecord::new(&llc, &input_path, &output_path);
+    let output = llc.output().map_err(|e| BuildError::LlcRunError {
+        tool: Some(llc.get_program().to_string_lossy().into_owned()),
+        source: Some(e),
+    })?;
+    if let Some(log) = &options.log {
+        log.command(&llc, &output);
+    }
+
+    if !output.status.success() {
+        eprintln!(
+            "Error compiling to object file: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(BuildError::ObjectBuildError {
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    // broken LLVM installs may exit successfully without a usable object
+    if let Err(problem) = check_object_file(&output_path) {
+        eprintln!("Error compiling to object file: {}", problem);
+        return Err(BuildError::ObjectBuildError {
+            exit_code: output.status.code(),
+            stderr: problem,
+        });
+    }
+    Ok(record)
+}
+
+/// Warns about the imports of functions the runtime doesn't provide, such a contract
+/// fails to load. They are an error with `--strict-imports`
+fn check_imports(
+    object: &Path,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<(), BuildError> {
+    let imports = unknown_imports(object, &config.host_functions).map_err(|message| {
+        BuildError::ObjectBuildError {
+            exit_code: None,
+            stderr: message,
+        }
+    })?;
+    if imports.is_empty() {
+        return Ok(());
+    }
+    if options.strict_imports {
+        return Err(BuildError::UnknownImports {
+            object: object.to_path_buf(),
+            imports,
+        });
+    }
+    options.warn(&format!(
+        "{} imports functions the runtime doesn't provide, it will fail to load: {}",
+        object.display(),
+        imports.join(", ")
+    ));
+    Ok(())
+}
+
+/// Validates the final object file: a 64-bit ELF for BPF, with the `_version` section and
+/// versions of `config`, and no undefined symbols outside of the runtime's host module.
+/// Unknown host functions are left to [`check_imports`], a newer runtime may provide them
+fn check_elf(
+    object: &Path,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<(), BuildError> {
+    let data = fs::read(object).map_err(io_error("Could not read", object))?;
+    let file = object::File::parse(&*data).map_err(|e| BuildError::InvalidObject {
+        object: object.to_path_buf(),
+        problems: vec![format!("not an object file: {}", e)],
+    })?;
+
+    let mut problems = Vec::new();
+    if !matches!(file, object::File::Elf64(_)) {
+        problems.push("not a 64-bit ELF file".to_string());
+    }
+    if file.architecture() != object::Architecture::Bpf {
+        problems.push(format!(
+            "built for {:?} instead of BPF",
+            file.architecture()
+        ));
+    }
+    if !options.no_version_info {
+        if file.section_by_name("_version").is_none() {
+            problems.push("no _version section".to_string());
+        }
+        let versions = [
+            (
+                "_OBJECT_VERSION",
+                read_version(&file, "_OBJECT_VERSION"),
+                config.object_file_version,
+            ),
+            (
+                "_EXPECTED_RUNTIME_VERSION",
+                read_version(&file, "_EXPECTED_RUNTIME_VERSION"),
+                config.expected_runtime_version,
+            ),
+        ];
+        for (name, found, expected) in versions {
+            match found {
+                None => problems.push(format!("no {} symbol", name)),
+                Some(found) if found != expected => {
+                    problems.push(format!("{} is {} instead of {}", name, found, expected))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    let foreign = foreign_imports(&file);
+    if !foreign.is_empty() {
+        problems.push(format!(
+            "undefined symbols outside of the host module: {}",
+            foreign.join(", ")
+        ));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(BuildError::InvalidObject {
+            object: object.to_path_buf(),
+            problems,
+        })
+    }
+}
+
+/// Sections an object file may have with `--deny-unknown-sections`: the ones llc writes
+/// for BPF, the `_version` and `_meta` sections of the build and the `_memory` and
+/// `_init_memory` sections of the translation. Also allowed with a `.<suffix>`, e.g.
+/// `.rodata.str1.1`, or as the target of a `.rel` or `.rela` relocation section
+pub const KNOWN_SECTIONS: [&str; 16] = [
+    "",
+    ".text",
+    ".data",
+    ".bss",
+    ".rodata",
+    ".symtab",
+    ".strtab",
+    ".shstrtab",
+    ".comment",
+    ".note.GNU-stack",
+    ".llvm_addrsig",
+    ".BTF",
+    "_version",
+    "_meta",
+    "_memory",
+    "_init_memory",
+];
+
+/// Fails on the sections of the object file the runtime may refuse to load, see
+/// [`KNOWN_SECTIONS`]. Debug information sections are allowed, the strip removes them
+fn check_sections(object: &Path) -> Result<(), BuildError> {
+    let data = fs::read(object).map_err(io_error("Could not read", object))?;
+    let file = object::File::parse(&*data).map_err(|e| BuildError::InvalidObject {
+        object: object.to_path_buf(),
+        problems: vec![format!("not an object file: {}", e)],
+    })?;
+    let mut unknown: Vec<&str> = file
+        .sections()
+        .filter_map(|section| section.name().ok())
+        .filter(|name| !is_known_section(name))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort_unstable();
+    unknown.dedup();
+    Err(BuildError::InvalidObject {
+        object: object.to_path_buf(),
+        problems: vec![format!("unknown sections: {}", unknown.join(", "))],
+    })
+}
+
+fn is_known_section(name: &str) -> bool {
+    let name = name
+        .strip_prefix(".rela")
+        .or_else(|| name.strip_prefix(".rel"))
+        .filter(|target| target.starts_with(['.', '_']))
+        .unwrap_or(name);
+    name.starts_with(".debug_")
+        || KNOWN_SECTIONS.iter().any(|known| {
+            name == *known
+                || (!known.is_empty()
+                    && name
+                        .strip_prefix(known)
+                        .is_some_and(|suffix| suffix.starts_with('.')))
+        })
+}
+
+/// The value of an `i64` global of the `_version` section
+fn read_version(file: &object::File, name: &str) -> Option<i64> {
+    let symbol = file.symbols().find(|symbol| symbol.name() == Ok(name))?;
+    let section = file.section_by_index(symbol.section_index()?).ok()?;
+    let data = section.data().ok()?;
+    let offset = usize::try_from(symbol.address().checked_sub(section.address())?).ok()?;
+    let bytes: [u8; 8] = data.get(offset..offset + 8)?.try_into().ok()?;
+    Some(if file.is_little_endian() {
+        i64::from_le_bytes(bytes)
+    } else {
+        i64::from_be_bytes(bytes)
+    })
+}
+
+/// Checks that llc wrote a non-empty ELF object
+fn check_object_file(path: &Path) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| format!("llc did not write {:?}: {}", path, e))?;
+    if data.is_empty() {
+        return Err(format!("llc wrote an empty object file {:?}", path));
+    }
+    match object::File::parse(&*data) {
+        Ok(object::File::Elf64(_)) => Ok(()),
+        Ok(_) => Err(format!("{:?} is not an ELF object file", path)),
+        Err(e) => Err(format!("{:?} is not a valid object file: {}", path, e)),
+    }
+}
+
+/// The llc invocation compiling `input_file` to the eBPF object `output_file`
+pub(crate) fn llc_command<P: AsRef<Path>>(
+    input_file: P,
+    output_file: P,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<Command, BuildError> {
+    let mut llc = Command::new(get_llc_command()?);
+    llc.args([
+        "-march=bpf",
+        &format!("-mcpu={}", options.cpu(config)),
+        "-filetype=obj",
+        "--nozero-initialized-in-bss",
+        "--bpf-stack-size",
+        options
+            .stack_size
+            .unwrap_or(config.stack_frame_size)
+            .to_string()
+            .as_str(),
+    ]);
+    if let Some(mattr) = &options.mattr {
+        llc.arg(format!("-mattr={}", mattr));
+    }
+    llc.arg(input_file.as_ref())
+        .arg("-o")
+        .arg(output_file.as_ref());
+    Ok(llc)
+}
+
+/// A backend command run by the build, an entry of `l1x-commands.json` in the format
+/// of the Clang compilation database
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CommandRecord {
+    pub directory: PathBuf,
+    pub arguments: Vec<String>,
+    pub file: PathBuf,
+    pub output: PathBuf,
+}
+
+impl CommandRecord {
+    fn new(command: &Command, file: &Path, output: &Path) -> Self {
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or(path.to_path_buf());
+        Self {
+            directory: absolute(Path::new(".")),
+            arguments: std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            file: absolute(file),
+            output: absolute(output),
+        }
+    }
+}
+
+/// File listing the llc and strip commands of the last build, see `--emit-commands`
+pub const COMMANDS_FILE_NAME: &str = "l1x-commands.json";
+
+/// Formats a command as a shell command line, quoting arguments when needed
+pub fn format_command(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes `arg` for a POSIX shell unless it only has characters that don't need it
+pub(crate) fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=+./,:@".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// The strip invocation removing the local symbols of `target_file`
+pub(crate) fn strip_command(
+    target_file: &Path,
+    options: &BuildOptions,
+) -> Result<Command, BuildError> {
+    let command = match &options.strip_binary {
+        Some(strip_binary) => strip_binary.clone(),
+        None => get_strip_command()?,
+    };
+    let mut strip = Command::new(command);
+    strip.arg("-x").arg(target_file);
+    Ok(strip)
+}
+
+fn strip_object_file<P: AsRef<Path>>(
+    target_file: P,
+    options: &BuildOptions,
+) -> Result<CommandRecord, BuildError> {
+    let mut strip = strip_command(target_file.as_ref(), options)?;
+    let command = strip.get_program().to_string_lossy().into_owned();
+    let record = CommandRecord::new(&strip, target_file.as_ref(), target_file.as_ref());
+    let output = strip.output().map_err(|e| BuildError::LlvmStripRunError {
+        tool: Some(command.clone()),
+        source: Some(e),
+    })?;
+    if let Some(log) = &options.log {
+        log.command(&strip, &output);
+    }
+
+    if !output.status.success() {
+        eprintln!(
+            "Error stripping object file: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(BuildError::LlvmStripError {
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(record)
+}
+
+pub(crate) fn get_llc_command() -> Result<String, BuildError> {
+    if let Ok(path_str) = std::env::var("LLVM_BIN_PATH") {
+        let path = format!("{}/llc", path_str);
+        if std::path::Path::new(&path).exists() {
+            return Ok(path);
+        }
+    }
+    if which("llc-17".to_string()).is_some() {
+        return Ok("llc-17".into());
+    } else if which("llc-18".to_string()).is_some() {
+        return Ok("llc-18".into());
+    } else if which("llc-19".to_string()).is_some() {
+        return Ok("llc-19".into());
+    } else if which("llc".to_string()).is_some() {
+        let output = Command::new("llc").arg("--version").output();
+
+        if let Ok(output) = output {
+            let version_str = String::from_utf8_lossy(&output.stdout);
+            if version_str.contains("version 17.")
+                || version_str.contains("version 18.")
+                || version_str.contains("version 19.")
+            {
+                return Ok("llc".into());
+            } else {
+                return Err(BuildError::LlcRunError {
+                    tool: None,
+                    source: None,
+                });
+            }
+        } else {
+            return Err(BuildError::LlcRunError {
+                tool: None,
+                source: None,
+            });
+        }
+    } else {
+        return Err(BuildError::LlcRunError {
+            tool: None,
+            source: None,
+        });
+    }
+}
+
+/// `llvm-strip`, or GNU `strip` with a warning when no suitable `llvm-strip` is installed.
+/// Both discard the local symbols with `-x`
+fn get_strip_command() -> Result<String, BuildError> {
+    get_llvm_command().or_else(|e| match which("strip".to_string()) {
+        Some(strip) => {
+            eprintln!("⚠️  llvm-strip 17-19 was not found, falling back to GNU strip");
+            Ok(strip.to_string_lossy().into_owned())
+        }
+        None => Err(e),
+    })
+}
+
+pub(crate) fn get_llvm_command() -> Result<String, BuildError> {
+    if std::env::var("LLVM_BIN_PATH").is_ok() {
+        let path = format!(
+            "{}/llvm-strip",
+            std::env::var("LLVM_BIN_PATH").expect("checked")
+        );
+        if std::path::Path::new(&path).exists() {
+            return Ok(path);
+        }
+    }
+    if which("llvm-strip-17".to_string()).is_some() {
+        return Ok("llvm-strip-17".into());
+    } else if which("llvm-strip-18".to_string()).is_some() {
+        return Ok("llvm-strip-18".into());
+    } else if which("llvm-strip-19".to_string()).is_some() {
+        return Ok("llvm-strip-19".into());
+    } else if which("llvm-strip".to_string()).is_some() {
+        let output = Command::new("llvm-strip").arg("--version").output();
+
+        if let Ok(output) = output {
+            let version_str = String::from_utf8_lossy(&output.stdout);
+            if version_str.contains("version 17.")
+                || version_str.contains("version 18.")
+                || version_str.contains("version 19.")
+            {
+                return Ok("llvm-strip".into());
+            } else {
+                return Err(BuildError::LlvmStripRunError {
+                    tool: None,
+                    source: None,
+                });
+            }
+        } else {
+            return Err(BuildError::LlvmStripRunError {
+                tool: None,
+                source: None,
+            });
+        }
+    } else {
+        return Err(BuildError::LlvmStripRunError {
+            tool: None,
+            source: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_build_options_from_args() {
+        let mut args = to_args(&["--no-strip", "-p", "foo", "--expect-runtime", "3"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(options.no_strip);
+        assert_eq!(options.expect_runtime, Some(3));
+        assert_eq!(args, to_args(&["-p", "foo"]));
+
+        let mut args = to_args(&["--strip-mode", "none"]);
+        assert!(BuildOptions::from_args(&mut args).unwrap().no_strip);
+        let mut args = to_args(&["--strip-mode=full"]);
+        assert!(!BuildOptions::from_args(&mut args).unwrap().no_strip);
+        // the last one wins, e.g. over the `--no-strip` of `L1X_NO_STRIP`
+        let mut args = to_args(&["--no-strip", "--strip-mode", "full"]);
+        assert!(!BuildOptions::from_args(&mut args).unwrap().no_strip);
+        let mut args = to_args(&["--strip-mode=full", "--no-strip"]);
+        assert!(BuildOptions::from_args(&mut args).unwrap().no_strip);
+        let mut args = to_args(&["--strip-mode", "none", "--strip"]);
+        assert!(!BuildOptions::from_args(&mut args).unwrap().no_strip);
+
+        let mut args = to_args(&["--expect-runtime=2"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.check_runtime_version(&BuildConfig::default()),
+            Err(BuildError::RuntimeVersionMismatch(
+                2,
+                EXPECTED_RUNTIME_VERSION
+            ))
+        ));
+
+        let mut args = to_args(&["--message-format", "short", "--release"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert_eq!(options.message_format, Some(MessageFormat::Short));
+        assert_eq!(args, to_args(&["--release"]));
+
+        let mut args = to_args(&["--cpu", "v4"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::InvalidOption(option, _)) if option == "--cpu"
+        ));
+
+        let mut args = to_args(&["--runtime-version", "2"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert_eq!(options.cpu(&BuildConfig::default()), "v2");
+        assert!(options.validate(&BuildConfig::default()).is_ok());
+
+        // without --runtime-version, the expected runtime version of the config
+        let options = BuildOptions::default();
+        let config = BuildConfig {
+            expected_runtime_version: 1,
+            ..Default::default()
+        };
+        assert_eq!(options.cpu(&config), "v1");
+        let config = BuildConfig {
+            expected_runtime_version: 4,
+            ..Default::default()
+        };
+        assert_eq!(options.cpu(&config), "v3");
+        let mut args = to_args(&["--cpu", "v2"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        let config = BuildConfig {
+            expected_runtime_version: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.validate(&config),
+            Err(BuildError::CpuNotSupportedByRuntime {
+                runtime_version: 1,
+                ..
+            })
+        ));
+
+        let mut args = to_args(&["--runtime-version", "0"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::InvalidOption(option, _)) if option == "--runtime-version"
+        ));
+
+        let mut args = to_args(&["--runtime-version", "1", "--cpu", "v3"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::CpuNotSupportedByRuntime {
+                supported: "v1",
+                ..
+            })
+        ));
+
+        // the runtime can't be checked against the processor of the build host
+        let mut args = to_args(&["--runtime-version", "1", "--cpu", "probe"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::ConflictingOptions(option, _)) if option == "--cpu probe"
+        ));
+        let mut args = to_args(&["--cpu", "probe"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(options.validate(&BuildConfig::default()).is_ok());
+
+        let mut args = to_args(&["--runtime-version", "2", "--mattr", "+alu32"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(options.validate(&BuildConfig::default()).is_err());
+
+        let mut args = to_args(&["--wasm-target", "x86_64-unknown-linux-gnu"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::InvalidOption(option, _)) if option == "--wasm-target"
+        ));
+
+        let mut args = to_args(&["--max-artifacts=1", "--keep-going"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert_eq!(options.max_artifacts, Some(1));
+        assert!(args.is_empty());
+
+        let mut args = to_args(&["--expect-runtime", "three"]);
+        assert!(matches!(
+            BuildOptions::from_args(&mut args),
+            Err(BuildError::InvalidOption(..))
+        ));
+    }
+
+    #[test]
+    fn test_split_cargo_args() {
+        let (mut args, cargo_args) =
+            split_cargo_args(to_args(&["--cpu", "v2", "--", "--cpu", "--", "-p", "foo"]));
+        assert_eq!(args, to_args(&["--cpu", "v2"]));
+        assert_eq!(cargo_args, to_args(&["--cpu", "--", "-p", "foo"]));
+        assert_eq!(
+            BuildOptions::from_args(&mut args)
+                .unwrap()
+                .cpu(&BuildConfig::default()),
+            "v2"
+        );
+
+        let (args, cargo_args) = split_cargo_args(to_args(&["--release"]));
+        assert_eq!(args, to_args(&["--release"]));
+        assert!(cargo_args.is_empty());
+    }
+
+    #[test]
+    fn test_take_profile_names() {
+        let mut args = to_args(&[
+            "--config",
+            "prod",
+            "--config=net.offline=true",
+            "--config=staging",
+            "--config",
+            "extra.toml",
+            "--config",
+            "prod",
+        ]);
+        assert_eq!(take_profile_names(&mut args), ["prod", "staging"]);
+        assert_eq!(
+            args,
+            to_args(&["--config=net.offline=true", "--config", "extra.toml"])
+        );
+    }
+
+    #[test]
+    fn test_profile_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            "[profiles.prod]\nstack-frame-size = 4096\n\n[profiles.old]\ncpu = \"v1\"\n",
+        )
+        .unwrap();
+        let options = BuildOptions {
+            configs: to_args(&["prod", "old"]),
+            ..Default::default()
+        };
+        let variants = profile_variants(&options, &BuildConfig::default(), dir.path()).unwrap();
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].0.profile_name.as_deref(), Some("prod"));
+        assert_eq!(variants[0].1.stack_frame_size, 4096);
+        assert_eq!(variants[1].0.cpu(&variants[1].1), "v1");
+        assert_eq!(variants[1].1.stack_frame_size, EBPF_STACK_FRAME_SIZE);
+
+        let options = BuildOptions {
+            configs: to_args(&["staging"]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            profile_variants(&options, &BuildConfig::default(), dir.path()),
+            Err(BuildError::UnknownProfile { available, .. }) if available == ["old", "prod"]
+        ));
+    }
+
+    #[test]
+    fn test_effective_config() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            "[profiles.prod]\nstack-frame-size = 4096\ncpu = \"v1\"\n",
+        )
+        .unwrap();
+        let options = BuildOptions {
+            configs: to_args(&["prod"]),
+            no_strip: true,
+            cargo_profile: Some("dev".to_string()),
+            ..Default::default()
+        };
+        let variants = profile_variants(&options, &BuildConfig::default(), dir.path()).unwrap();
+        let settings: BTreeMap<_, _> =
+            effective_config(&variants[0].0, &variants[0].1, Path::new("target"))
+                .into_iter()
+                .collect();
+        assert_eq!(settings["profile"], "prod");
+        assert_eq!(settings["cargo_profile"], "dev");
+        assert_eq!(settings["output_dir"], "target/l1x/dev");
+        assert_eq!(settings["stack_size"], 4096);
+        assert_eq!(settings["cpu"], "v1");
+        assert_eq!(settings["strip_mode"], "none");
+        assert!(settings["strip"].is_null());
+        assert_eq!(settings["check_elf"], true);
+
+        // the command line takes precedence over the profile
+        let options = BuildOptions {
+            stack_size: Some(1024),
+            ..variants[0].0.clone()
+        };
+        let settings = effective_config(&options, &variants[0].1, Path::new("target"));
+        assert!(settings.contains(&("stack_size", 1024.into())));
+    }
+
+    #[test]
+    fn test_list_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("examples")).unwrap();
+        fs::write(dir.path().join("counter.o"), [0; 16]).unwrap();
+        fs::write(dir.path().join("counter.ll"), "").unwrap();
+        fs::write(dir.path().join("examples/demo.o"), [0; 8]).unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        assert_eq!(
+            list_artifacts(dir.path()).unwrap(),
+            vec![
+                (dir.path().join("counter.ll"), 0),
+                (dir.path().join("counter.o"), 16),
+                (dir.path().join("examples/demo.o"), 8),
+            ]
+        );
+        assert!(list_artifacts(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_has_contract_impl() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            "pub fn add(a: u8, b: u8) -> u8 { a + b }",
+        )
+        .unwrap();
+        assert!(!has_contract_impl(dir.path()));
+
+        fs::write(
+            dir.path().join("lib.rs"),
+            "#[l1x_sdk::contract]\nimpl Counter { pub fn get() -> u64 { 0 } }",
+        )
+        .unwrap();
+        assert!(has_contract_impl(dir.path()));
+
+        // not for syn
+        fs::write(dir.path().join("lib.rs"), "pub fn broken(").unwrap();
+        assert!(has_contract_impl(dir.path()));
+    }
+
+    #[test]
+    fn test_is_llc_crash() {
+        assert!(is_llc_crash(None, ""));
+        assert!(is_llc_crash(Some(139), ""));
+        assert!(is_llc_crash(Some(0xC000_0005_u32 as i32), ""));
+        assert!(is_llc_crash(
+            Some(1),
+            "PLEASE submit a bug report to https://github.com/llvm/llvm-project/issues/"
+        ));
+        assert!(!is_llc_crash(
+            Some(1),
+            "llc: error: counter.ll:3:1: expected top-level entity"
+        ));
+        assert!(!is_llc_crash(Some(0), "object file has no .text section"));
+    }
+
+    #[test]
+    fn test_wasm_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = cargo_metadata::camino::Utf8Path::from_path(dir.path()).unwrap();
+        let artifact = |crate_types: &[&str], filenames: &[&str]| {
+            serde_json::from_value::<cargo_metadata::Artifact>(serde_json::json!({
+                "package_id": "counter 0.1.0 (path+file:///p)",
+                "manifest_path": "/p/Cargo.toml",
+                "target": {
+                    "name": "counter",
+                    "kind": crate_types,
+                    "crate_types": crate_types,
+                    "src_path": "/p/src/lib.rs",
+                    "edition": "2021",
+                    "doctest": false,
+                    "test": false
+                },
+                "profile": {
+                    "opt_level": "3",
+                    "debuginfo": 0,
+                    "debug_assertions": false,
+                    "overflow_checks": false,
+                    "test": false
+                },
+                "features": [],
+                "filenames": filenames.iter().map(|name| dir.join(name)).collect::<Vec<_>>(),
+                "executable": null,
+                "fresh": false
+            }))
+            .unwrap()
+        };
+        fs::create_dir(dir.join("deps")).unwrap();
+        fs::write(dir.join("deps/counter-1f2e3d.wasm"), "").unwrap();
+        let filenames = [
+            "libcounter.rlib",
+            "deps/counter-1f2e3d.wasm",
+            "counter.wasm",
+        ];
+
+        // the copy named after the target is missing, e.g. with a custom profile
+        let wasm = wasm_file(&artifact(&["cdylib", "rlib"], &filenames));
+        assert_eq!(wasm, Some(dir.join("deps/counter-1f2e3d.wasm")));
+
+        fs::write(dir.join("counter.wasm"), "").unwrap();
+        let wasm = wasm_file(&artifact(&["cdylib", "rlib"], &filenames));
+        assert_eq!(wasm, Some(dir.join("counter.wasm")));
+
+        assert_eq!(wasm_file(&artifact(&["rlib"], &filenames)), None);
+        assert_eq!(
+            wasm_file(&artifact(&["cdylib"], &["libcounter.rlib"])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_read_messages() {
+        let artifact = |id: &str| {
+            serde_json::json!({
+                "reason": "compiler-artifact",
+                "package_id": id,
+                "manifest_path": "/p/Cargo.toml",
+                "target": {
+                    "name": "counter",
+                    "kind": ["cdylib"],
+                    "crate_types": ["cdylib"],
+                    "src_path": "/p/src/lib.rs",
+                    "edition": "2021",
+                    "doctest": false,
+                    "test": false
+                },
+                "profile": {
+                    "opt_level": "3",
+                    "debuginfo": 0,
+                    "debug_assertions": false,
+                    "overflow_checks": false,
+                    "test": false
+                },
+                "features": [],
+                "filenames": ["/p/target/counter.wasm"],
+                "executable": null,
+                "fresh": false
+            })
+            .to_string()
+        };
+        let output = [
+            artifact("counter 0.1.0 (path+file:///p)"),
+            "build script output".to_string(),
+            artifact("l1x-sdk 0.3.1 (registry+https://github.com/rust-lang/crates.io-index)"),
+            r#"{"reason":"build-finished","success":true}"#.to_string(),
+        ]
+        .join("\n");
+        let members = [cargo_metadata::PackageId {
+            repr: "counter 0.1.0 (path+file:///p)".to_string(),
+        }];
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let artifacts = read_messages(
+            output.as_bytes(),
+            MessageFormat::Human,
+            false,
+            &members,
+            None,
+            Some(&EventSink::channel(sender)),
+        )
+        .artifacts;
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].package_id, members[0]);
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert!(matches!(
+            &events[..],
+            [BuildEvent::ArtifactCompiled { crate_name, wasm }]
+                if crate_name == "counter" && wasm.ends_with("counter.wasm")
+        ));
+        assert!(read_messages(
+            output.as_bytes(),
+            MessageFormat::Json,
+            false,
+            &[],
+            None,
+            None
+        )
+        .artifacts
+        .is_empty());
+
+        // invalid UTF-8 and a truncated message don't hide the following artifacts
+        let mut output = b"warning: \xff\xfe not utf-8\n".to_vec();
+        output.extend_from_slice(br#"{"reason":"compiler-artifact","package_id":"#);
+        output.extend_from_slice(b"\r\n\xc3\n");
+        output.extend_from_slice(artifact("counter 0.1.0 (path+file:///p)").as_bytes());
+        let artifacts = read_messages(
+            output.as_slice(),
+            MessageFormat::Short,
+            true,
+            &members,
+            None,
+            None,
+        )
+        .artifacts;
+        assert_eq!(artifacts.len(), 1);
+    }
+
+    #[test]
+    fn test_count_warnings() {
+        let message = |target: &str, level: &str, text: &str, code: Option<&str>| {
+            serde_json::json!({
+                "reason": "compiler-message",
+                "package_id": format!("{} 0.1.0 (path+file:///p)", target),
+                "manifest_path": "/p/Cargo.toml",
+                "target": {
+                    "name": target,
+                    "kind": ["cdylib"],
+                    "crate_types": ["cdylib"],
+                    "src_path": "/p/src/lib.rs",
+                    "edition": "2021",
+                    "doctest": false,
+                    "test": false
+                },
+                "message": {
+                    "message": text,
+                    "code": code.map(|code| serde_json::json!({ "code": code, "explanation": null })),
+                    "level": level,
+                    "spans": [],
+                    "children": [],
+                    "rendered": format!("{}: {}\n", level, text)
+                }
+            })
+            .to_string()
+        };
+        let output = [
+            message(
+                "counter",
+                "warning",
+                "unused variable: `x`",
+                Some("unused_variables"),
+            ),
+            message(
+                "counter",
+                "warning",
+                "unused import",
+                Some("unused_imports"),
+            ),
+            message("counter", "warning", "2 warnings emitted", None),
+            message("token", "warning", "field is never read", Some("dead_code")),
+            message("token", "error", "mismatched types", Some("E0308")),
+        ]
+        .join("\n");
+
+        let warnings = read_messages(
+            output.as_bytes(),
+            MessageFormat::Json,
+            false,
+            &[],
+            None,
+            None,
+        )
+        .warnings;
+        assert_eq!(
+            warnings,
+            BTreeMap::from([("counter".to_string(), 2), ("token".to_string(), 1)])
+        );
+        assert_eq!(
+            BuildError::CompilerWarnings(warnings).to_string(),
+            "The build emitted 3 warning(s): counter (2), token (1)"
+        );
+    }
+
+    #[test]
+    fn test_summary_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let ll = dir.path().join("counter.ll");
+        fs::write(&ll, "").unwrap();
+        fs::write(ll.with_extension("o"), b"object").unwrap();
+        let mut report = ContractReport {
+            package_id: String::new(),
+            ll: ll.clone(),
+            object: Some(ll.with_extension("o")),
+            debug_object: None,
+            padded: None,
+            compressed: None,
+            raw: None,
+            borsh_schema: None,
+            commands: Vec::new(),
+            profile: None,
+            wasm_features: Some(vec!["bulk-memory".to_string()]),
+            symbol_sizes: None,
+        };
+        let config = BuildConfig::default();
+
+        let entry = SummaryEntry::new("counter", &report, &config).unwrap();
+        assert_eq!(entry.file, ll.with_extension("o"));
+        assert_eq!(entry.size, 6);
+        assert_eq!(entry.sha256, format!("{:x}", Sha256::digest(b"object")));
+        assert_eq!(entry.expected_runtime_version, EXPECTED_RUNTIME_VERSION);
+        assert_eq!(entry.wasm_features.unwrap(), ["bulk-memory"]);
+
+        // with --skip-llc the LLVM IR is the output
+        report.object = None;
+        let entry = SummaryEntry::new("counter", &report, &config).unwrap();
+        assert_eq!((entry.file, entry.size), (ll, 0));
+    }
+
+    #[test]
+    fn test_compress_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let object = dir.path().join("contract.o");
+        fs::write(&object, vec![7; 4096]).unwrap();
+
+        let (gzip, gzip_size) = compress_object(&object, Compression::Gzip).unwrap();
+        assert_eq!(gzip, dir.path().join("contract.o.gz"));
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(fs::File::open(&gzip).unwrap()),
+            &mut content,
+        )
+        .unwrap();
+        assert_eq!(content, vec![7; 4096]);
+        assert!(gzip_size < 4096);
+
+        let (zstd, _) = compress_object(&object, Compression::Zstd).unwrap();
+        assert_eq!(zstd, dir.path().join("contract.o.zst"));
+        let content = zstd::stream::decode_all(fs::File::open(&zstd).unwrap()).unwrap();
+        assert_eq!(content, vec![7; 4096]);
+    }
+
+    #[test]
+    fn test_write_raw_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut object = object::write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Bpf,
+            object::Endianness::Little,
+        );
+        let text = object.add_section(Vec::new(), b".text".to_vec(), object::SectionKind::Text);
+        object.append_section_data(text, &[1; 12], 8);
+        let rodata = object.add_section(
+            Vec::new(),
+            b".rodata".to_vec(),
+            object::SectionKind::ReadOnlyData,
+        );
+        object.append_section_data(rodata, &[2; 4], 8);
+        let path = dir.path().join("contract.o");
+        fs::write(&path, object.write().unwrap()).unwrap();
+
+        let raw = write_raw_binary(&path).unwrap();
+        assert_eq!(raw.path, dir.path().join("contract.bin"));
+        // the data is aligned to 8 bytes after the code
+        let mut expected = vec![1; 12];
+        expected.extend([0; 4]);
+        expected.extend([2; 4]);
+        assert_eq!(fs::read(&raw.path).unwrap(), expected);
+        assert_eq!(raw.size, 20);
+        let section = |name: &str, offset, size| RawSection {
+            name: name.to_string(),
+            offset,
+            size,
+        };
+        assert_eq!(
+            raw.sections,
+            [section(".text", 0, 12), section(".rodata", 16, 4)]
+        );
+
+        let symbol = object.add_symbol(object::write::Symbol {
+            name: b"counter".to_vec(),
+            value: 0,
+            size: 0,
+            kind: object::SymbolKind::Data,
+            scope: object::write::SymbolScope::Linkage,
+            weak: false,
+            section: object::write::SymbolSection::Undefined,
+            flags: object::SymbolFlags::None,
+        });
+        object
+            .add_relocation(
+                text,
+                object::write::Relocation {
+                    offset: 0,
+                    symbol,
+                    addend: 0,
+                    flags: object::RelocationFlags::Elf { r_type: 1 },
+                },
+            )
+            .unwrap();
+        fs::write(&path, object.write().unwrap()).unwrap();
+        match write_raw_binary(&path) {
+            Err(BuildError::InvalidObject { problems, .. }) => assert_eq!(
+                problems,
+                [".text has relocations, a raw binary can't have them applied"]
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        fs::write(&path, b"not an object").unwrap();
+        assert!(matches!(
+            write_raw_binary(&path),
+            Err(BuildError::InvalidObject { .. })
+        ));
+    }
+
+    #[test]
+    fn test_normalize_profile_args() {
+        let mut args = to_args(&["--release", "--profile", "release", "-p", "foo"]);
+        assert_eq!(
+            normalize_profile_args(&mut args).unwrap().as_deref(),
+            Some("release")
+        );
+        assert_eq!(args, to_args(&["-p", "foo"]));
+
+        let mut args = to_args(&["-r", "--profile=release", "--RELEASE"]);
+        normalize_profile_args(&mut args).unwrap();
+        assert!(args.is_empty());
+
+        let mut args = to_args(&["--release", "-p", "foo"]);
+        assert_eq!(normalize_profile_args(&mut args).unwrap(), None);
+        assert_eq!(args, to_args(&["-p", "foo"]));
+
+        let mut args = to_args(&["--profile", "dev"]);
+        assert_eq!(
+            normalize_profile_args(&mut args).unwrap().as_deref(),
+            Some("dev")
+        );
+        assert!(args.is_empty());
+
+        let mut args = to_args(&["--release", "--profile", "dev"]);
+        assert!(matches!(
+            normalize_profile_args(&mut args),
+            Err(BuildError::InvalidOption(option, profile)) if option == "--profile" && profile == "dev"
+        ));
+
+        let mut args = to_args(&["--profile", "dev", "--profile=bench"]);
+        assert!(normalize_profile_args(&mut args).is_err());
+    }
+
+    #[test]
+    fn test_cargo_profile() {
+        let target_dir = Path::new("target");
+        let profile_args = |options: &BuildOptions| {
+            let command = cargo_build_command(&[], options);
+            let args: Vec<_> = command.get_args().map(|arg| arg.to_owned()).collect();
+            args[3..].to_vec()
+        };
+
+        let options = BuildOptions::default();
+        assert_eq!(options.bin_dir(target_dir), target_dir.join("l1x/release"));
+        assert_eq!(profile_args(&options), ["--release"]);
+
+        for profile in ["release", "dev"] {
+            let options = BuildOptions {
+                cargo_profile: Some(profile.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                options.bin_dir(target_dir),
+                target_dir.join("l1x").join(profile)
+            );
+            assert_eq!(profile_args(&options), ["--profile", profile]);
+        }
+
+        let options = BuildOptions {
+            cargo_profile: Some("size-opt".to_string()),
+            size_profile: Some(SIZE_PROFILES[0]),
+            ..Default::default()
+        };
+        let command = cargo_build_command(&[], &options);
+        assert!(command
+            .get_envs()
+            .any(|(name, _)| name == "CARGO_PROFILE_SIZE_OPT_OPT_LEVEL"));
+    }
+
+    #[test]
+    fn test_keep_going_is_forwarded() {
+        let options = BuildOptions {
+            keep_going: true,
+            ..Default::default()
+        };
+        let command = cargo_build_command(&[], &options);
+        assert!(command.get_args().any(|arg| arg == "--keep-going"));
+        let command = cargo_build_command(&[], &BuildOptions::default());
+        assert!(!command.get_args().any(|arg| arg == "--keep-going"));
+    }
+
+    #[test]
+    fn test_check_object_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let object = dir.path().join("contract.o");
+        assert!(check_object_file(&object).is_err());
+        fs::write(&object, "").unwrap();
+        assert!(check_object_file(&object).unwrap_err().contains("empty"));
+        fs::write(&object, [0x7f, b'E', b'L', b'F', 2, 1]).unwrap();
+        assert!(check_object_file(&object).is_err());
+    }
+
+    #[test]
+    fn test_check_elf() {
+        use object::write;
+        let write_object = |path: &Path, architecture, versions: &[i64], undefined: &[&str]| {
+            let mut object = write::Object::new(
+                object::BinaryFormat::Elf,
+                architecture,
+                object::Endianness::Little,
+            );
+            let section =
+                object.add_section(Vec::new(), b"_version".to_vec(), object::SectionKind::Data);
+            let names = ["_OBJECT_VERSION", "_EXPECTED_RUNTIME_VERSION"];
+            for (name, version) in names.iter().zip(versions) {
+                let symbol = object.add_symbol(write::Symbol {
+                    name: name.as_bytes().to_vec(),
+                    value: 0,
+                    size: 8,
+                    kind: object::SymbolKind::Data,
+                    scope: object::SymbolScope::Linkage,
+                    weak: false,
+                    section: write::SymbolSection::Undefined,
+                    flags: object::SymbolFlags::None,
+                });
+                object.add_symbol_data(symbol, section, &version.to_le_bytes(), 1);
+            }
+            for name in undefined {
+                object.add_symbol(write::Symbol {
+                    name: name.as_bytes().to_vec(),
+                    value: 0,
+                    size: 0,
+                    kind: object::SymbolKind::Text,
+                    scope: object::SymbolScope::Linkage,
+                    weak: false,
+                    section: write::SymbolSection::Undefined,
+                    flags: object::SymbolFlags::None,
+                });
+            }
+            fs::write(path, object.write().unwrap()).unwrap();
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contract.o");
+        let options = BuildOptions::default();
+        let config = BuildConfig::default();
+
+        write_object(
+            &path,
+            object::Architecture::Bpf,
+            &[1, 3],
+            &["env_input", "env_future"],
+        );
+        check_elf(&path, &options, &config).unwrap();
+
+        write_object(&path, object::Architecture::X86_64, &[1, 4], &["memcpy"]);
+        let Err(BuildError::InvalidObject { problems, .. }) = check_elf(&path, &options, &config)
+        else {
+            panic!("The object should be invalid");
+        };
+        assert_eq!(
+            problems,
+            [
+                "built for X86_64 instead of BPF",
+                "_EXPECTED_RUNTIME_VERSION is 4 instead of 3",
+                "undefined symbols outside of the host module: memcpy",
+            ]
+        );
+
+        write_object(&path, object::Architecture::Bpf, &[], &[]);
+        assert!(check_elf(&path, &options, &config).is_err());
+        let options = BuildOptions {
+            no_version_info: true,
+            ..Default::default()
+        };
+        check_elf(&path, &options, &config).unwrap();
+
+        let mut args = to_args(&["--no-check-elf"]);
+        assert!(BuildOptions::from_args(&mut args).unwrap().no_check_elf);
+        let mut args = to_args(&["--no-check-elf", "--check-elf"]);
+        assert!(!BuildOptions::from_args(&mut args).unwrap().no_check_elf);
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn test_clean_outputs() {
+        let (dir, metadata) = temp_workspace();
+        let target_dir = dir.path().join("target");
+        let options = BuildOptions::default();
+        let bin_dir = options.bin_dir(&target_dir);
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::write(bin_dir.join("removed.o"), b"").unwrap();
+        let out_dir = dir.path().join("out");
+        fs::create_dir_all(&out_dir).unwrap();
+        fs::write(out_dir.join("other.txt"), b"").unwrap();
+        let outside = BuildOptions {
+            out_dir: Some(out_dir.clone()),
+            ..BuildOptions::default()
+        };
+
+        let variants = [
+            (options, BuildConfig::default()),
+            (outside, BuildConfig::default()),
+        ];
+        clean_outputs(&[], &metadata, &variants, &target_dir).unwrap();
+        assert!(!bin_dir.exists());
+        assert!(out_dir.join("other.txt").exists());
+        // nothing to remove the second time
+        clean_outputs(&[], &metadata, &variants, &target_dir).unwrap();
+    }
+
+    #[test]
+    fn test_cargo_clean_command() {
+        let args: Vec<String> = [
+            "-p",
+            "counter",
+            "--features",
+            "x",
+            "--locked",
+            "--target-dir=t",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let options = BuildOptions {
+            cargo_profile: Some("dev".to_string()),
+            ..BuildOptions::default()
+        };
+        let command = cargo_clean_command(&args, &options).unwrap();
+        let command_args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert_eq!(
+            command_args,
+            [
+                "clean",
+                "--target",
+                options.wasm_target(),
+                "--profile",
+                "dev",
+                "-p",
+                "counter",
+                "--target-dir",
+                "t",
+                "--locked"
+            ]
+        );
+
+        let command = cargo_clean_command(&[], &BuildOptions::default()).unwrap();
+        assert!(command.get_args().any(|arg| arg == "--release"));
+    }
+
+    #[test]
+    fn test_differing_stage() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = |build: &str, wasm: &[u8], ll: &str| {
+            let build_dir = dir.path().join(build);
+            fs::create_dir_all(&build_dir).unwrap();
+            fs::write(build_dir.join("counter.wasm"), wasm).unwrap();
+            fs::write(build_dir.join("counter.ll"), ll).unwrap();
+            StageOutputs {
+                target: "counter".to_string(),
+                wasm: build_dir.join("counter.wasm"),
+                ll: build_dir.join("counter.ll"),
+                object: None,
+            }
+        };
+        let first = outputs("first", b"\0asm", "define i64 @get() {\n  ret i64 1\n}\n");
+        let same = outputs("same", b"\0asm", "define i64 @get() {\n  ret i64 1\n}\n");
+        assert!(differing_stage(&first, &same).unwrap().is_none());
+
+        let second = outputs("second", b"\0asm", "define i64 @get() {\n  ret i64 22\n}\n");
+        let (stage, first_file, second_file) = differing_stage(&first, &second).unwrap().unwrap();
+        assert_eq!(stage, "the translation to LLVM IR");
+        assert_eq!(
+            file_differences(first_file, second_file).unwrap(),
+            ["size: 34 != 35 bytes", "first different line: 2"]
+        );
+
+        let third = outputs("third", b"\0asm\x01", "");
+        assert_eq!(
+            differing_stage(&first, &third).unwrap().unwrap().0,
+            "the wasm build by cargo and rustc"
+        );
+
+        let write_object = |name: &str, text: &[u8]| {
+            let mut object = object::write::Object::new(
+                object::BinaryFormat::Elf,
+                object::Architecture::Bpf,
+                object::Endianness::Little,
+            );
+            let section =
+                object.add_section(Vec::new(), b".text".to_vec(), object::SectionKind::Text);
+            object.append_section_data(section, text, 8);
+            let path = dir.path().join(name);
+            fs::write(&path, object.write().unwrap()).unwrap();
+            path
+        };
+        let (first, second) = (
+            write_object("first.o", &[1; 8]),
+            write_object("second.o", &[2; 8]),
+        );
+        assert_eq!(
+            file_differences(&first, &second).unwrap(),
+            ["section .text: different content"]
+        );
+    }
+
+    #[test]
+    fn test_check_sections() {
+        for name in [
+            ".text",
+            ".rodata.str1.1",
+            ".rel.text",
+            ".rela_memory",
+            "_init_memory",
+            ".debug_info",
+            ".BTF.ext",
+        ] {
+            assert!(is_known_section(name), "{}", name);
+        }
+        for name in [".maps", "license", ".textual", "_memory_extra", ".rel.maps"] {
+            assert!(!is_known_section(name), "{}", name);
+        }
+
+        let mut object = object::write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Bpf,
+            object::Endianness::Little,
+        );
+        for name in ["_memory", ".maps", "license"] {
+            let section = object.add_section(
+                Vec::new(),
+                name.as_bytes().to_vec(),
+                object::SectionKind::Data,
+            );
+            object.append_section_data(section, &[0; 8], 8);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contract.o");
+        fs::write(&path, object.write().unwrap()).unwrap();
+        let Err(BuildError::InvalidObject { problems, .. }) = check_sections(&path) else {
+            panic!("The sections should be rejected");
+        };
+        assert_eq!(problems, ["unknown sections: .maps, license"]);
+    }
+
+    #[test]
+    fn test_rustflags() {
+        let options = BuildOptions::default();
+        assert_eq!(rustflags(None, &options).as_deref(), Some("-C link-arg=-s"));
+        let options = BuildOptions {
+            no_link_strip: true,
+            deny_warnings: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            rustflags(Some("-C opt-level=z ".to_string()), &options).as_deref(),
+            Some("-C opt-level=z -D warnings")
+        );
+        let options = BuildOptions {
+            no_link_strip: true,
+            ..Default::default()
+        };
+        assert_eq!(rustflags(Some(String::new()), &options), None);
+    }
+
+    #[test]
+    fn test_is_hermetic_cleared() {
+        assert!(is_hermetic_cleared("RUSTFLAGS"));
+        assert!(is_hermetic_cleared("CARGO_ENCODED_RUSTFLAGS"));
+        assert!(is_hermetic_cleared("CARGO_PROFILE_RELEASE_LTO"));
+        assert!(is_hermetic_cleared(
+            "CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUSTFLAGS"
+        ));
+        assert!(!is_hermetic_cleared("CARGO_TARGET_DIR"));
+        assert!(!is_hermetic_cleared("CARGO_HOME"));
+        assert!(!is_hermetic_cleared("RUSTUP_TOOLCHAIN"));
+        assert!(!is_hermetic_cleared("L1X_CPU"));
+
+        // the tool's own settings are set after the environment is cleared
+        let options = BuildOptions {
+            hermetic: true,
+            size_profile: Some(SIZE_PROFILES[0]),
+            ..Default::default()
+        };
+        let command = cargo_build_command(&[], &options);
+        let envs: std::collections::HashMap<_, _> = command.get_envs().collect();
+        assert_eq!(
+            envs[std::ffi::OsStr::new("RUSTFLAGS")],
+            Some(std::ffi::OsStr::new("-C link-arg=-s"))
+        );
+        assert!(envs[std::ffi::OsStr::new("CARGO_PROFILE_RELEASE_LTO")].is_some());
+    }
+
+    #[test]
+    fn test_size_profile() {
+        let profile: SizeProfile = "z-lto".parse().unwrap();
+        assert_eq!(profile.opt_level, "z");
+        assert!(profile.lto);
+        assert_eq!(profile.to_string(), "opt-level=z, lto");
+        assert!("3".parse::<SizeProfile>().is_err());
+
+        let options = BuildOptions {
+            size_profile: Some(profile),
+            ..Default::default()
+        };
+        let command = cargo_build_command(&[], &options);
+        let envs: Vec<_> = command.get_envs().collect();
+        assert!(envs.contains(&(
+            "CARGO_PROFILE_RELEASE_OPT_LEVEL".as_ref(),
+            Some("z".as_ref())
+        )));
+        assert!(envs.contains(&("CARGO_PROFILE_RELEASE_LTO".as_ref(), Some("true".as_ref()))));
+    }
+
+    #[test]
+    fn test_stabilize_ll() {
+        let content = "; ModuleID = '/tmp/build-1/contract.wasm'\n\
+            source_filename = \"/tmp/build-1/contract.wasm\"\n\
+            @0 = constant [40 x i8] c\"/home/dev/.cargo/registry/src/lib.rs\"\n\
+            @1 = constant [22 x i8] c\"/work/app/src/lib.rs\"\n";
+        let replacements = [
+            ("/home/dev".to_string(), "<home>"),
+            ("/home/dev/.cargo".to_string(), "<cargo-home>"),
+            ("/work/app".to_string(), "<workspace>"),
+        ];
+        assert_eq!(
+            stabilize_ll(content, &replacements),
+            "; ModuleID = '<module>'\n\
+            source_filename = \"<module>\"\n\
+            @0 = constant [40 x i8] c\"<cargo-home>/registry/src/lib.rs\"\n\
+            @1 = constant [22 x i8] c\"<workspace>/src/lib.rs\"\n"
+        );
+
+        // the same module with the unnamed values created in another order
+        let first = "@7 = global i64 0\n\
+            @3 = constant [3 x i8] c\"%1@2\"\n\
+            define i64 @f(i64 %5) {\n\
+            %9 = load i64, ptr @7\n\
+            br label %4\n\
+            4:  ; preds = %1\n\
+            %x.2 = add i64 %9, %5\n\
+            ret i64 %x.2\n\
+            }\n\
+            define void @g(i64 %2) {\n\
+            ret void\n\
+            }\n";
+        let second = first
+            .replace("@7", "@1")
+            .replace("%9", "%6")
+            .replace("%5", "%8")
+            .replace("%4", "%3")
+            .replace("4:", "3:");
+        assert_ne!(first, second);
+        let stable = stabilize_ll(first, &[]);
+        assert_eq!(stable, stabilize_ll(&second, &[]));
+        assert_eq!(
+            stable,
+            "@0 = global i64 0\n\
+            @1 = constant [3 x i8] c\"%1@2\"\n\
+            define i64 @f(i64 %0) {\n\
+            %1 = load i64, ptr @0\n\
+            br label %2\n\
+            2:  ; preds = %3\n\
+            %x.2 = add i64 %1, %0\n\
+            ret i64 %x.2\n\
+            }\n\
+            define void @g(i64 %0) {\n\
+            ret void\n\
+            }\n"
+        );
+    }
+
+    #[test]
+    fn test_command_record() {
+        let mut command = Command::new("llvm-strip");
+        command.arg("-x").arg("contract.o");
+        let record = CommandRecord::new(&command, Path::new("contract.o"), Path::new("contract.o"));
+        assert_eq!(record.arguments, vec!["llvm-strip", "-x", "contract.o"]);
+        assert!(record.directory.is_absolute());
+        assert_eq!(record.file, record.directory.join("contract.o"));
+    }
+
+    #[test]
+    fn test_format_command() {
+        let mut command = Command::new("llc-17");
+        command
+            .arg("-mcpu=v3")
+            .arg("my dir/contract.ll")
+            .arg("it's");
+        assert_eq!(
+            format_command(&command),
+            "llc-17 -mcpu=v3 'my dir/contract.ll' 'it'\\''s'"
+        );
+    }
+
+    #[test]
+    fn test_take_flag() {
+        let mut args = to_args(&["--keep-going", "-p", "foo", "--no-keep-going"]);
+        assert!(!take_flag(&mut args, "--keep-going").unwrap());
+        assert_eq!(args, to_args(&["-p", "foo"]));
+        let mut args = to_args(&["--keep-going=false", "--keep-going=true"]);
+        assert!(take_flag(&mut args, "--keep-going").unwrap());
+        assert!(args.is_empty());
+        let mut args = to_args(&["--no-check-elf", "--check-elf"]);
+        assert!(!take_flag(&mut args, "--no-check-elf").unwrap());
+        assert!(!take_flag(&mut to_args(&["--strict-imports"]), "--strict").unwrap());
+        assert!(matches!(
+            take_flag(&mut to_args(&["--strict=yes"]), "--strict"),
+            Err(BuildError::InvalidOption(..))
+        ));
+    }
+
+    #[test]
+    fn test_env_name() {
+        assert_eq!(env_name("--no-strip"), "L1X_NO_STRIP");
+        assert_eq!(env_name("--stack-size"), "L1X_STACK_SIZE");
+        assert!(ENV_FLAGS.contains(&"--profile-size"));
+    }
+
+    #[test]
+    fn test_conflicting_options() {
+        let conflict = |args: &[&str]| {
+            let options = BuildOptions::from_args(&mut to_args(args)).unwrap();
+            match options.validate(&BuildConfig::default()) {
+                Err(BuildError::ConflictingOptions(option, other)) => Some((option, other)),
+                _ => None,
+            }
+        };
+        let pair = |option: &str, other: &str| Some((option.to_string(), other.to_string()));
+        assert_eq!(
+            conflict(&["--profile-size", "--skip-llc"]),
+            pair("--profile-size", "--skip-llc")
+        );
+        assert_eq!(
+            conflict(&["--profile-size", "--config", "small"]),
+            pair("--profile-size", "--config")
+        );
+        assert_eq!(
+            conflict(&["--assert-deterministic", "--profile-size"]),
+            pair("--assert-deterministic", "--profile-size")
+        );
+        assert_eq!(
+            conflict(&["--assert-deterministic", "--config", "small"]),
+            pair("--assert-deterministic", "--config")
+        );
+        assert_eq!(
+            conflict(&["--emit-script", "build.sh", "--profile-size"]),
+            pair("--emit-script", "--profile-size")
+        );
+        assert_eq!(
+            conflict(&["--emit-script", "build.sh", "--container"]),
+            pair("--emit-script", "--container")
+        );
+        assert_eq!(
+            conflict(&["--emit-abi-only", "--emit-script", "build.sh"]),
+            pair("--emit-abi-only", "--emit-script")
+        );
+        assert_eq!(
+            conflict(&["--emit-abi-only", "--profile-size"]),
+            pair("--emit-abi-only", "--profile-size")
+        );
+        assert_eq!(
+            conflict(&["--log-file", "build.log", "--container"]),
+            pair("--log-file", "--container")
+        );
+        assert_eq!(
+            conflict(&["--output-format", "raw", "--skip-llc"]),
+            pair("--output-format raw", "--skip-llc")
+        );
+        assert_eq!(
+            conflict(&["--compare-to", "ref.o", "--skip-llc"]),
+            pair("--compare-to", "--skip-llc")
+        );
+        assert_eq!(
+            conflict(&["--compare-to", "ref.o", "--config", "small"]),
+            pair("--compare-to", "--config")
+        );
+        assert_eq!(
+            conflict(&["--explain-size", "--skip-llc"]),
+            pair("--explain-size", "--skip-llc")
+        );
+        assert_eq!(
+            conflict(&["--explain-size", "--summary-only"]),
+            pair("--explain-size", "--summary-only")
+        );
+        assert_eq!(conflict(&["--profile-size"]), None);
+
+        let options = BuildOptions::from_args(&mut to_args(&["--compress-only"])).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::MissingOption(option, _)) if option == "--compress-only"
+        ));
+    }
+
+    #[test]
+    fn test_validate_mattr() {
+        assert!(validate_mattr("+alu32").is_ok());
+        assert!(validate_mattr("+alu32,-dwarfris").is_ok());
+        assert!(matches!(
+            validate_mattr("+jmp32"),
+            Err(BuildError::UnknownBpfFeature(feature)) if feature == "jmp32"
+        ));
+        assert!(matches!(
+            validate_mattr("alu32"),
+            Err(BuildError::InvalidOption(..))
+        ));
+    }
+
+    /// Workspace with the `token` and `dao` contracts and a `cli` tool
+    fn temp_workspace() -> (tempfile::TempDir, Metadata) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"contracts/*\", \"tools/*\"]\n",
+        )
+        .unwrap();
+        for member in ["contracts/token", "contracts/dao", "tools/cli"] {
+            let member_dir = dir.path().join(member);
+            fs::create_dir_all(member_dir.join("src")).unwrap();
+            fs::write(member_dir.join("src/lib.rs"), "").unwrap();
+            let name = member.rsplit('/').next().unwrap();
+            fs::write(
+                member_dir.join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+            )
+            .unwrap();
+        }
+        let manifest_path = dir.path().join("Cargo.toml");
+        let metadata = load_metadata(&to_args(&[
+            "--manifest-path",
+            manifest_path.to_str().unwrap(),
+        ]))
+        .unwrap();
+        (dir, metadata)
+    }
+
+    #[test]
+    fn test_select_members() {
+        let (_dir, metadata) = temp_workspace();
+        let mut selected = select_members(&metadata, &to_args(&["contracts/*"])).unwrap();
+        selected.sort();
+        assert_eq!(selected, to_args(&["dao", "token"]));
+        assert!(matches!(
+            select_members(&metadata, &to_args(&["libs/*"])),
+            Err(BuildError::NoMatchingMembers(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_with_events_reports_failure() {
+        let (dir, _metadata) = temp_workspace();
+        let manifest_path = dir.path().join("Cargo.toml");
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let result = build_with_events(
+            to_args(&[
+                "--manifest-path",
+                manifest_path.to_str().unwrap(),
+                "--strip-mode",
+                "bogus",
+            ]),
+            dir.path().join("target"),
+            EventSink::channel(sender),
+        );
+        assert!(matches!(result, Err(BuildError::InvalidOption(..))));
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert!(matches!(
+            events.as_slice(),
+            [BuildEvent::Failed { contract: None, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_dirty_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(dirty_files(dir.path()), None);
+
+        let status = Command::new("git")
+            .arg("init")
+            .arg("-q")
+            .current_dir(dir.path())
+            .status();
+        if !status.is_ok_and(|status| status.success()) {
+            return; // git is not installed
+        }
+        assert_eq!(dirty_files(dir.path()), Some(vec![]));
+        fs::write(dir.path().join("lib.rs"), "").unwrap();
+        assert_eq!(dirty_files(dir.path()), Some(vec!["lib.rs".to_string()]));
+    }
+
+    #[test]
+    fn test_clean_outputs_of_packages() {
+        let (dir, _) = temp_workspace();
+        for member in ["contracts/token", "contracts/dao"] {
+            let manifest = dir.path().join(member).join("Cargo.toml");
+            let content = fs::read_to_string(&manifest).unwrap();
+            fs::write(&manifest, content + "\n[lib]\ncrate-type = [\"cdylib\"]\n").unwrap();
+        }
+        let manifest_path = dir.path().join("Cargo.toml");
+        let metadata = load_metadata(&to_args(&[
+            "--manifest-path",
+            manifest_path.to_str().unwrap(),
+        ]))
+        .unwrap();
+        let target_dir = dir.path().join("target");
+        let variants = [(BuildOptions::default(), BuildConfig::default())];
+        let bin_dir = variants[0].0.bin_dir(&target_dir);
+        fs::create_dir_all(&bin_dir).unwrap();
+        for file in ["token.o", "token.ll", "token_v2.o", "dao.o"] {
+            fs::write(bin_dir.join(file), "").unwrap();
+        }
+
+        clean_outputs(
+            &to_args(&["-p", "token"]),
+            &metadata,
+            &variants,
+            &target_dir,
+        )
+        .unwrap();
+        let mut left: Vec<_> = fs::read_dir(&bin_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["dao.o", "token_v2.o"]);
+
+        clean_outputs(&[], &metadata, &variants, &target_dir).unwrap();
+        assert!(!bin_dir.exists());
+    }
+
+    #[test]
+    fn test_changed_members() {
+        let (dir, metadata) = temp_workspace();
+        let files = [
+            "contracts/dao/src/lib.rs",
+            "README.md",
+            "contracts/dao/Cargo.toml",
+        ];
+        assert_eq!(
+            changed_members(&metadata, files.into_iter()),
+            to_args(&["dao"])
+        );
+        assert!(changed_members(&metadata, ["contracts/daos.rs"].into_iter()).is_empty());
+        let mut all = changed_members(&metadata, ["Cargo.lock"].into_iter());
+        all.sort();
+        assert_eq!(all, to_args(&["cli", "dao", "token"]));
+        assert_eq!(
+            changed_members(&metadata, ["l1x.toml"].into_iter()).len(),
+            3
+        );
+
+        // dao is built again when the token it depends on changes
+        fs::write(
+            dir.path().join("contracts/dao/Cargo.toml"),
+            "[package]\nname = \"dao\"\nversion = \"0.1.0\"\n\n[dependencies]\ntoken = { path = \"../token\" }\n",
+        )
+        .unwrap();
+        let manifest_path = dir.path().join("Cargo.toml");
+        let metadata = load_metadata(&to_args(&[
+            "--manifest-path",
+            manifest_path.to_str().unwrap(),
+        ]))
+        .unwrap();
+        let mut changed = changed_members(&metadata, ["contracts/token/src/lib.rs"].into_iter());
+        changed.sort();
+        assert_eq!(changed, to_args(&["dao", "token"]));
+    }
+
+    #[test]
+    fn test_pad_object_file() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(&[1u8; 100]).unwrap();
+
+        let padded = pad_object_file(temp_file.path(), 64).unwrap();
+        assert_eq!(padded.size, 128);
+        let content = fs::read(temp_file.path()).unwrap();
+        assert_eq!(content.len(), 128);
+        assert!(content[100..].iter().all(|b| *b == 0));
+
+        let padded = pad_object_file(temp_file.path(), 64).unwrap();
+        assert_eq!(padded.size, 128);
+    }
+
+    #[test]
+    fn test_meta() {
+        assert_eq!(
+            parse_meta("build.id=a=b").unwrap(),
+            ("build.id".to_string(), "a=b".to_string())
+        );
+        assert!(parse_meta("author").is_err());
+        assert!(parse_meta("=x").is_err());
+        assert!(parse_meta("a b=x").is_err());
+
+        let mut args = to_args(&["--meta", "author=a", "--meta", "author=b"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::InvalidOption(option, value))
+                if option == "--meta" && value.starts_with("author")
+        ));
+        let mut args = to_args(&["--meta", "author=a", "--no-version-info"]);
+        let options = BuildOptions::from_args(&mut args).unwrap();
+        assert!(matches!(
+            options.validate(&BuildConfig::default()),
+            Err(BuildError::ConflictingOptions(_, other)) if other == "--no-version-info"
+        ));
+
+        assert_eq!(
+            meta_global("author", "Jo \"J\" Doe\n"),
+            r#"@"_meta.author" = constant [11 x i8] c"Jo \22J\22 Doe\0A", section "_meta", align 1"#
+        );
+    }
 
     #[test]
     fn test_fix_version_file() {
@@ -0,0 +1,101 @@
+use super::build::ContractReport;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+/// A step of the build pipeline, reported to the [`EventSink`] of
+/// [`build_with_events`](super::build::build_with_events) as the build progresses
+#[derive(Debug, Clone)]
+pub enum BuildEvent {
+    /// The wasm build of the contracts started
+    CargoStarted,
+    /// cargo built the wasm module of a workspace crate
+    ArtifactCompiled { crate_name: String, wasm: PathBuf },
+    /// The wasm module is being translated to LLVM IR
+    TranslatingIr { wasm: PathBuf },
+    /// llc is compiling the LLVM IR to an object file
+    CompilingObject { ll: PathBuf },
+    /// The symbols and debug info are being stripped from the object file
+    Stripping { object: PathBuf },
+    /// The build is running in the `--container` image
+    ContainerStarted { image: String },
+    /// A contract was built, `name` has the `--config` profile, the `--profile-size`
+    /// profile or the `--assert-deterministic` build in parentheses
+    ContractBuilt {
+        name: String,
+        report: Box<ContractReport>,
+    },
+    /// A contract failed with `--keep-going`, or the whole build when `contract` is `None`
+    Failed {
+        contract: Option<String>,
+        error: String,
+    },
+    /// All the contracts were built, `failed` has the contracts failing with
+    /// `--keep-going` or differing between the builds of `--assert-deterministic`
+    Finished {
+        built: Vec<String>,
+        failed: Vec<String>,
+    },
+}
+
+/// Receives the [`BuildEvent`]s of a build. The clones send to the same receiver
+#[derive(Clone)]
+pub struct EventSink {
+    callback: Arc<dyn Fn(BuildEvent) + Send + Sync>,
+}
+
+impl EventSink {
+    /// Calls `callback` with each event, on the thread running the build
+    pub fn new<F: Fn(BuildEvent) + Send + Sync + 'static>(callback: F) -> Self {
+        Self {
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Sends each event to `sender`, the events are dropped once the receiver is gone
+    pub fn channel(sender: Sender<BuildEvent>) -> Self {
+        let sender = Mutex::new(sender);
+        Self::new(move |event| {
+            let _ = sender
+                .lock()
+                .expect("No event send should panic")
+                .send(event);
+        })
+    }
+
+    /// Sends `event` to the callback
+    pub fn emit(&self, event: BuildEvent) {
+        (self.callback)(event)
+    }
+}
+
+impl fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventSink")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_sink_channel() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sink = EventSink::channel(sender);
+        sink.clone().emit(BuildEvent::CargoStarted);
+        sink.emit(BuildEvent::Stripping {
+            object: PathBuf::from("counter.o"),
+        });
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert!(matches!(events[0], BuildEvent::CargoStarted));
+        assert!(
+            matches!(&events[1], BuildEvent::Stripping { object } if object.ends_with("counter.o"))
+        );
+
+        drop(receiver);
+        // the build goes on without a receiver
+        sink.emit(BuildEvent::CargoStarted);
+    }
+}
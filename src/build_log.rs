@@ -0,0 +1,174 @@
+use super::build::{format_command, io_error, BuildError};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The `--log-file` of a build: a timestamped record of the commands it runs and of their
+/// output, next to the normal terminal output. The clones write to the same file
+#[derive(Debug, Clone)]
+pub struct BuildLog {
+    file: Arc<Mutex<File>>,
+}
+
+impl BuildLog {
+    /// Creates or truncates `path` and records the command line of the build
+    pub fn create(path: &Path, args: &[String]) -> Result<Self, BuildError> {
+        let file = File::create(path).map_err(io_error("Could not create the log file", path))?;
+        let log = Self {
+            file: Arc::new(Mutex::new(file)),
+        };
+        log.record(&format!(
+            "cargo-l1x {} build {}",
+            env!("CARGO_PKG_VERSION"),
+            args.join(" ")
+        ));
+        Ok(log)
+    }
+
+    /// Appends `text` with the current time. The log never fails the build, a write
+    /// error only loses the record
+    pub fn record(&self, text: &str) {
+        let mut file = self.file.lock().expect("No log write should panic");
+        let _ = writeln!(file, "[{}] {}", utc_timestamp(SystemTime::now()), text);
+    }
+
+    /// Records a line of output of a running command, without its colors and with only
+    /// the last state of the lines redrawn with `\r`, like the progress bar of cargo
+    pub fn output(&self, line: &[u8]) {
+        let line = plain_text(&String::from_utf8_lossy(line));
+        if !line.is_empty() {
+            self.record(&line);
+        }
+    }
+
+    /// Records a finished command with its exit status, stdout and stderr
+    pub fn command(&self, command: &Command, output: &Output) {
+        let mut text = format!("$ {}\n{}", format_command(command), output.status);
+        for (stream, content) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+            let content = String::from_utf8_lossy(content);
+            if !content.trim().is_empty() {
+                text.push_str(&format!("\n--- {}\n{}", stream, content.trim_end()));
+            }
+        }
+        self.record(&text);
+    }
+}
+
+/// `text` without the ANSI escape sequences and the text before its last `\r`
+fn plain_text(text: &str) -> String {
+    let text = text.trim_end_matches(['\r', '\n']);
+    let text = text.rsplit('\r').next().unwrap_or_default();
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\u{1b}' {
+            plain.push(c);
+        } else if chars.next() == Some('[') {
+            // a control sequence ends with a byte in '@'..='~'
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    plain.trim_end().to_string()
+}
+
+/// `time` as `YYYY-MM-DDTHH:MM:SS.mmmZ`
+fn utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = ((seconds / 86_400) as i64, seconds % 86_400);
+    // the civil date of a day count, from Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_millis(951_782_400_250)),
+            "2000-02-29T00:00:00.250Z"
+        );
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_792_065_599)),
+            "2026-10-15T11:59:59.000Z"
+        );
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            plain_text("\u{1b}[1m\u{1b}[32m   Compiling\u{1b}[0m counter v0.1.0\n"),
+            "   Compiling counter v0.1.0"
+        );
+        assert_eq!(
+            plain_text("    Building [==>  ] 3/9\r\u{1b}[K\u{1b}[33mwarning\u{1b}[0m: unused\r\n"),
+            "warning: unused"
+        );
+        assert_eq!(plain_text("    Building [==>  ] 3/9\r\u{1b}[K\n"), "");
+    }
+
+    #[test]
+    fn test_build_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.log");
+        let log = BuildLog::create(&path, &["--skip-llc".to_string()]).unwrap();
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo compiled; echo 'bad input' >&2; exit 1"]);
+        let output = command.output().unwrap();
+        log.clone().command(&command, &output);
+        log.output(b"invalid \xff utf-8\n");
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines[0].starts_with('['));
+        assert!(lines[0].ends_with(" build --skip-llc"));
+        assert!(
+            lines[1].ends_with("] $ sh -c 'echo compiled; echo '\\''bad input'\\'' >&2; exit 1'")
+        );
+        assert_eq!(
+            &lines[2..7],
+            [
+                "exit status: 1",
+                "--- stdout",
+                "compiled",
+                "--- stderr",
+                "bad input"
+            ]
+        );
+        assert!(lines[7].ends_with("] invalid \u{fffd} utf-8"));
+    }
+}
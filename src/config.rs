@@ -0,0 +1,206 @@
+use super::build::{
+    BuildError, BuildOptions, EBPF_STACK_FRAME_SIZE, EXPECTED_RUNTIME_VERSION, OBJECT_FILE_VERSION,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Name of the optional configuration file at the workspace root
+pub const CONFIG_FILE_NAME: &str = "l1x.toml";
+
+/// Values tied to the L1X runtime. The compiled-in defaults can be overridden by the
+/// `[build]` table of `l1x.toml`, which can be overridden by environment variables,
+/// so that objects for a newer runtime can be built without a new cargo-l1x release
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BuildConfig {
+    /// eBPF stack size in bytes, `--stack-size` and its `L1X_STACK_SIZE` take precedence
+    pub stack_frame_size: u32,
+    /// Written to the `_OBJECT_VERSION` global. `L1X_OBJECT_FILE_VERSION`
+    pub object_file_version: i64,
+    /// Written to the `_EXPECTED_RUNTIME_VERSION` global. `L1X_EXPECTED_RUNTIME_VERSION`
+    pub expected_runtime_version: i64,
+    /// Host functions of the runtime in addition to [`KNOWN_HOST_FUNCTIONS`](crate::imports::KNOWN_HOST_FUNCTIONS), see `--strict-imports`
+    pub host_functions: Vec<String>,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            stack_frame_size: EBPF_STACK_FRAME_SIZE,
+            object_file_version: OBJECT_FILE_VERSION,
+            expected_runtime_version: EXPECTED_RUNTIME_VERSION,
+            host_functions: Vec::new(),
+        }
+    }
+}
+
+/// A named bundle of build settings, a `[profiles.<name>]` table of `l1x.toml` built
+/// with `--config <name>`. The values override the `[build]` table and its environment
+/// variables, the command line options take precedence over the profile
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BuildProfile {
+    pub stack_frame_size: Option<u32>,
+    pub object_file_version: Option<i64>,
+    pub expected_runtime_version: Option<i64>,
+    /// BPF processor, see `--cpu`
+    pub cpu: Option<String>,
+    /// BPF target features, see `--mattr`
+    pub mattr: Option<String>,
+}
+
+impl BuildProfile {
+    /// Applies the profile to the `[build]` settings and to the options not given on the
+    /// command line
+    pub fn apply(&self, config: &mut BuildConfig, options: &mut BuildOptions) {
+        if let Some(stack_frame_size) = self.stack_frame_size {
+            config.stack_frame_size = stack_frame_size;
+        }
+        if let Some(object_file_version) = self.object_file_version {
+            config.object_file_version = object_file_version;
+        }
+        if let Some(expected_runtime_version) = self.expected_runtime_version {
+            config.expected_runtime_version = expected_runtime_version;
+        }
+        if options.cpu.is_none() {
+            options.cpu = self.cpu.clone();
+        }
+        if options.mattr.is_none() {
+            options.mattr = self.mattr.clone();
+        }
+    }
+}
+
+/// `true` if `name` can name a profile, it's used as a suffix of the generated files
+pub fn is_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ConfigFile {
+    #[serde(default)]
+    build: BuildConfig,
+    #[serde(default)]
+    profiles: BTreeMap<String, BuildProfile>,
+}
+
+impl ConfigFile {
+    fn read(dir: &Path) -> Result<Self, BuildError> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                Self::parse(&content).map_err(|message| BuildError::ConfigError { path, message })
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(BuildError::IoError {
+                context: "Could not read",
+                path,
+                source: e,
+            }),
+        }
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let file: Self = toml::from_str(content).map_err(|e| e.message().to_string())?;
+        if let Some(name) = file.profiles.keys().find(|name| !is_profile_name(name)) {
+            return Err(format!(
+                "invalid profile name '{}', only letters, digits, '-' and '_' are allowed",
+                name
+            ));
+        }
+        Ok(file)
+    }
+}
+
+/// Reads the `[profiles]` of `l1x.toml` in `dir`, empty if there's no such file
+pub fn load_profiles(dir: &Path) -> Result<BTreeMap<String, BuildProfile>, BuildError> {
+    Ok(ConfigFile::read(dir)?.profiles)
+}
+
+impl BuildConfig {
+    /// Reads `l1x.toml` in `dir` if it exists, then applies the environment overrides
+    pub fn load(dir: &Path) -> Result<Self, BuildError> {
+        let mut config = ConfigFile::read(dir)?.build;
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    #[cfg(test)]
+    fn parse(content: &str) -> Result<Self, String> {
+        ConfigFile::parse(content).map(|file| file.build)
+    }
+
+    fn apply_env(&mut self) -> Result<(), BuildError> {
+        if let Some(value) = env_value("L1X_OBJECT_FILE_VERSION")? {
+            self.object_file_version = value;
+        }
+        if let Some(value) = env_value("L1X_EXPECTED_RUNTIME_VERSION")? {
+            self.expected_runtime_version = value;
+        }
+        Ok(())
+    }
+}
+
+fn env_value<T: std::str::FromStr>(name: &str) -> Result<Option<T>, BuildError> {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => value
+            .parse()
+            .map(Some)
+            .map_err(|_| BuildError::InvalidOption(name.to_string(), value)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(BuildConfig::parse("").unwrap(), BuildConfig::default());
+
+        let config = BuildConfig::parse("[build]\nexpected-runtime-version = 4\n").unwrap();
+        assert_eq!(config.expected_runtime_version, 4);
+        assert_eq!(config.stack_frame_size, EBPF_STACK_FRAME_SIZE);
+
+        assert!(BuildConfig::parse("[build]\nstack-size = 4096\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let file = ConfigFile::parse(
+            "[build]\nstack-frame-size = 4096\n\n\
+             [profiles.prod]\ncpu = \"v3\"\n\n\
+             [profiles.staging]\nstack-frame-size = 16384\nexpected-runtime-version = 4\n",
+        )
+        .unwrap();
+        assert_eq!(file.build.stack_frame_size, 4096);
+        assert_eq!(
+            file.profiles.keys().collect::<Vec<_>>(),
+            ["prod", "staging"]
+        );
+
+        let mut config = file.build.clone();
+        let mut options = BuildOptions {
+            cpu: Some("v2".to_string()),
+            ..Default::default()
+        };
+        file.profiles["staging"].apply(&mut config, &mut options);
+        assert_eq!(config.stack_frame_size, 16384);
+        assert_eq!(config.expected_runtime_version, 4);
+        assert_eq!(options.cpu.as_deref(), Some("v2"));
+
+        let mut options = BuildOptions::default();
+        file.profiles["prod"].apply(&mut config, &mut options);
+        assert_eq!(options.cpu.as_deref(), Some("v3"));
+
+        assert!(ConfigFile::parse("[profiles.\"a.b\"]\n").is_err());
+        assert!(ConfigFile::parse("[profiles.prod]\nstack-size = 1\n").is_err());
+    }
+}
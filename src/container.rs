@@ -0,0 +1,231 @@
+use super::build::BuildError;
+use super::which::which;
+use cargo_metadata::Metadata;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Image with cargo-l1x and the LLVM version it was released with
+pub const DEFAULT_CONTAINER_IMAGE: &str =
+    concat!("l1xfoundation/cargo-l1x:", env!("CARGO_PKG_VERSION"));
+
+/// Digest of [`DEFAULT_CONTAINER_IMAGE`], set by the release build with
+/// `L1X_CONTAINER_IMAGE_DIGEST` once the image is pushed
+const DEFAULT_CONTAINER_IMAGE_DIGEST: Option<&str> = option_env!("L1X_CONTAINER_IMAGE_DIGEST");
+
+/// The default image, pinned to its digest when the release recorded one
+pub fn default_container_image() -> String {
+    match DEFAULT_CONTAINER_IMAGE_DIGEST {
+        Some(digest) => format!("{DEFAULT_CONTAINER_IMAGE}@{digest}"),
+        None => DEFAULT_CONTAINER_IMAGE.to_string(),
+    }
+}
+
+/// Container runtimes in order of preference
+const CONTAINER_RUNTIMES: [&str; 2] = ["docker", "podman"];
+
+/// Runs `cargo l1x build <args>` in `image`
+///
+/// The workspace is mounted at the same path inside the container so the objects end up
+/// in the host `target` directory and the paths printed by the build are valid on the host.
+/// Path dependencies outside the workspace and the cargo registry are mounted too, and the
+/// build runs as the host user so the outputs aren't owned by root
+pub fn build_in_container(
+    args: &[String],
+    metadata: &Metadata,
+    image: &str,
+) -> Result<(), BuildError> {
+    let runtime = CONTAINER_RUNTIMES
+        .iter()
+        .find_map(|runtime| which(runtime.to_string()))
+        .ok_or(BuildError::ContainerRuntimeNotFound)?;
+    let current_dir = std::env::current_dir().map_err(|e| BuildError::ContainerRunError {
+        runtime: runtime.display().to_string(),
+        source: e,
+    })?;
+
+    let workspace_root = metadata.workspace_root.as_std_path();
+    let mut volumes = vec![workspace_root.to_path_buf()];
+    volumes.extend(external_path_dependencies(metadata));
+    let cargo_home = cargo_home();
+    if let Some(cargo_home) = &cargo_home {
+        volumes.extend(
+            ["registry", "git"]
+                .iter()
+                .map(|dir| cargo_home.join(dir))
+                .filter(|dir| dir.is_dir()),
+        );
+    }
+
+    let mut command = container_command(
+        Command::new(&runtime),
+        &ContainerMounts {
+            volumes: &volumes,
+            cargo_home: cargo_home.as_deref(),
+            user: host_user().as_deref(),
+        },
+        &current_dir,
+        image,
+        args,
+    );
+    let status = command
+        .status()
+        .map_err(|e| BuildError::ContainerRunError {
+            runtime: runtime.display().to_string(),
+            source: e,
+        })?;
+    if !status.success() {
+        return Err(BuildError::ContainerBuildError {
+            exit_code: status.code(),
+        });
+    }
+    Ok(())
+}
+
+/// What the container shares with the host
+struct ContainerMounts<'a> {
+    /// Directories mounted at the same path, the workspace first
+    volumes: &'a [PathBuf],
+    /// The host `CARGO_HOME`, whose registry and git checkouts are among the volumes
+    cargo_home: Option<&'a Path>,
+    /// `uid:gid` of the host user
+    user: Option<&'a str>,
+}
+
+/// Directories of the local packages that are outside the workspace, without the ones
+/// nested in another
+fn external_path_dependencies(metadata: &Metadata) -> Vec<PathBuf> {
+    let workspace_root = metadata.workspace_root.as_std_path();
+    let mut dirs: Vec<PathBuf> = metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_none())
+        .filter_map(|package| package.manifest_path.parent())
+        .map(|dir| dir.as_std_path().to_path_buf())
+        .filter(|dir| !dir.starts_with(workspace_root))
+        .collect();
+    dirs.sort();
+    dirs.dedup_by(|dir, parent| dir.starts_with(parent));
+    dirs
+}
+
+/// `$CARGO_HOME`, else `~/.cargo`, `None` without either
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")))
+}
+
+/// `uid:gid` of the current user, `None` where `id` isn't available
+fn host_user() -> Option<String> {
+    let id = |flag: &str| {
+        let output = Command::new("id").arg(flag).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let id = String::from_utf8(output.stdout).ok()?;
+        Some(id.trim().to_string())
+    };
+    Some(format!("{}:{}", id("-u")?, id("-g")?))
+}
+
+fn container_command(
+    mut command: Command,
+    mounts: &ContainerMounts,
+    current_dir: &Path,
+    image: &str,
+    args: &[String],
+) -> Command {
+    command.args(["run", "--rm"]);
+    if let Some(user) = mounts.user {
+        command.arg("--user").arg(user);
+    }
+    for dir in mounts.volumes {
+        let mut volume = dir.as_os_str().to_owned();
+        volume.push(":");
+        volume.push(dir);
+        command.arg("--volume").arg(volume);
+    }
+    if let Some(cargo_home) = mounts.cargo_home {
+        // the registry is found at the mounted path, and it's writable by the host user
+        let mut env = std::ffi::OsString::from("CARGO_HOME=");
+        env.push(cargo_home);
+        command.arg("--env").arg(env);
+    }
+    command.arg("--workdir").arg(current_dir);
+    // the L1X_* defaults apply inside the container too
+    for (name, _) in std::env::vars_os() {
+        let name = name.to_string_lossy();
+        if name.starts_with("L1X_") && name != "L1X_CONTAINER" {
+            command.arg("--env").arg(name.as_ref());
+        }
+    }
+    command
+        .arg(image)
+        .args(["cargo", "l1x", "build"])
+        .args(args);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_command() {
+        let volumes = [
+            PathBuf::from("/work/contracts"),
+            PathBuf::from("/work/shared"),
+            PathBuf::from("/home/dev/.cargo/registry"),
+        ];
+        let command = container_command(
+            Command::new("docker"),
+            &ContainerMounts {
+                volumes: &volumes,
+                cargo_home: Some(Path::new("/home/dev/.cargo")),
+                user: Some("1000:1000"),
+            },
+            Path::new("/work/contracts/counter"),
+            "l1xfoundation/cargo-l1x:0.1.1",
+            &["--no-strip".to_string()],
+        );
+        let all: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        // the L1X_* variables of the test environment are forwarded too
+        let args: Vec<_> = all
+            .iter()
+            .enumerate()
+            .filter(|(i, arg)| {
+                let forwarded = |arg: &String| arg.starts_with("L1X_");
+                let forwarded_env = *arg == "--env" && all.get(i + 1).is_some_and(forwarded);
+                !forwarded(arg) && !forwarded_env
+            })
+            .map(|(_, arg)| arg.as_str())
+            .collect();
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--rm",
+                "--user",
+                "1000:1000",
+                "--volume",
+                "/work/contracts:/work/contracts",
+                "--volume",
+                "/work/shared:/work/shared",
+                "--volume",
+                "/home/dev/.cargo/registry:/home/dev/.cargo/registry",
+                "--env",
+                "CARGO_HOME=/home/dev/.cargo",
+                "--workdir",
+                "/work/contracts/counter",
+                "l1xfoundation/cargo-l1x:0.1.1",
+                "cargo",
+                "l1x",
+                "build",
+                "--no-strip"
+            ]
+        );
+    }
+}
@@ -1,27 +1,922 @@
-use anyhow::anyhow;
+use super::build::shell_quote;
+use super::container::cargo_home;
+use super::exit_code;
+use super::sink::{FileSink, FsSink};
+use super::which::{which, which_optional};
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::fs::File;
 use std::io::Cursor;
-use std::path::PathBuf;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Condvar, Mutex};
 use thiserror::Error;
+use toml_edit::DocumentMut;
 use zip::ZipArchive;
 
+/// File at the template root listing glob patterns of the files not to scaffold
+pub const IGNORE_FILE_NAME: &str = ".l1xignore";
+
+/// File in the created projects pinning the template archive, see [`CreateOptions::lock`]
+pub const TEMPLATE_LOCK_FILE_NAME: &str = ".l1x-template.lock";
+
+/// Shell script at the template root run in the new project by `create --run-hooks`, with
+/// the project name as argument and in `L1X_PROJECT_NAME`
+pub const POST_CREATE_HOOK_NAME: &str = "l1x-post-create";
+
+/// The git hook installed by `create --init-git-hooks`, building the contract before
+/// each commit
+pub const PRE_COMMIT_HOOK_NAME: &str = "pre-commit";
+
+/// File at the template root with the description of the template and the tools it
+/// needs, see [`TemplateManifest`]
+pub const TEMPLATE_MANIFEST_NAME: &str = "l1x-template.toml";
+
+/// The SPDX identifiers `--license` can write the text of
+pub const SUPPORTED_LICENSES: [&str; 3] = ["MIT", "Apache-2.0", "MIT OR Apache-2.0"];
+
+const MIT_LICENSE: &str = include_str!("../licenses/MIT.txt");
+const APACHE_LICENSE: &str = include_str!("../licenses/Apache-2.0.txt");
+
 #[derive(Error, Debug)]
 pub enum CreateError {
-    #[error("filesystem error: {0}")]
-    IoError(anyhow::Error, std::io::Error),
-    #[error("unknown template: {0}")]
+    #[error("filesystem error: {context} '{}': {source}", path.display())]
+    IoError {
+        context: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unknown template: {0}, expected one of: {}", Template::all().map(|t| t.name()).join(", "))]
     UnknownTemplate(String),
     #[error("Connection error: {0}")]
     ConnectionError(#[from] reqwest::Error),
     #[error("Zip error: {0}")]
     ZipError(#[from] zip::result::ZipError),
-    #[error("A directory with this name already exists: {0}")]
+    #[error("A directory with this name already exists and isn't empty: {0}, use --force to create the project in it")]
     DirectoryAlreadyExists(String),
+    #[error("The template archive has no files")]
+    EmptyTemplate,
+    #[error("Unexpected template archive layout: {0}")]
+    UnexpectedArchiveLayout(String),
+    #[error("Template not found: {url} answered HTTP {status}")]
+    TemplateNotFound { url: String, status: u16 },
+    #[error("Template download redirect rejected: {0}")]
+    RedirectRejected(String),
+    #[error("Invalid pattern '{0}' in {IGNORE_FILE_NAME}")]
+    InvalidIgnorePattern(String),
+    #[error("Invalid template subdirectory '{}', expected a relative path inside the archive", .0.display())]
+    InvalidTemplateSubdir(PathBuf),
+    #[error("The template archive has no files in '{}'", .0.display())]
+    TemplateSubdirNotFound(PathBuf),
+    #[error("Invalid l1x-sdk version requirement '{0}': {1}")]
+    InvalidSdkVersion(String, cargo_metadata::semver::Error),
+    #[error("Invalid {}: {message}", path.display())]
+    InvalidLockFile { path: PathBuf, message: String },
+    #[error(
+        "The template '{0}' is not in the lock file, create a project with --lock to record it"
+    )]
+    TemplateNotLocked(String),
+    #[error("The '{template}' template archive changed, the lock file has sha256 {locked}, the downloaded archive {resolved}")]
+    TemplateHashMismatch {
+        template: String,
+        locked: String,
+        resolved: String,
+    },
+    #[error("Unsupported license '{0}', expected one of: {}", SUPPORTED_LICENSES.join(", "))]
+    UnsupportedLicense(String),
+    #[error("The {POST_CREATE_HOOK_NAME} hook of the template failed with {}, the project is left as it is", match .0 { Some(code) => format!("exit code {}", code), None => "a signal".to_string() })]
+    HookFailed(Option<i32>),
+    #[error("Invalid {TEMPLATE_MANIFEST_NAME} in the template: {0}")]
+    InvalidTemplateManifest(String),
+    #[error("The template needs tools that are missing or too old: {}", .0.join(", "))]
+    MissingPrerequisites(Vec<String>),
+    #[error("The files of the template can't replace these paths, a file and a directory clash: {}", .0.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", "))]
+    PathConflicts(Vec<PathBuf>),
+    #[error("Invalid {} in the template: {message}", path.display())]
+    InvalidCargoManifest { path: PathBuf, message: String },
+    #[error("No Cargo.toml of the template has {0} to rewrite")]
+    SubstitutionNotApplied(String),
+}
+
+impl CreateError {
+    /// Stable identifier of the error for scripts, see `--json`
+    pub fn code(&self) -> &'static str {
+        match self {
+            CreateError::IoError { .. } => "io",
+            CreateError::UnknownTemplate(_) => "unknown-template",
+            CreateError::ConnectionError(_) => "connection",
+            CreateError::ZipError(_) => "zip",
+            CreateError::DirectoryAlreadyExists(_) => "directory-already-exists",
+            CreateError::EmptyTemplate => "empty-template",
+            CreateError::UnexpectedArchiveLayout(_) => "unexpected-archive-layout",
+            CreateError::RedirectRejected(_) => "redirect-rejected",
+            CreateError::TemplateNotFound { .. } => "template-not-found",
+            CreateError::InvalidIgnorePattern(_) => "invalid-ignore-pattern",
+            CreateError::InvalidTemplateSubdir(_) => "invalid-template-subdir",
+            CreateError::TemplateSubdirNotFound(_) => "template-subdir-not-found",
+            CreateError::InvalidSdkVersion(..) => "invalid-sdk-version",
+            CreateError::InvalidLockFile { .. } => "invalid-lock-file",
+            CreateError::TemplateNotLocked(_) => "template-not-locked",
+            CreateError::TemplateHashMismatch { .. } => "template-hash-mismatch",
+            CreateError::UnsupportedLicense(_) => "unsupported-license",
+            CreateError::HookFailed(_) => "hook-failed",
+            CreateError::InvalidTemplateManifest(_) => "invalid-template-manifest",
+            CreateError::MissingPrerequisites(_) => "missing-prerequisites",
+            CreateError::PathConflicts(_) => "path-conflicts",
+            CreateError::InvalidCargoManifest { .. } => "invalid-cargo-manifest",
+            CreateError::SubstitutionNotApplied(_) => "substitution-not-applied",
+        }
+    }
+
+    /// The exit code of `cargo l1x` for the error, see [`exit_code`]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CreateError::UnknownTemplate(_)
+            | CreateError::DirectoryAlreadyExists(_)
+            | CreateError::PathConflicts(_)
+            | CreateError::InvalidTemplateSubdir(_)
+            | CreateError::InvalidSdkVersion(..)
+            | CreateError::UnsupportedLicense(_) => exit_code::USAGE,
+            CreateError::ConnectionError(_)
+            | CreateError::ZipError(_)
+            | CreateError::EmptyTemplate
+            | CreateError::UnexpectedArchiveLayout(_)
+            | CreateError::TemplateNotFound { .. }
+            | CreateError::RedirectRejected(_)
+            | CreateError::InvalidIgnorePattern(_)
+            | CreateError::TemplateSubdirNotFound(_)
+            | CreateError::InvalidLockFile { .. }
+            | CreateError::TemplateNotLocked(_)
+            | CreateError::TemplateHashMismatch { .. }
+            | CreateError::HookFailed(_)
+            | CreateError::InvalidTemplateManifest(_)
+            | CreateError::InvalidCargoManifest { .. }
+            | CreateError::SubstitutionNotApplied(_) => exit_code::TEMPLATE,
+            CreateError::MissingPrerequisites(_) => exit_code::TOOLCHAIN,
+            CreateError::IoError { .. } => exit_code::FAILURE,
+        }
+    }
+
+    /// The data of the error as JSON, see `--json`
+    pub fn context(&self) -> serde_json::Value {
+        use serde_json::json;
+        match self {
+            CreateError::IoError { context, path, .. } => {
+                json!({ "context": context, "path": path })
+            }
+            CreateError::UnknownTemplate(template) => json!({ "template": template }),
+            CreateError::ConnectionError(e) => json!({ "url": e.url().map(|url| url.as_str()) }),
+            CreateError::DirectoryAlreadyExists(name) => json!({ "directory": name }),
+            CreateError::PathConflicts(paths) => json!({ "paths": paths }),
+            CreateError::TemplateNotFound { url, status } => {
+                json!({ "url": url, "status": status })
+            }
+            CreateError::UnexpectedArchiveLayout(reason)
+            | CreateError::RedirectRejected(reason) => json!({ "reason": reason }),
+            CreateError::InvalidIgnorePattern(pattern) => json!({ "pattern": pattern }),
+            CreateError::InvalidTemplateSubdir(subdir)
+            | CreateError::TemplateSubdirNotFound(subdir) => json!({ "subdir": subdir }),
+            CreateError::InvalidSdkVersion(version, _) => json!({ "sdk_version": version }),
+            CreateError::InvalidLockFile { path, message }
+            | CreateError::InvalidCargoManifest { path, message } => {
+                json!({ "path": path, "message": message })
+            }
+            CreateError::TemplateNotLocked(template) => json!({ "template": template }),
+            CreateError::UnsupportedLicense(license) => json!({ "license": license }),
+            CreateError::HookFailed(status) => json!({ "exit_code": status }),
+            CreateError::InvalidTemplateManifest(message) => json!({ "message": message }),
+            CreateError::MissingPrerequisites(tools) => json!({ "tools": tools }),
+            CreateError::SubstitutionNotApplied(entry) => json!({ "entry": entry }),
+            CreateError::TemplateHashMismatch {
+                template,
+                locked,
+                resolved,
+            } => json!({ "template": template, "locked": locked, "resolved": resolved }),
+            CreateError::ZipError(_) | CreateError::EmptyTemplate => json!({}),
+        }
+    }
+}
+
+/// Returns a `map_err` adapter creating a [`CreateError::IoError`] for `path`
+pub(crate) fn io_error<P: AsRef<Path>>(
+    context: &'static str,
+    path: P,
+) -> impl FnOnce(std::io::Error) -> CreateError {
+    let path = path.as_ref().to_path_buf();
+    move |source| CreateError::IoError {
+        context,
+        path,
+        source,
+    }
+}
+
+/// GitHub redirects branch archives to codeload.github.com once, leave some headroom
+const MAX_TEMPLATE_REDIRECTS: usize = 5;
+
+/// Only follows a limited number of redirects, and only to HTTPS URLs
+fn redirect_policy() -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() > MAX_TEMPLATE_REDIRECTS {
+            let message = format!("more than {} redirects", MAX_TEMPLATE_REDIRECTS);
+            attempt.error(message)
+        } else if attempt.url().scheme() != "https" {
+            let message = format!("redirect to non-HTTPS URL {}", attempt.url());
+            attempt.error(message)
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Default of [`CreateOptions::net_concurrency`], small enough to stay clear of the GitHub
+/// rate limits
+pub const DEFAULT_NET_CONCURRENCY: NonZeroUsize = match NonZeroUsize::new(4) {
+    Some(concurrency) => concurrency,
+    None => unreachable!(),
+};
+
+/// HTTP client running at most `concurrency` requests at once, shared by the threads
+/// downloading templates
+pub struct HttpClient {
+    client: reqwest::blocking::Client,
+    permits: Mutex<usize>,
+    released: Condvar,
+    /// Where the interrupted downloads are kept, see [`HttpClient::download`]
+    downloads_dir: Option<PathBuf>,
+}
+
+/// A request slot of an [`HttpClient`], given back when dropped
+struct Permit<'a>(&'a HttpClient);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.permits.lock().expect("No request should panic") += 1;
+        self.0.released.notify_one();
+    }
+}
+
+impl HttpClient {
+    pub fn new(concurrency: NonZeroUsize) -> Result<Self, CreateError> {
+        let client = reqwest::blocking::Client::builder()
+            .redirect(redirect_policy())
+            .build()?;
+        Ok(Self {
+            client,
+            permits: Mutex::new(concurrency.get()),
+            released: Condvar::new(),
+            downloads_dir: cargo_home().map(|home| home.join(PARTIAL_DOWNLOADS_DIR)),
+        })
+    }
+
+    /// Keeps the interrupted downloads in `dir` instead of [`PARTIAL_DOWNLOADS_DIR`]
+    pub fn with_downloads_dir(mut self, dir: PathBuf) -> Self {
+        self.downloads_dir = Some(dir);
+        self
+    }
+
+    /// Waits until fewer than `concurrency` requests are running
+    fn acquire(&self) -> Permit<'_> {
+        let permits = self.permits.lock().expect("No request should panic");
+        let mut permits = self
+            .released
+            .wait_while(permits, |permits| *permits == 0)
+            .expect("No request should panic");
+        *permits -= 1;
+        Permit(self)
+    }
+
+    /// Downloads `url` through a file in the downloads directory, [`PARTIAL_DOWNLOADS_DIR`]
+    /// by default, left there when the
+    /// transfer breaks off. The next download of `url` continues it with a range request
+    /// if the server sent a strong `ETag` and `Accept-Ranges: bytes`, and restarts it
+    /// when the server doesn't support ranges or the file changed. A resumed archive is
+    /// only used when the CRC-32 of all its files verify, otherwise it's downloaded again.
+    /// Without a private downloads directory, or while another `create` downloads the same
+    /// URL, the download isn't resumable
+    pub fn download(&self, url: &str) -> Result<Vec<u8>, CreateError> {
+        let _permit = self.acquire();
+        let partial = self.downloads_dir.as_deref().and_then(|dir| {
+            let path = partial_download_path(dir, url)?;
+            PartialDownload::lock(&path)
+        });
+        match partial {
+            Some(mut partial) => self.download_to(url, &mut partial),
+            None => {
+                let response = self.get(url, None)?;
+                let response_body = response.bytes().map_err(connection_error)?;
+                Ok(response_body.to_vec())
+            }
+        }
+    }
+
+    /// Sends the GET request of `url`, with a range request after `resume` bytes of the
+    /// file with its `ETag`. The errors of the server are returned
+    fn get(
+        &self,
+        url: &str,
+        resume: Option<(u64, &str)>,
+    ) -> Result<reqwest::blocking::Response, CreateError> {
+        let mut request = self.client.get(url);
+        if let Some((offset, etag)) = resume {
+            // `If-Range` makes the server send the whole file when it has changed
+            request = request
+                .header(header::RANGE, format!("bytes={}-", offset))
+                .header(header::IF_RANGE, etag);
+        }
+        let response = request.send().map_err(connection_error)?;
+        // the body of an error is an HTML page, not an archive
+        if !response.status().is_success()
+            && response.status() != reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+        {
+            return Err(CreateError::TemplateNotFound {
+                url: url.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+        Ok(response)
+    }
+
+    fn download_to(
+        &self,
+        url: &str,
+        partial: &mut PartialDownload,
+    ) -> Result<Vec<u8>, CreateError> {
+        let resume = partial.resumable();
+        let mut response = self.get(
+            url,
+            resume
+                .as_ref()
+                .map(|(offset, etag)| (*offset, etag.as_str())),
+        )?;
+        if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            // the partial file is out of date, start over
+            partial.discard()?;
+            response = self.get(url, None)?;
+        }
+        let resumed = resume.as_ref().is_some_and(|(offset, etag)| {
+            response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+                && response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|range| range.to_str().ok())
+                    .is_some_and(|range| range.starts_with(&format!("bytes {}-", offset)))
+                && response
+                    .headers()
+                    .get(header::ETAG)
+                    .is_none_or(|response_etag| response_etag == etag.as_str())
+        });
+        if resumed {
+            partial.append()?;
+            println!(
+                "⏬ Resuming the download of {} after {} bytes",
+                url,
+                resume.as_ref().map_or(0, |(offset, _)| *offset)
+            );
+        } else {
+            if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+                // a range of another version of the file
+                partial.discard()?;
+                response = self.get(url, None)?;
+            }
+            // a fresh download, resumable next time only with a strong validator
+            let etag = response
+                .headers()
+                .get(header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .filter(|etag| !etag.starts_with("W/"));
+            let accepts_ranges = response
+                .headers()
+                .get(header::ACCEPT_RANGES)
+                .is_some_and(|ranges| ranges == "bytes");
+            partial.start(etag.filter(|_| accepts_ranges))?;
+        }
+        if let Err(e) = response.copy_to(&mut partial.file) {
+            let received = partial.len();
+            if partial.etag_file.is_file() && received > 0 {
+                eprintln!(
+                    "⚠️  The download of {} stopped after {} bytes, run the command again to resume it",
+                    url, received
+                );
+            }
+            return Err(connection_error(e));
+        }
+        let content = partial.read()?;
+        if resumed && !is_valid_archive(&content) {
+            eprintln!(
+                "⚠️  The resumed download of {} doesn't verify, downloading it again",
+                url
+            );
+            partial.discard()?;
+            return self.download_to(url, partial);
+        }
+        partial.remove();
+        Ok(content)
+    }
+}
+
+fn connection_error(e: reqwest::Error) -> CreateError {
+    match std::error::Error::source(&e) {
+        Some(reason) if e.is_redirect() => CreateError::RedirectRejected(reason.to_string()),
+        _ => CreateError::ConnectionError(e),
+    }
+}
+
+/// `true` when `content` is a zip archive whose files all match their CRC-32
+fn is_valid_archive(content: &[u8]) -> bool {
+    let Ok(mut archive) = ZipArchive::new(Cursor::new(content)) else {
+        return false;
+    };
+    (0..archive.len()).all(|i| {
+        archive
+            .by_index(i)
+            .is_ok_and(|mut file| std::io::copy(&mut file, &mut std::io::sink()).is_ok())
+    })
+}
+
+/// Subdirectory of the cargo home holding the interrupted downloads, only accessible by
+/// the user
+pub const PARTIAL_DOWNLOADS_DIR: &str = "l1x/downloads";
+
+/// Creates the private directory `dir` of the interrupted downloads with mode 0700.
+/// `None` when it can't be made private, e.g. when it belongs to another user
+fn create_private_dir(dir: &Path) -> Option<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        builder.mode(0o700);
+        builder.create(dir).ok()?;
+        // only the owner can change the mode of an existing directory
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).ok()?;
+    }
+    #[cfg(not(unix))]
+    builder.create(dir).ok()?;
+    Some(())
+}
+
+/// The file in the downloads directory `dir` an [`HttpClient`] downloads `url` to, named
+/// after the hash of the URL
+fn partial_download_path(dir: &Path, url: &str) -> Option<PathBuf> {
+    create_private_dir(dir)?;
+    let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+    Some(dir.join(format!("{}.part", &hash[..16])))
+}
+
+/// A partial download and the `ETag` of the file it's a part of, locked while a
+/// download writes to it
+struct PartialDownload {
+    path: PathBuf,
+    etag_file: PathBuf,
+    file: fs::File,
+}
+
+impl PartialDownload {
+    /// Opens the file at `path` with an exclusive lock, `None` when another process holds
+    /// it or it can't be opened
+    fn lock(path: &Path) -> Option<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)
+            .ok()?;
+        file.try_lock().ok()?;
+        Some(Self {
+            path: path.to_path_buf(),
+            etag_file: path.with_extension("etag"),
+            file,
+        })
+    }
+
+    fn len(&self) -> u64 {
+        self.file.metadata().map_or(0, |metadata| metadata.len())
+    }
+
+    /// The size and `ETag` of the part downloaded before, to resume from
+    fn resumable(&self) -> Option<(u64, String)> {
+        let offset = self.len();
+        let etag = fs::read_to_string(&self.etag_file).ok()?;
+        (offset > 0).then_some((offset, etag))
+    }
+
+    /// Empties the file for a download from the start, resumable with `etag`. Otherwise
+    /// the file is written to after its content
+    fn start(&mut self, etag: Option<&str>) -> Result<(), CreateError> {
+        use std::io::{Seek, SeekFrom};
+        self.file
+            .set_len(0)
+            .and_then(|()| self.file.seek(SeekFrom::Start(0)))
+            .map_err(io_error("Couldn't write the partial download", &self.path))?;
+        match etag {
+            Some(etag) => fs::write(&self.etag_file, etag).map_err(io_error(
+                "Couldn't write the partial download",
+                &self.etag_file,
+            ))?,
+            None => {
+                let _ = fs::remove_file(&self.etag_file);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the rest of the download after the part downloaded before
+    fn append(&mut self) -> Result<(), CreateError> {
+        use std::io::{Seek, SeekFrom};
+        self.file
+            .seek(SeekFrom::End(0))
+            .map_err(io_error("Couldn't write the partial download", &self.path))?;
+        Ok(())
+    }
+
+    /// Drops the downloaded part, the next download starts over
+    fn discard(&mut self) -> Result<(), CreateError> {
+        self.start(None)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, CreateError> {
+        use std::io::{Seek, SeekFrom};
+        let mut content = Vec::new();
+        self.file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.read_to_end(&mut content))
+            .map_err(io_error("Couldn't read the download", &self.path))?;
+        Ok(content)
+    }
+
+    /// Removes the files of a finished download
+    fn remove(&self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_file(&self.etag_file);
+    }
+}
+
+/// A template archive with its source and checksum, see [`prefetch_templates`]
+#[derive(Debug, Clone)]
+pub struct TemplateArchive {
+    pub template: Template,
+    pub content: Vec<u8>,
+    pub resolved: LockedTemplate,
+}
+
+impl TemplateArchive {
+    fn new(template: Template, content: Vec<u8>) -> Self {
+        let (repository, reference) = template.source();
+        // a branch moves, the commit pins the archive
+        let reference = match template.url() {
+            Some(_) => archive_commit(&content).unwrap_or(reference),
+            None => reference,
+        };
+        let resolved = LockedTemplate {
+            name: template.name().to_string(),
+            repository,
+            reference,
+            sha256: format!("{:x}", Sha256::digest(&content)),
+        };
+        Self {
+            template,
+            content,
+            resolved,
+        }
+    }
+}
+
+/// A template and its archive, or the reason it couldn't be fetched
+pub type PrefetchedTemplate = (Template, Result<TemplateArchive, CreateError>);
+
+/// Fetches the archives of `templates` on up to `concurrency` threads sharing one
+/// [`HttpClient`], the ones in the offline bundle `cache_dir` are read from it. A template
+/// listed several times is fetched once, the results are in the order the templates first
+/// appear. Use [`create_from_archive`] to extract them
+pub fn prefetch_templates(
+    templates: &[Template],
+    cache_dir: Option<&Path>,
+    concurrency: NonZeroUsize,
+) -> Result<Vec<PrefetchedTemplate>, CreateError> {
+    let mut unique: Vec<Template> = Vec::new();
+    for template in templates {
+        if !unique.contains(template) {
+            unique.push(*template);
+        }
+    }
+    let client = HttpClient::new(concurrency)?;
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: Vec<_> = unique.iter().map(|_| std::sync::Mutex::new(None)).collect();
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.get().min(unique.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(template) = unique.get(index) else {
+                    break;
+                };
+                let content = match (template.local_zip_template(cache_dir), template.url()) {
+                    (Ok(Some(content)), _) => Ok(content),
+                    (Ok(None), Some(url)) => client.download(url),
+                    (Ok(None), None) => unreachable!("The bundled template is always at hand"),
+                    (Err(e), _) => Err(e),
+                };
+                let result = content.map(|content| TemplateArchive::new(*template, content));
+                *results[index].lock().expect("No download should panic") = Some(result);
+            });
+        }
+    });
+    Ok(unique
+        .into_iter()
+        .zip(results)
+        .map(|(template, result)| {
+            let result = result.into_inner().expect("No download should panic");
+            (template, result.expect("Every template should be fetched"))
+        })
+        .collect())
+}
+
+/// Options that customize a project generated by [`create_with_options`]
+#[derive(Debug, Default, Clone)]
+pub struct CreateOptions {
+    /// Version requirement for the `l1x-sdk` dependency, e.g. `0.3.1` or `=0.3.0`
+    pub sdk_version: Option<String>,
+    /// Run `cargo generate-lockfile` in the new project to pin its dependencies, and
+    /// record the template archive in its [`TEMPLATE_LOCK_FILE_NAME`]
+    pub lock: bool,
+    /// Only extract this directory of the template archive, for repositories hosting
+    /// several templates, e.g. `templates/token`
+    pub template_subdir: Option<PathBuf>,
+    /// Fail unless the template archive matches this [`TEMPLATE_LOCK_FILE_NAME`], e.g. the
+    /// one of a project created with [`lock`](Self::lock)
+    pub locked: Option<PathBuf>,
+    /// SPDX identifier of the project license, one of [`SUPPORTED_LICENSES`]. Sets the
+    /// `license` field of `Cargo.toml` and writes the license text
+    pub license: Option<String>,
+    /// Read-only directory of pre-downloaded template archives named `<template>.zip`,
+    /// used instead of downloading them
+    pub template_cache: Option<PathBuf>,
+    /// Maximum number of concurrent HTTP requests, [`DEFAULT_NET_CONCURRENCY`] if `None`
+    pub net_concurrency: Option<NonZeroUsize>,
+    /// Run the [`POST_CREATE_HOOK_NAME`] script of the template in the new project. Off by
+    /// default, the templates are downloaded code
+    pub run_hooks: bool,
+    /// Create the project in an existing directory that isn't empty, overwriting the files
+    /// the template has. An empty directory is always accepted
+    pub force: bool,
+    /// Install a git [`PRE_COMMIT_HOOK_NAME`] hook building the contract when the project
+    /// is in a git repository
+    pub init_git_hooks: bool,
+}
+
+/// The source and checksum of a template archive, an entry of [`TEMPLATE_LOCK_FILE_NAME`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedTemplate {
+    pub name: String,
+    /// The repository the archive is downloaded from, `cargo-l1x` for the bundled template
+    pub repository: String,
+    /// The commit of the archive, else its git ref, the cargo-l1x version for the bundled
+    /// template
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TemplateLock {
+    #[serde(default, rename = "template")]
+    templates: Vec<LockedTemplate>,
 }
 
-#[derive(Debug, Default)]
+impl TemplateLock {
+    fn read(path: &Path) -> Result<Self, CreateError> {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).map_err(|e| CreateError::InvalidLockFile {
+                path: path.to_path_buf(),
+                message: e.message().to_string(),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(io_error("Couldn't read", path)(e)),
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<(), CreateError> {
+        let content = toml::to_string(self).expect("Template lock should serialize");
+        fs::write(
+            path,
+            format!(
+                "# Generated by cargo-l1x, the template archives `cargo l1x create` uses\n\n{}",
+                content
+            ),
+        )
+        .map_err(io_error("Couldn't write", path))
+    }
+}
+
+/// Checks `resolved` against its entry in the lock file at `path`
+fn check_template_lock(path: &Path, resolved: &LockedTemplate) -> Result<(), CreateError> {
+    let lock = TemplateLock::read(path)?;
+    match lock
+        .templates
+        .iter()
+        .find(|entry| entry.name == resolved.name)
+    {
+        Some(entry) if entry == resolved => Ok(()),
+        Some(entry) => Err(CreateError::TemplateHashMismatch {
+            template: resolved.name.clone(),
+            locked: entry.sha256.clone(),
+            resolved: resolved.sha256.clone(),
+        }),
+        None => Err(CreateError::TemplateNotLocked(resolved.name.clone())),
+    }
+}
+
+/// The commit a GitHub archive was made from, `git archive` writes it as the zip comment
+fn archive_commit(content: &[u8]) -> Option<String> {
+    let archive = ZipArchive::new(Cursor::new(content)).ok()?;
+    let comment = std::str::from_utf8(archive.comment()).ok()?.trim();
+    (comment.len() == 40 && comment.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| comment.to_ascii_lowercase())
+}
+
+/// Rewrites the value of `key` in the generated `Cargo.toml`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Substitution {
+    key: String,
+    value: String,
+    /// Table the key is added to when the manifest doesn't have it
+    table: Option<&'static str>,
+}
+
+impl Substitution {
+    /// Replaces the version of the `name` dependency, keeping other fields of an inline table
+    pub fn dependency_version(name: &str, version: &str) -> Self {
+        Self {
+            key: name.to_string(),
+            value: version.to_string(),
+            table: None,
+        }
+    }
+
+    /// Sets the string field `key` of the `[package]` table, adding it if it's missing
+    pub fn package_field(key: &str, value: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.to_string(),
+            table: Some("package"),
+        }
+    }
+
+    /// What the substitution rewrites, for the error when no manifest has it
+    fn entry(&self) -> String {
+        match self.table {
+            Some(table) => format!("a [{}] table", table),
+            None => format!("an '{}' dependency", self.key),
+        }
+    }
+
+    /// The rewritten manifest, `None` when it has nothing to rewrite. A field inherited
+    /// from the workspace, e.g. `license.workspace = true`, is set in the
+    /// `[workspace.package]` or `[workspace.dependencies]` of the root manifest instead
+    pub(crate) fn apply(&self, content: &str) -> Result<Option<String>, toml_edit::TomlError> {
+        let mut manifest: DocumentMut = content.parse()?;
+        let found = match self.table {
+            Some(table) => self.set_field(&mut manifest, table),
+            None => self.set_dependency_version(&mut manifest),
+        };
+        Ok(found.then(|| manifest.to_string()))
+    }
+
+    fn set_field(&self, manifest: &mut DocumentMut, table: &str) -> bool {
+        let mut found = false;
+        if let Some(workspace) = workspace_table(manifest, table) {
+            workspace.insert(&self.key, toml_edit::value(&self.value));
+            found = true;
+        }
+        if let Some(package) = manifest
+            .get_mut(table)
+            .and_then(|item| item.as_table_like_mut())
+        {
+            if !package.get(&self.key).is_some_and(is_inherited) {
+                set_value(package, &self.key, &self.value);
+            }
+            found = true;
+        }
+        found
+    }
+
+    fn set_dependency_version(&self, manifest: &mut DocumentMut) -> bool {
+        let mut found = false;
+        if let Some(dependencies) = workspace_table(manifest, "dependencies") {
+            found |= set_version(dependencies, &self.key, &self.value);
+        }
+        for table in ["dependencies", "dev-dependencies", "build-dependencies"] {
+            if let Some(dependencies) = manifest
+                .get_mut(table)
+                .and_then(|item| item.as_table_like_mut())
+            {
+                match dependencies.get(&self.key) {
+                    Some(dependency) if is_inherited(dependency) => found = true,
+                    Some(_) => found |= set_version(dependencies, &self.key, &self.value),
+                    None => {}
+                }
+            }
+        }
+        found
+    }
+}
+
+/// The `[workspace.<name>]` table of a root manifest
+fn workspace_table<'a>(
+    manifest: &'a mut DocumentMut,
+    name: &str,
+) -> Option<&'a mut dyn toml_edit::TableLike> {
+    manifest
+        .get_mut("workspace")?
+        .get_mut(name)?
+        .as_table_like_mut()
+}
+
+/// `true` for a `{ workspace = true }` entry
+fn is_inherited(item: &toml_edit::Item) -> bool {
+    item.as_table_like()
+        .and_then(|table| table.get("workspace"))
+        .and_then(|workspace| workspace.as_bool())
+        == Some(true)
+}
+
+/// Sets `key` to the string `value`, keeping the comments around an existing value
+fn set_value(table: &mut dyn toml_edit::TableLike, key: &str, value: &str) {
+    match table.get_mut(key).and_then(|item| item.as_value_mut()) {
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = value.into();
+            *existing.decor_mut() = decor;
+        }
+        None => {
+            table.insert(key, toml_edit::value(value));
+        }
+    }
+}
+
+/// Sets the version of the `name` dependency of `dependencies`, `false` when it isn't there
+fn set_version(dependencies: &mut dyn toml_edit::TableLike, name: &str, version: &str) -> bool {
+    let Some(dependency) = dependencies.get_mut(name) else {
+        return false;
+    };
+    match dependency.as_table_like_mut() {
+        // l1x-sdk = { version = "0.3.1", features = [...] }
+        Some(table) => set_value(table, "version", version),
+        None => set_value(dependencies, name, version),
+    }
+    true
+}
+
+impl CreateOptions {
+    /// The validated `template_subdir`, empty for the whole archive
+    fn template_subdir(&self) -> Result<PathBuf, CreateError> {
+        let Some(subdir) = &self.template_subdir else {
+            return Ok(PathBuf::new());
+        };
+        let is_normal = subdir
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)));
+        if is_normal && !subdir.as_os_str().is_empty() {
+            Ok(subdir.clone())
+        } else {
+            Err(CreateError::InvalidTemplateSubdir(subdir.clone()))
+        }
+    }
+
+    fn substitutions(&self) -> Result<Vec<Substitution>, CreateError> {
+        let mut substitutions = Vec::new();
+        if let Some(sdk_version) = &self.sdk_version {
+            cargo_metadata::semver::VersionReq::parse(sdk_version)
+                .map_err(|e| CreateError::InvalidSdkVersion(sdk_version.clone(), e))?;
+            substitutions.push(Substitution::dependency_version("l1x-sdk", sdk_version));
+        }
+        if let Some(license) = &self.license {
+            license_files(license, "")?;
+            substitutions.push(Substitution::package_field("license", license));
+        }
+        Ok(substitutions)
+    }
+}
+
+/// The license files of a project under `license`, `project` is the copyright holder of
+/// the MIT text. Dual licensed projects get a `LICENSE-MIT` and a `LICENSE-APACHE`
+fn license_files(license: &str, project: &str) -> Result<Vec<(&'static str, String)>, CreateError> {
+    let mit = || format!("Copyright (c) The {} authors\n\n{}", project, MIT_LICENSE);
+    match license {
+        "MIT" => Ok(vec![("LICENSE", mit())]),
+        "Apache-2.0" => Ok(vec![("LICENSE", APACHE_LICENSE.to_string())]),
+        "MIT OR Apache-2.0" | "Apache-2.0 OR MIT" => Ok(vec![
+            ("LICENSE-MIT", mit()),
+            ("LICENSE-APACHE", APACHE_LICENSE.to_string()),
+        ]),
+        _ => Err(CreateError::UnsupportedLicense(license.to_string())),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub enum Template {
     #[default]
     LocalDefault,
@@ -33,114 +928,1704 @@ pub enum Template {
 impl FromStr for Template {
     type Err = CreateError;
 
+    /// Accepts the names case-insensitively, with `-` or `_`, an optional `-template`
+    /// suffix and a few aliases, e.g. `FT`, `nft-template` or `fungible`
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "local_default" => Ok(Template::LocalDefault),
+        let name = s.to_lowercase().replace('-', "_");
+        let name = name.strip_suffix("_template").unwrap_or(&name);
+        match name {
+            "local_default" | "local" => Ok(Template::LocalDefault),
             "default" => Ok(Template::Default),
-            "ft" => Ok(Template::Ft),
-            "nft" => Ok(Template::Nft),
+            "ft" | "fungible" | "fungible_token" => Ok(Template::Ft),
+            "nft" | "non_fungible" | "nonfungible" | "non_fungible_token" => Ok(Template::Nft),
             _ => Err(CreateError::UnknownTemplate(s.to_string())),
         }
     }
 }
 
 impl Template {
-    fn get_zip_template(&self) -> Result<ZipArchive<Cursor<Vec<u8>>>, CreateError> {
+    pub fn all() -> [Template; 4] {
+        [
+            Template::LocalDefault,
+            Template::Default,
+            Template::Ft,
+            Template::Nft,
+        ]
+    }
+
+    /// The name accepted by `--template`
+    pub fn name(&self) -> &'static str {
         match self {
-            Template::LocalDefault => {
-                let content =
-                    include_bytes!(concat!(env!("OUT_DIR"), "/default_template.zip")).to_vec();
-                let reader = std::io::Cursor::new(content);
-                let zip = ZipArchive::new(reader)?;
-                Ok(zip)
-            }
-            Template::Default => {
-                let response_body = reqwest::blocking::get("https://github.com/L1X-Foundation/cargo-l1x-templates/archive/refs/heads/default.zip")?.bytes()?;
-                let reader = Cursor::new(response_body.to_vec());
-                let zip = ZipArchive::new(reader)?;
-                Ok(zip)
+            Template::LocalDefault => "local_default",
+            Template::Default => "default",
+            Template::Ft => "ft",
+            Template::Nft => "nft",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Template::LocalDefault => "Counter contract bundled with cargo-l1x, works offline",
+            Template::Default => "Counter contract from the templates repository",
+            Template::Ft => "Fungible token contract",
+            Template::Nft => "Non-fungible token contract",
+        }
+    }
+
+    /// The description of the template's [`TemplateManifest`] when its archive is at hand
+    /// without a download, bundled or in `cache_dir`, else [`Template::description`]
+    pub fn describe(&self, cache_dir: Option<&Path>) -> String {
+        self.local_zip_template(cache_dir)
+            .ok()
+            .flatten()
+            .and_then(|archive| TemplateManifest::from_archive(archive).ok().flatten())
+            .and_then(|manifest| manifest.description)
+            .unwrap_or_else(|| self.description().to_string())
+    }
+
+    /// The repository and git ref of the archive, `cargo-l1x` and its version for the
+    /// bundled template
+    pub fn source(&self) -> (String, String) {
+        match self.url().and_then(|url| url.split_once("/archive/")) {
+            Some((repository, reference)) => (
+                repository.to_string(),
+                reference.trim_end_matches(".zip").to_string(),
+            ),
+            None => (
+                "cargo-l1x".to_string(),
+                env!("CARGO_PKG_VERSION").to_string(),
+            ),
+        }
+    }
+
+    /// The archive the template is downloaded from, `None` for the bundled template
+    pub fn url(&self) -> Option<&'static str> {
+        match self {
+            Template::LocalDefault => None,
+            Template::Default => Some("https://github.com/L1X-Foundation/cargo-l1x-templates/archive/refs/heads/default.zip"),
+            Template::Ft => Some("https://github.com/L1X-Foundation/cargo-l1x-templates/archive/refs/heads/ft.zip"),
+            Template::Nft => Some("https://github.com/L1X-Foundation/cargo-l1x-templates/archive/refs/heads/nft.zip"),
+        }
+    }
+
+    /// The bundled archive, else `<name>.zip` of the offline bundle `cache_dir` if it's
+    /// there, else the downloaded archive
+    fn get_zip_template(
+        &self,
+        cache_dir: Option<&Path>,
+        net_concurrency: NonZeroUsize,
+    ) -> Result<Vec<u8>, CreateError> {
+        match (self.local_zip_template(cache_dir)?, self.url()) {
+            (Some(content), _) => Ok(content),
+            (None, Some(url)) => HttpClient::new(net_concurrency)?.download(url),
+            (None, None) => unreachable!("The bundled template is always at hand"),
+        }
+    }
+
+    /// The bundled archive, else `<name>.zip` of the offline bundle `cache_dir`, `None`
+    /// when the archive has to be downloaded
+    fn local_zip_template(&self, cache_dir: Option<&Path>) -> Result<Option<Vec<u8>>, CreateError> {
+        if self.url().is_none() {
+            return Ok(Some(
+                include_bytes!(concat!(env!("OUT_DIR"), "/default_template.zip")).to_vec(),
+            ));
+        }
+        let Some(cache_dir) = cache_dir else {
+            return Ok(None);
+        };
+        let path = cache_dir.join(format!("{}.zip", self.name()));
+        match fs::read(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error("Couldn't read the template archive", &path)(e)),
+        }
+    }
+
+    /// Checks that all entries are in a single top-level directory, which is stripped
+    /// when extracting, and that there is at least one file
+    fn top_level_dir(archive: &mut ZipArchive<Cursor<Vec<u8>>>) -> Result<PathBuf, CreateError> {
+        let mut top_level_dir: Option<PathBuf> = None;
+        let mut has_files = false;
+        for i in 0..archive.len() {
+            let file = archive.by_index(i)?;
+            let file_path = file.mangled_name();
+            let mut components = file_path.components();
+            let Some(first) = components.next() else {
+                continue;
+            };
+            if !file.is_dir() {
+                if components.next().is_none() {
+                    return Err(CreateError::UnexpectedArchiveLayout(format!(
+                        "'{}' is not in a top-level directory",
+                        file.name()
+                    )));
+                }
+                has_files = true;
             }
-            Template::Ft => {
-                let response_body = reqwest::blocking::get("https://github.com/L1X-Foundation/cargo-l1x-templates/archive/refs/heads/ft.zip")?.bytes()?;
-                let reader = Cursor::new(response_body.to_vec());
-                let zip = ZipArchive::new(reader)?;
-                Ok(zip)
+            match &top_level_dir {
+                None => top_level_dir = Some(PathBuf::from(first.as_os_str())),
+                Some(dir) if dir.as_os_str() != first.as_os_str() => {
+                    return Err(CreateError::UnexpectedArchiveLayout(format!(
+                        "several top-level directories: '{}' and '{}'",
+                        dir.display(),
+                        first.as_os_str().to_string_lossy()
+                    )));
+                }
+                Some(_) => {}
             }
-            Template::Nft => {
-                let response_body = reqwest::blocking::get("https://github.com/L1X-Foundation/cargo-l1x-templates/archive/refs/heads/nft.zip")?.bytes()?;
-                let reader = Cursor::new(response_body.to_vec());
-                let zip = ZipArchive::new(reader)?;
-                Ok(zip)
+        }
+        match top_level_dir {
+            Some(dir) if has_files => Ok(dir),
+            _ => Err(CreateError::EmptyTemplate),
+        }
+    }
+
+    /// Reads the patterns of the `.l1xignore` at the template root, if any
+    fn ignore_patterns(
+        archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+        template_dir: &Path,
+    ) -> Result<Vec<glob::Pattern>, CreateError> {
+        let name = template_dir
+            .join(IGNORE_FILE_NAME)
+            .to_string_lossy()
+            .into_owned();
+        let mut content = String::new();
+        match archive.by_name(&name) {
+            Ok(mut file) => {
+                file.read_to_string(&mut content)
+                    .map_err(io_error("Couldn't read file", &name))?;
             }
+            Err(zip::result::ZipError::FileNotFound) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
         }
+
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                glob::Pattern::new(line.trim_matches('/'))
+                    .map_err(|_| CreateError::InvalidIgnorePattern(line.to_string()))
+            })
+            .collect()
     }
 
-    fn unzip(
+    /// Extracts the files of the archive under `subdir` of its top-level directory, both
+    /// prefixes are stripped. An empty `subdir` extracts the whole template
+    fn unzip<S: FileSink>(
         archive: &mut ZipArchive<Cursor<Vec<u8>>>,
-        destination_path: &PathBuf,
+        destination_path: &Path,
+        subdir: &Path,
+        substitutions: &[Substitution],
+        sink: &mut S,
     ) -> Result<(), CreateError> {
-        let mut top_level_dir_name = None;
+        let template_dir = Self::top_level_dir(archive)?.join(subdir);
+        let ignored = Self::ignore_patterns(archive, &template_dir)?;
 
+        let mut has_files = false;
+        let mut applied = vec![false; substitutions.len()];
         for i in 0..archive.len() {
             let mut file = archive.by_index(i)?;
-            let mut file_path = file.mangled_name();
-            if let Some(top_level_dir_name) = top_level_dir_name.as_ref() {
-                if let Ok(stripped_file_path) = file_path.strip_prefix(top_level_dir_name) {
-                    file_path = stripped_file_path.to_owned();
-                }
+            let file_path = file.mangled_name();
+            let Ok(file_path) = file_path.strip_prefix(&template_dir) else {
+                continue; // Outside of the selected subdirectory
+            };
+            if file_path.as_os_str().is_empty() {
+                continue; // Skip the template directory
+            }
+            if file_path == Path::new(IGNORE_FILE_NAME) || is_ignored(file_path, &ignored) {
+                continue;
             }
             let mut path = destination_path.join(file_path);
-            let file_name = file.name().to_owned();
             if file.is_dir() {
-                if top_level_dir_name.is_none() {
-                    top_level_dir_name = Some(file_name);
-                    continue; // Skip the top-level directory
-                }
-                std::fs::create_dir_all(&path).map_err(|e| {
-                    CreateError::IoError(
-                        anyhow!("Couldn't create a directory: {}", path.display()),
-                        e,
-                    )
-                })?;
+                sink.create_dir_all(&path)?;
             } else {
+                has_files = true;
                 let parent = path.parent().unwrap();
-                if !parent.exists() {
-                    std::fs::create_dir_all(parent).map_err(|e| {
-                        CreateError::IoError(
-                            anyhow!("Couldn't create a directory: {}", parent.display()),
-                            e,
-                        )
-                    })?;
-                }
+                sink.create_dir_all(parent)?;
+                let mut is_manifest = path.file_name() == Some("Cargo.toml".as_ref());
                 if path.file_name() == Some("Cargo.toml.template".as_ref()) {
                     path = path.with_file_name("Cargo.toml");
+                    is_manifest = true;
+                }
+                if is_manifest && !substitutions.is_empty() {
+                    let mut content = String::new();
+                    file.read_to_string(&mut content)
+                        .map_err(io_error("Couldn't read file", &path))?;
+                    for (substitution, applied) in substitutions.iter().zip(&mut applied) {
+                        let rewritten = substitution.apply(&content).map_err(|e| {
+                            CreateError::InvalidCargoManifest {
+                                path: file_path.to_path_buf(),
+                                message: e.message().to_string(),
+                            }
+                        })?;
+                        if let Some(rewritten) = rewritten {
+                            content = rewritten;
+                            *applied = true;
+                        }
+                    }
+                    sink.write_file(&path, &mut content.as_bytes())?;
+                } else {
+                    sink.write_file(&path, &mut file)?;
                 }
-                let mut outfile = File::create(&path).map_err(|e| {
-                    CreateError::IoError(anyhow!("Couldn't create a file: {}", path.display()), e)
-                })?;
-                std::io::copy(&mut file, &mut outfile).map_err(|e| {
-                    CreateError::IoError(anyhow!("Couldn't copy file: {}", path.display()), e)
-                })?;
             }
         }
+        if !has_files {
+            // the top-level directory has files, checked by `top_level_dir`
+            return Err(CreateError::TemplateSubdirNotFound(subdir.to_path_buf()));
+        }
+        // e.g. --sdk-version for a template without l1x-sdk
+        if let Some((substitution, _)) = substitutions
+            .iter()
+            .zip(applied)
+            .find(|(_, applied)| !applied)
+        {
+            return Err(CreateError::SubstitutionNotApplied(substitution.entry()));
+        }
         Ok(())
     }
 }
 
+/// A path is ignored when it or one of its parent directories matches a pattern.
+/// Patterns without a `/` match a file or directory name at any depth
+fn is_ignored(path: &Path, patterns: &[glob::Pattern]) -> bool {
+    path.ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .any(|ancestor| {
+            patterns.iter().any(|pattern| {
+                pattern.matches_path(ancestor)
+                    || (!pattern.as_str().contains('/')
+                        && ancestor
+                            .file_name()
+                            .is_some_and(|name| pattern.matches(&name.to_string_lossy())))
+            })
+        })
+}
+
 pub fn create(name: String, from_template: String) -> Result<(), CreateError> {
-    let template = Template::from_str(&from_template)?;
+    create_with_options(name, from_template, CreateOptions::default())
+}
+
+pub fn create_with_options(
+    name: String,
+    from_template: String,
+    options: CreateOptions,
+) -> Result<(), CreateError> {
+    // Fail early, before anything is created
+    Template::from_str(&from_template)?;
+    options.substitutions()?;
+    options.template_subdir()?;
 
     let destination_path = PathBuf::from(&name);
-    if destination_path.exists() {
+    let existing = destination_path.exists();
+    if existing
+        && !(destination_path.is_dir() && (options.force || is_empty_dir(&destination_path)))
+    {
         return Err(CreateError::DirectoryAlreadyExists(name));
     }
 
-    fs::create_dir_all(name.clone())
-        .map_err(|e| CreateError::IoError(anyhow!("Couldn't create a directory: {}", name), e))?;
+    // Extract next to the destination and move the project in place only when it's
+    // complete, an interrupted create leaves nothing behind
+    let parent = match destination_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    fs::create_dir_all(&parent).map_err(io_error("Couldn't create a directory", &parent))?;
+    let staging = tempfile::Builder::new()
+        .prefix(".l1x-create-")
+        .tempdir_in(&parent)
+        .map_err(io_error(
+            "Couldn't create a temporary directory in",
+            &parent,
+        ))?;
+    // the directory name is the project name in the license text
+    let staged_project = staging
+        .path()
+        .join(destination_path.file_name().unwrap_or("project".as_ref()));
 
-    let mut archive = template.get_zip_template()?;
+    let lock = options.lock;
+    let locked = options.locked.clone();
+    let run_hooks = options.run_hooks;
+    let init_git_hooks = options.init_git_hooks;
+    let resolved = create_into(
+        &staged_project.to_string_lossy(),
+        &from_template,
+        options,
+        &mut FsSink,
+    )?;
+    // the project is still staged, it's left out when a tool is missing
+    let manifest = TemplateManifest::read(&staged_project)?;
+    if let Some(manifest) = &manifest {
+        manifest.check_tools()?;
+        let path = staged_project.join(TEMPLATE_MANIFEST_NAME);
+        fs::remove_file(&path).map_err(io_error("Couldn't remove", &path))?;
+    }
+    if let Some(lock_file) = &locked {
+        check_template_lock(lock_file, &resolved)?;
+    }
+    if lock {
+        let lock_file = staged_project.join(TEMPLATE_LOCK_FILE_NAME);
+        TemplateLock {
+            templates: vec![resolved.clone()],
+        }
+        .write(&lock_file)?;
+        println!(
+            "🔒 The '{}' template archive was recorded in {}",
+            resolved.name,
+            destination_path.join(TEMPLATE_LOCK_FILE_NAME).display()
+        );
+    }
+    if existing {
+        move_into(&staged_project, &destination_path)?;
+    } else {
+        fs::rename(&staged_project, &destination_path)
+            .map_err(io_error("Couldn't move the project to", &destination_path))?;
+    }
 
-    Template::unzip(&mut archive, &destination_path)?;
+    if let Some(description) = manifest.and_then(|manifest| manifest.description) {
+        println!("📋 {}", description);
+    }
+    if lock {
+        generate_lockfile(&destination_path);
+    }
+    run_post_create_hook(&destination_path, run_hooks)?;
+    if init_git_hooks {
+        install_git_hooks(&destination_path)?;
+    }
+    Ok(())
+}
+
+/// Time a tool of a [`TemplateManifest`] gets to print its version
+const TOOL_VERSION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The [`TEMPLATE_MANIFEST_NAME`] of a template, read by `create` and left out of the
+/// project, e.g.
+///
+/// ```toml
+/// description = "Token contract with generated bindings"
+///
+/// [[tools]]
+/// name = "wasm-opt"
+/// min_version = "116"
+///
+/// [[tools]]
+/// name = "jq"
+/// optional = true
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateManifest {
+    /// Shown when a project is created from the template and by `list-templates`
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tools: Vec<ToolRequirement>,
+}
+
+/// A tool a template needs, looked up on the `PATH`. The name can't be a path, the
+/// template is downloaded code
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolRequirement {
+    pub name: String,
+    /// Lowest accepted version, compared to the first version number in the output of
+    /// `<name> --version`
+    pub min_version: Option<String>,
+    /// Only warn when the tool is missing or too old, instead of failing
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl TemplateManifest {
+    /// Reads the manifest extracted into `project`, `None` when the template has none
+    pub fn read(project: &Path) -> Result<Option<Self>, CreateError> {
+        let path = project.join(TEMPLATE_MANIFEST_NAME);
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error("Couldn't read", &path)(e)),
+        }
+    }
+
+    /// Reads the manifest at the root of a template `archive`, `None` when it has none
+    pub fn from_archive(archive: Vec<u8>) -> Result<Option<Self>, CreateError> {
+        let mut archive = ZipArchive::new(Cursor::new(archive))?;
+        let root = Template::top_level_dir(&mut archive)?;
+        let Ok(mut file) = archive.by_name(&format!(
+            "{}/{}",
+            root.to_string_lossy(),
+            TEMPLATE_MANIFEST_NAME
+        )) else {
+            return Ok(None);
+        };
+        let mut content = String::new();
+        file.read_to_string(&mut content)
+            .map_err(|e| CreateError::InvalidTemplateManifest(e.to_string()))?;
+        Self::parse(&content).map(Some)
+    }
 
+    fn parse(content: &str) -> Result<Self, CreateError> {
+        let manifest: Self = toml::from_str(content)
+            .map_err(|e| CreateError::InvalidTemplateManifest(e.message().to_string()))?;
+        if let Some(tool) = manifest.tools.iter().find(|tool| {
+            tool.name.is_empty() || tool.name.contains(['/', '\\']) || tool.name.starts_with('.')
+        }) {
+            return Err(CreateError::InvalidTemplateManifest(format!(
+                "the tool '{}' isn't a command name",
+                tool.name
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// Checks that the tools are installed in the required versions. The optional ones
+    /// only get a warning
+    pub fn check_tools(&self) -> Result<(), CreateError> {
+        let mut missing = Vec::new();
+        for tool in &self.tools {
+            let Err(problem) = tool.check() else {
+                continue;
+            };
+            if tool.optional {
+                eprintln!("⚠️  The template works best with {}", problem);
+            } else {
+                missing.push(problem);
+            }
+        }
+        if !missing.is_empty() {
+            return Err(CreateError::MissingPrerequisites(missing));
+        }
+        Ok(())
+    }
+}
+
+impl ToolRequirement {
+    /// What's wrong with the installed tool, if anything
+    fn check(&self) -> Result<(), String> {
+        let Some(path) = which(self.name.clone()) else {
+            return Err(format!("{} (not installed)", self.name));
+        };
+        let Some(min_version) = &self.min_version else {
+            return Ok(());
+        };
+        let min = parse_tool_version(min_version)
+            .ok_or_else(|| format!("{} (invalid min_version '{}')", self.name, min_version))?;
+        let version = tool_version(&path, TOOL_VERSION_TIMEOUT)
+            .map_err(|problem| format!("{} ({})", self.name, problem))?;
+        match version {
+            Some(version) if !version_older(&version, &min) => Ok(()),
+            Some(version) => Err(format!(
+                "{} >= {} (found {})",
+                self.name,
+                min_version,
+                version
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            )),
+            None => Err(format!(
+                "{} >= {} (no version in its output)",
+                self.name, min_version
+            )),
+        }
+    }
+}
+
+/// The version printed by `<tool> --version`, `None` when it prints none. The tool is
+/// killed after `timeout`
+fn tool_version(tool: &Path, timeout: std::time::Duration) -> Result<Option<Vec<u64>>, String> {
+    let mut child = std::process::Command::new(tool)
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't run it: {}", e))?;
+    let started = std::time::Instant::now();
+    while child.try_wait().map_err(|e| e.to_string())?.is_none() {
+        if started.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("--version didn't exit within {:?}", timeout));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    Ok(parse_tool_version(&String::from_utf8_lossy(&output.stdout))
+        .or_else(|| parse_tool_version(&String::from_utf8_lossy(&output.stderr))))
+}
+
+/// The numbers of the first version in `text`, e.g. `[17, 0, 6]` for
+/// `clang version 17.0.6` or `[116]` for `wasm-opt version 116`
+fn parse_tool_version(text: &str) -> Option<Vec<u64>> {
+    text.split(|c: char| c.is_whitespace() || c == '(' || c == ',')
+        .map(|word| word.strip_prefix('v').unwrap_or(word))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|word| {
+            word.split('.')
+                .map_while(|part| {
+                    let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+                    digits.parse().ok()
+                })
+                .collect()
+        })
+}
+
+/// `true` when `version` is lower than `min`, the missing numbers count as 0
+fn version_older(version: &[u64], min: &[u64]) -> bool {
+    let len = version.len().max(min.len());
+    let padded = |numbers: &[u64]| {
+        let mut numbers = numbers.to_vec();
+        numbers.resize(len, 0);
+        numbers
+    };
+    padded(version) < padded(min)
+}
+
+/// Writes a [`PRE_COMMIT_HOOK_NAME`] hook into the git repository of `project` that runs
+/// `cargo l1x build` in the project and blocks the commit when it fails. Skipped with a
+/// notice when git isn't installed, the project isn't in a git repository or the
+/// repository already has the hook
+fn install_git_hooks(project: &Path) -> Result<(), CreateError> {
+    let Some(git) = which_optional("git", "the git hooks") else {
+        return Ok(());
+    };
+    let git_output = |args: &[&str]| {
+        let output = std::process::Command::new(&git)
+            .args(args)
+            .current_dir(project)
+            .output()
+            .ok()?;
+        output.status.success().then(|| {
+            String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string()
+        })
+    };
+    // the project directory relative to the root of the working tree, and the hooks
+    // directory relative to the project unless absolute
+    let (Some(prefix), Some(hooks_dir)) = (
+        git_output(&["rev-parse", "--show-prefix"]),
+        git_output(&["rev-parse", "--git-path", "hooks"]),
+    ) else {
+        println!(
+            "🪝 '{}' is not in a git repository, the git hooks weren't installed",
+            project.display()
+        );
+        return Ok(());
+    };
+    let hooks_dir = project.join(hooks_dir);
+    let hook = hooks_dir.join(PRE_COMMIT_HOOK_NAME);
+    if hook.exists() {
+        eprintln!(
+            "⚠️  The repository already has a {} hook, it was kept: {}",
+            PRE_COMMIT_HOOK_NAME,
+            hook.display()
+        );
+        return Ok(());
+    }
+    let script = format!(
+        "#!/bin/sh\n\
+         # Installed by `cargo l1x create --init-git-hooks`: the commit is blocked when the\n\
+         # contract doesn't build. `git commit --no-verify` skips it\n\
+         cd \"$(git rev-parse --show-toplevel)\"/{} || exit 1\n\
+         if ! cargo l1x build --summary-only; then\n    \
+         echo \"pre-commit: cargo l1x build failed, the commit is blocked\" >&2\n    \
+         exit 1\n\
+         fi\n",
+        shell_quote(&prefix)
+    );
+    fs::create_dir_all(&hooks_dir).map_err(io_error("Couldn't create a directory", &hooks_dir))?;
+    fs::write(&hook, script).map_err(io_error("Couldn't write the git hook", &hook))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755))
+            .map_err(io_error("Couldn't make executable", &hook))?;
+    }
+    println!(
+        "🪝 Installed a git {} hook building the contract before each commit",
+        PRE_COMMIT_HOOK_NAME
+    );
     Ok(())
 }
+
+/// Runs the [`POST_CREATE_HOOK_NAME`] script of the new project with `sh` if `run` is set,
+/// otherwise only tells how to run it
+fn run_post_create_hook(project: &Path, run: bool) -> Result<(), CreateError> {
+    let hook = project.join(POST_CREATE_HOOK_NAME);
+    if !hook.is_file() {
+        return Ok(());
+    }
+    let name = project
+        .file_name()
+        .map_or_else(|| project.to_string_lossy(), |name| name.to_string_lossy());
+    if !run {
+        println!(
+            "🪝 The template has a {} hook, it wasn't run. Review it and run `sh {} {}` in the project, or create the project with --run-hooks",
+            POST_CREATE_HOOK_NAME, POST_CREATE_HOOK_NAME, name
+        );
+        return Ok(());
+    }
+    println!(
+        "🪝 Running the {} hook of the template",
+        POST_CREATE_HOOK_NAME
+    );
+    let status = std::process::Command::new("sh")
+        .arg(POST_CREATE_HOOK_NAME)
+        .arg(name.as_ref())
+        .env("L1X_PROJECT_NAME", name.as_ref())
+        .current_dir(project)
+        .status()
+        .map_err(io_error("Couldn't run the post-create hook", &hook))?;
+    if !status.success() {
+        return Err(CreateError::HookFailed(status.code()));
+    }
+    println!("🪝 The {} hook exited with code 0", POST_CREATE_HOOK_NAME);
+    Ok(())
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+/// Moves the files of `source` into the existing `destination` directory, replacing the
+/// files with the same path. Nothing is moved when a file of one is a directory of the other
+fn move_into(source: &Path, destination: &Path) -> Result<(), CreateError> {
+    let mut conflicts = Vec::new();
+    find_conflicts(source, destination, &mut conflicts)?;
+    if !conflicts.is_empty() {
+        return Err(CreateError::PathConflicts(conflicts));
+    }
+    move_entries(source, destination)
+}
+
+/// The paths of `destination` that are a directory where `source` has a file, or the
+/// other way around
+fn find_conflicts(
+    source: &Path,
+    destination: &Path,
+    conflicts: &mut Vec<PathBuf>,
+) -> Result<(), CreateError> {
+    for entry in fs::read_dir(source).map_err(io_error("Couldn't read the directory", source))? {
+        let entry = entry.map_err(io_error("Couldn't read the directory", source))?;
+        let target = destination.join(entry.file_name());
+        if !target.exists() {
+            continue;
+        }
+        match (entry.path().is_dir(), target.is_dir()) {
+            (true, true) => find_conflicts(&entry.path(), &target, conflicts)?,
+            (false, false) => {}
+            _ => conflicts.push(target),
+        }
+    }
+    Ok(())
+}
+
+fn move_entries(source: &Path, destination: &Path) -> Result<(), CreateError> {
+    for entry in fs::read_dir(source).map_err(io_error("Couldn't read the directory", source))? {
+        let entry = entry.map_err(io_error("Couldn't read the directory", source))?;
+        let target = destination.join(entry.file_name());
+        if entry.path().is_dir() && target.is_dir() {
+            move_entries(&entry.path(), &target)?;
+        } else {
+            fs::rename(entry.path(), &target)
+                .map_err(io_error("Couldn't move the project file to", &target))?;
+        }
+    }
+    Ok(())
+}
+
+/// Pins the dependencies of the new project, only warns when it's not possible, e.g. offline
+fn generate_lockfile(project: &Path) {
+    let Some(cargo) = which_optional("cargo", "Cargo.lock generation") else {
+        return;
+    };
+    let manifest = project.join("Cargo.toml");
+    let output = std::process::Command::new(cargo)
+        .arg("generate-lockfile")
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {
+            println!("🔒 Cargo.lock generated, the dependency versions are pinned")
+        }
+        Ok(output) => eprintln!(
+            "⚠️  Could not generate Cargo.lock, run `cargo generate-lockfile` in the project later:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(e) => eprintln!("⚠️  Could not run cargo to generate Cargo.lock: {}", e),
+    }
+}
+
+/// Extracts the template into `sink` under the `name` directory, without touching the
+/// filesystem. Returns the source and checksum of the template archive
+pub fn create_into<S: FileSink>(
+    name: &str,
+    from_template: &str,
+    options: CreateOptions,
+    sink: &mut S,
+) -> Result<LockedTemplate, CreateError> {
+    let template = Template::from_str(from_template)?;
+    // Fail before the download
+    options.substitutions()?;
+    options.template_subdir()?;
+
+    let content = template.get_zip_template(
+        options.template_cache.as_deref(),
+        options.net_concurrency.unwrap_or(DEFAULT_NET_CONCURRENCY),
+    )?;
+    let archive = TemplateArchive::new(template, content);
+    create_from_archive(name, archive, options, sink)
+}
+
+/// Like [`create_into`], with an archive fetched beforehand, e.g. by [`prefetch_templates`]
+pub fn create_from_archive<S: FileSink>(
+    name: &str,
+    archive: TemplateArchive,
+    options: CreateOptions,
+    sink: &mut S,
+) -> Result<LockedTemplate, CreateError> {
+    let substitutions = options.substitutions()?;
+    let subdir = options.template_subdir()?;
+
+    let resolved = archive.resolved;
+    let mut archive = ZipArchive::new(Cursor::new(archive.content))?;
+
+    Template::unzip(&mut archive, Path::new(name), &subdir, &substitutions, sink)?;
+
+    if let Some(license) = &options.license {
+        let project = Path::new(name)
+            .file_name()
+            .map_or_else(|| name.into(), |name| name.to_string_lossy());
+        for (file_name, text) in license_files(license, &project)? {
+            sink.write_file(&Path::new(name).join(file_name), &mut text.as_bytes())?;
+        }
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::MemorySink;
+
+    #[test]
+    fn test_dependency_version_substitution() {
+        let apply = |manifest: &str, version: &str| {
+            Substitution::dependency_version("l1x-sdk", version)
+                .apply(manifest)
+                .unwrap()
+        };
+        let manifest = "[dependencies]\nborsh = \"0.9\"\nl1x-sdk = \"0.3.1\" # pinned\n";
+        assert_eq!(
+            apply(manifest, "=0.2.0").unwrap(),
+            "[dependencies]\nborsh = \"0.9\"\nl1x-sdk = \"=0.2.0\" # pinned\n"
+        );
+
+        let manifest = "[dependencies]\nl1x-sdk = { version = \"0.3.1\", features = [\"a\"] }\n";
+        assert_eq!(
+            apply(manifest, "0.4").unwrap(),
+            "[dependencies]\nl1x-sdk = { version = \"0.4\", features = [\"a\"] }\n"
+        );
+
+        // only the dependency tables are rewritten
+        let manifest = "[package]\nname = \"l1x-sdk\"\n\n[features]\nl1x-sdk = []\n";
+        assert_eq!(apply(manifest, "0.4"), None);
+        let manifest = "[dependencies]\nborsh = \"0.9\"\n";
+        assert_eq!(apply(manifest, "0.4"), None);
+
+        // the version of an inherited dependency is set in the workspace
+        let manifest = "[dependencies]\nl1x-sdk.workspace = true\n";
+        assert_eq!(apply(manifest, "0.4").unwrap(), manifest);
+        let manifest = "[workspace.dependencies]\nl1x-sdk = \"0.3.1\"\n";
+        assert_eq!(
+            apply(manifest, "0.4").unwrap(),
+            "[workspace.dependencies]\nl1x-sdk = \"0.4\"\n"
+        );
+
+        assert!(Substitution::dependency_version("l1x-sdk", "0.4")
+            .apply("content")
+            .is_err());
+    }
+
+    #[test]
+    fn test_create_into_memory() {
+        let mut sink = MemorySink::default();
+        let options = CreateOptions {
+            sdk_version: Some("0.3.0".to_string()),
+            ..Default::default()
+        };
+        create_into("contract", "local_default", options, &mut sink).unwrap();
+
+        let manifest = sink
+            .files
+            .get(Path::new("contract/Cargo.toml"))
+            .expect("Cargo.toml should be extracted");
+        let manifest = String::from_utf8_lossy(manifest);
+        assert!(manifest.contains("l1x-sdk = \"0.3.0\""));
+        assert!(sink.files.contains_key(Path::new("contract/src/lib.rs")));
+        assert!(!Path::new("contract").exists());
+    }
+
+    /// Answers a single request with the `response` built from the server address
+    fn serve_once(
+        response: impl FnOnce(std::net::SocketAddr) -> String + Send + 'static,
+    ) -> String {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream.write_all(response(address).as_bytes()).unwrap();
+        });
+        format!("http://{}/", address)
+    }
+
+    #[test]
+    fn test_reject_insecure_redirect() {
+        let url = serve_once(|address| {
+            format!(
+                "HTTP/1.1 302 Found\r\nLocation: http://{}/template.zip\r\nContent-Length: 0\r\n\r\n",
+                address
+            )
+        });
+
+        let result = HttpClient::new(DEFAULT_NET_CONCURRENCY)
+            .unwrap()
+            .download(&url);
+        assert!(matches!(result, Err(CreateError::RedirectRejected(_))));
+    }
+
+    #[test]
+    fn test_prefetch_templates() {
+        let fetched = prefetch_templates(
+            &[
+                Template::LocalDefault,
+                Template::LocalDefault,
+                Template::LocalDefault,
+            ],
+            None,
+            DEFAULT_NET_CONCURRENCY,
+        )
+        .unwrap();
+        assert_eq!(fetched.len(), 1);
+        let (template, archive) = fetched.into_iter().next().unwrap();
+        assert_eq!(template, Template::LocalDefault);
+        let archive = archive.unwrap();
+        assert_eq!(
+            archive.resolved.sha256,
+            format!("{:x}", Sha256::digest(&archive.content))
+        );
+
+        let mut sink = MemorySink::default();
+        let resolved =
+            create_from_archive("contract", archive, CreateOptions::default(), &mut sink).unwrap();
+        assert_eq!(resolved.name, "local_default");
+        assert!(sink.files.contains_key(Path::new("contract/Cargo.toml")));
+    }
+
+    #[test]
+    fn test_resume_download() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let archive = zip_with(&["Cargo.toml", "src/lib.rs"])
+            .into_inner()
+            .into_inner();
+        let (head, tail) = archive.split_at(archive.len() / 2);
+        let broken_off = |body: &[u8]| {
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n\r\n",
+                archive.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(body);
+            response
+        };
+        let rest = |body: &[u8]| {
+            let mut response = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                head.len(),
+                archive.len() - 1,
+                archive.len(),
+                body.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(body);
+            response
+        };
+        let mut whole = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            archive.len()
+        )
+        .into_bytes();
+        whole.extend_from_slice(&archive);
+        let responses = [
+            broken_off(head),
+            rest(tail),
+            broken_off(head),
+            // the file changed on the server without a new ETag
+            rest(&vec![0; tail.len()]),
+            whole,
+        ];
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..read]).to_lowercase());
+                stream.write_all(&response).unwrap();
+            }
+            requests
+        });
+        let url = format!("http://{}/template.zip", address);
+        let downloads = tempfile::tempdir().unwrap();
+        let downloads_dir = downloads.path().join("downloads");
+        let client = HttpClient::new(DEFAULT_NET_CONCURRENCY)
+            .unwrap()
+            .with_downloads_dir(downloads_dir.clone());
+        let partial = partial_download_path(&downloads_dir, &url).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&downloads_dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        assert!(matches!(
+            client.download(&url),
+            Err(CreateError::ConnectionError(_))
+        ));
+        assert_eq!(fs::read(&partial).unwrap(), head);
+        assert_eq!(client.download(&url).unwrap(), archive);
+        assert!(!partial.exists());
+
+        assert!(client.download(&url).is_err());
+        // the resumed archive doesn't verify and is downloaded again
+        assert_eq!(client.download(&url).unwrap(), archive);
+        assert!(!partial.exists());
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("range:"));
+        let range = format!("range: bytes={}-\r\n", head.len());
+        assert!(requests[1].contains(&range));
+        assert!(requests[1].contains("if-range: \"v1\"\r\n"));
+        assert!(requests[3].contains(&range));
+        assert!(!requests[4].contains("range:"));
+    }
+
+    #[test]
+    fn test_partial_download_lock() {
+        let path = std::env::temp_dir().join(format!("l1x-partial-{}.part", std::process::id()));
+        let partial = PartialDownload::lock(&path).unwrap();
+        assert!(PartialDownload::lock(&path).is_none());
+        drop(partial);
+        assert!(PartialDownload::lock(&path).is_some());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_http_client_concurrency() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let client = HttpClient::new(NonZeroUsize::new(1).unwrap()).unwrap();
+        let permit = client.acquire();
+        let acquired = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let _permit = client.acquire();
+                acquired.store(true, Ordering::SeqCst);
+            });
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!acquired.load(Ordering::SeqCst));
+            drop(permit);
+        });
+        assert!(acquired.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_template_cache() {
+        let cache = tempfile::tempdir().unwrap();
+        fs::write(cache.path().join("ft.zip"), b"cached").unwrap();
+        assert_eq!(
+            Template::Ft
+                .get_zip_template(Some(cache.path()), DEFAULT_NET_CONCURRENCY)
+                .unwrap(),
+            b"cached"
+        );
+        // the bundled template doesn't need a cache
+        let bundled = Template::LocalDefault
+            .get_zip_template(None, DEFAULT_NET_CONCURRENCY)
+            .unwrap();
+        assert_eq!(
+            Template::LocalDefault
+                .get_zip_template(Some(cache.path()), DEFAULT_NET_CONCURRENCY)
+                .unwrap(),
+            bundled
+        );
+    }
+
+    #[test]
+    fn test_template_not_found() {
+        let url = serve_once(|_| {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nNot Found".to_string()
+        });
+
+        let result = HttpClient::new(DEFAULT_NET_CONCURRENCY)
+            .unwrap()
+            .download(&url);
+        assert!(matches!(
+            result,
+            Err(CreateError::TemplateNotFound { status: 404, .. })
+        ));
+    }
+
+    fn zip_with(entries: &[&str]) -> ZipArchive<Cursor<Vec<u8>>> {
+        use std::io::Write;
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for entry in entries {
+            if entry.ends_with('/') {
+                writer
+                    .add_directory(*entry, zip::write::FileOptions::<()>::default())
+                    .unwrap();
+            } else {
+                writer
+                    .start_file(*entry, zip::write::FileOptions::<()>::default())
+                    .unwrap();
+                writer.write_all(b"content").unwrap();
+            }
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn unzip_to_memory(entries: &[&str]) -> Result<MemorySink, CreateError> {
+        let mut sink = MemorySink::default();
+        Template::unzip(
+            &mut zip_with(entries),
+            Path::new("contract"),
+            Path::new(""),
+            &[],
+            &mut sink,
+        )?;
+        Ok(sink)
+    }
+
+    #[test]
+    fn test_unzip_substitution_not_applied() {
+        use std::io::Write;
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "template/Cargo.toml",
+                zip::write::FileOptions::<()>::default(),
+            )
+            .unwrap();
+        writer
+            .write_all(b"[dependencies]\nborsh = \"0.9\"\n")
+            .unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let result = Template::unzip(
+            &mut archive,
+            Path::new("contract"),
+            Path::new(""),
+            &[Substitution::dependency_version("l1x-sdk", "0.4")],
+            &mut MemorySink::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(CreateError::SubstitutionNotApplied(entry)) if entry.contains("l1x-sdk")
+        ));
+    }
+
+    #[test]
+    fn test_unzip_layout() {
+        // no explicit entry for the top-level directory
+        let sink = unzip_to_memory(&["repo-ft/Cargo.toml.template", "repo-ft/src/lib.rs"]).unwrap();
+        assert!(sink.files.contains_key(Path::new("contract/Cargo.toml")));
+        assert!(sink.files.contains_key(Path::new("contract/src/lib.rs")));
+
+        assert!(matches!(
+            unzip_to_memory(&[]),
+            Err(CreateError::EmptyTemplate)
+        ));
+        assert!(matches!(
+            unzip_to_memory(&["repo/", "repo/src/"]),
+            Err(CreateError::EmptyTemplate)
+        ));
+        assert!(matches!(
+            unzip_to_memory(&["Cargo.toml", "src/lib.rs"]),
+            Err(CreateError::UnexpectedArchiveLayout(_))
+        ));
+        assert!(matches!(
+            unzip_to_memory(&["one/Cargo.toml", "two/src/lib.rs"]),
+            Err(CreateError::UnexpectedArchiveLayout(_))
+        ));
+    }
+
+    #[test]
+    fn test_unzip_template_subdir() {
+        let entries = [
+            "repo/README.md",
+            "repo/templates/token/Cargo.toml.template",
+            "repo/templates/token/src/lib.rs",
+            "repo/templates/dao/src/lib.rs",
+        ];
+        let mut sink = MemorySink::default();
+        Template::unzip(
+            &mut zip_with(&entries),
+            Path::new("contract"),
+            Path::new("templates/token"),
+            &[],
+            &mut sink,
+        )
+        .unwrap();
+        let files: Vec<_> = sink.files.keys().cloned().collect();
+        assert_eq!(
+            files,
+            ["contract/Cargo.toml", "contract/src/lib.rs"].map(PathBuf::from)
+        );
+
+        let result = Template::unzip(
+            &mut zip_with(&entries),
+            Path::new("contract"),
+            Path::new("templates/nft"),
+            &[],
+            &mut MemorySink::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(CreateError::TemplateSubdirNotFound(_))
+        ));
+
+        let options = CreateOptions {
+            template_subdir: Some(PathBuf::from("../templates")),
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.template_subdir(),
+            Err(CreateError::InvalidTemplateSubdir(_))
+        ));
+    }
+
+    #[test]
+    fn test_unzip_l1xignore() {
+        use std::io::Write;
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (entry, content) in [
+            (
+                "repo/.l1xignore",
+                "# local files\n*.swp\n.vscode/\nsrc/scratch\n",
+            ),
+            ("repo/Cargo.toml", ""),
+            ("repo/src/lib.rs", ""),
+            ("repo/src/.lib.rs.swp", ""),
+            ("repo/src/scratch/notes.rs", ""),
+            ("repo/.vscode/settings.json", ""),
+            ("repo/tests/scratch/keep.rs", ""),
+        ] {
+            writer
+                .start_file(entry, zip::write::FileOptions::<()>::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let mut sink = MemorySink::default();
+        Template::unzip(
+            &mut archive,
+            Path::new("contract"),
+            Path::new(""),
+            &[],
+            &mut sink,
+        )
+        .unwrap();
+        let files: Vec<_> = sink.files.keys().cloned().collect();
+        assert_eq!(
+            files,
+            [
+                "contract/Cargo.toml",
+                "contract/src/lib.rs",
+                "contract/tests/scratch/keep.rs"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_create_moves_project_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("contract");
+        create(
+            project.to_string_lossy().into_owned(),
+            "local_default".to_string(),
+        )
+        .unwrap();
+
+        assert!(project.join("Cargo.toml").exists());
+        let mut entries: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, vec!["contract"]);
+    }
+
+    #[test]
+    fn test_create_into_empty_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("contract");
+        fs::create_dir(&project).unwrap();
+        let name = project.to_string_lossy().into_owned();
+        create(name, "local_default".to_string()).unwrap();
+        assert!(project.join("Cargo.toml").exists());
+    }
+
+    #[test]
+    fn test_create_into_non_empty_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("contract");
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::write(project.join("src/lib.rs"), "// mine").unwrap();
+        fs::write(project.join("NOTES.md"), "notes").unwrap();
+        let name = project.to_string_lossy().into_owned();
+
+        let result = create(name.clone(), "local_default".to_string());
+        assert!(matches!(
+            result,
+            Err(CreateError::DirectoryAlreadyExists(_))
+        ));
+        assert!(!project.join("Cargo.toml").exists());
+
+        let options = CreateOptions {
+            force: true,
+            ..Default::default()
+        };
+        create_with_options(name, "local_default".to_string(), options).unwrap();
+        assert!(project.join("Cargo.toml").exists());
+        // the template files replace the existing ones, the others are kept
+        assert_ne!(
+            fs::read_to_string(project.join("src/lib.rs")).unwrap(),
+            "// mine"
+        );
+        assert_eq!(
+            fs::read_to_string(project.join("NOTES.md")).unwrap(),
+            "notes"
+        );
+
+        // a file and a directory clash, nothing is moved
+        let other = dir.path().join("other");
+        fs::create_dir_all(other.join("Cargo.toml")).unwrap();
+        fs::write(other.join("src"), "").unwrap();
+        let options = CreateOptions {
+            force: true,
+            ..Default::default()
+        };
+        let result = create_with_options(
+            other.to_string_lossy().into_owned(),
+            "local_default".to_string(),
+            options,
+        );
+        assert!(matches!(
+            result,
+            Err(CreateError::PathConflicts(paths))
+                if paths.len() == 2 && paths.contains(&other.join("src"))
+        ));
+        assert!(!other.join(".gitignore").exists());
+
+        // never into a file
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let options = CreateOptions {
+            force: true,
+            ..Default::default()
+        };
+        let result = create_with_options(
+            file.to_string_lossy().into_owned(),
+            "local_default".to_string(),
+            options,
+        );
+        assert!(matches!(
+            result,
+            Err(CreateError::DirectoryAlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_template_names() {
+        for template in Template::all() {
+            assert_eq!(Template::from_str(template.name()).unwrap(), template);
+        }
+        for (alias, template) in [
+            ("FT", Template::Ft),
+            ("nft-template", Template::Nft),
+            ("fungible", Template::Ft),
+            ("Non-Fungible", Template::Nft),
+            ("local-default", Template::LocalDefault),
+        ] {
+            assert_eq!(Template::from_str(alias).unwrap(), template);
+        }
+        let error = Template::from_str("dao").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown template: dao, expected one of: local_default, default, ft, nft"
+        );
+    }
+
+    #[test]
+    fn test_template_url() {
+        assert_eq!(Template::LocalDefault.url(), None);
+        assert_eq!(
+            Template::Nft.url(),
+            Some(
+                "https://github.com/L1X-Foundation/cargo-l1x-templates/archive/refs/heads/nft.zip"
+            )
+        );
+    }
+
+    #[test]
+    fn test_template_source() {
+        assert_eq!(
+            Template::Ft.source(),
+            (
+                "https://github.com/L1X-Foundation/cargo-l1x-templates".to_string(),
+                "refs/heads/ft".to_string()
+            )
+        );
+        assert_eq!(Template::LocalDefault.source().0, "cargo-l1x");
+    }
+
+    #[test]
+    fn test_template_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("contract");
+        let lock_file = project.join(TEMPLATE_LOCK_FILE_NAME);
+        let create = |options: CreateOptions| {
+            create_with_options(
+                project.to_string_lossy().into_owned(),
+                "local_default".to_string(),
+                options,
+            )
+        };
+
+        create(CreateOptions::default()).unwrap();
+        assert!(!lock_file.exists());
+        fs::remove_dir_all(&project).unwrap();
+
+        // the lock is written in the project, outside the cargo workspace files
+        let mut options = CreateOptions {
+            lock: true,
+            ..Default::default()
+        };
+        create(options.clone()).unwrap();
+        let lock = TemplateLock::read(&lock_file).unwrap();
+        assert_eq!(lock.templates.len(), 1);
+        assert_eq!(lock.templates[0].repository, "cargo-l1x");
+        let saved = dir.path().join(TEMPLATE_LOCK_FILE_NAME);
+        fs::rename(&lock_file, &saved).unwrap();
+        fs::remove_dir_all(&project).unwrap();
+
+        options.lock = false;
+        options.locked = Some(saved.clone());
+        create(options.clone()).unwrap();
+        fs::remove_dir_all(&project).unwrap();
+
+        let content = fs::read_to_string(&saved).unwrap();
+        let sha256 = &lock.templates[0].sha256;
+        fs::write(&saved, content.replace(sha256.as_str(), &"0".repeat(64))).unwrap();
+        assert!(matches!(
+            create(options.clone()),
+            Err(CreateError::TemplateHashMismatch { resolved, .. }) if &resolved == sha256
+        ));
+        assert!(!project.exists());
+
+        fs::write(&saved, "").unwrap();
+        assert!(matches!(
+            create(options),
+            Err(CreateError::TemplateNotLocked(template)) if template == "local_default"
+        ));
+        assert!(!project.exists());
+    }
+
+    #[test]
+    fn test_archive_commit() {
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.set_comment(commit);
+        let content = zip.finish().unwrap().into_inner();
+        assert_eq!(archive_commit(&content).as_deref(), Some(commit));
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.set_comment("made by hand");
+        let content = zip.finish().unwrap().into_inner();
+        assert_eq!(archive_commit(&content), None);
+    }
+
+    #[test]
+    fn test_license() {
+        let mut sink = MemorySink::default();
+        let options = CreateOptions {
+            license: Some("MIT".to_string()),
+            ..Default::default()
+        };
+        create_into("contract", "local_default", options, &mut sink).unwrap();
+        let manifest = String::from_utf8_lossy(&sink.files[Path::new("contract/Cargo.toml")]);
+        assert!(manifest.contains("license = \"MIT\"\n"));
+        assert!(!manifest.contains("Apache-2.0"));
+        let license = String::from_utf8_lossy(&sink.files[Path::new("contract/LICENSE")]);
+        assert!(license.starts_with("Copyright (c) The contract authors\n\nPermission"));
+
+        let apply = |manifest: &str, license: &str| {
+            Substitution::package_field("license", license)
+                .apply(manifest)
+                .unwrap()
+        };
+        let manifest = "[package]\nname = \"a\"\n\n[dependencies]\n";
+        assert_eq!(
+            apply(manifest, "MIT").unwrap(),
+            "[package]\nname = \"a\"\nlicense = \"MIT\"\n\n[dependencies]\n"
+        );
+        // a key of another table isn't touched, the value is escaped
+        let manifest = "[package]\nlicense = \"Apache-2.0\"\n\n[badges]\nlicense = \"x\"\n";
+        let content: toml::Table =
+            toml::from_str(&apply(manifest, "MIT \"or\" \\'").unwrap()).unwrap();
+        assert_eq!(
+            content["package"]["license"].as_str(),
+            Some("MIT \"or\" \\'")
+        );
+        assert_eq!(content["badges"]["license"].as_str(), Some("x"));
+        // an inherited license is set in the workspace
+        let manifest = "[package]\nname = \"a\"\nlicense.workspace = true\n";
+        assert_eq!(apply(manifest, "MIT").unwrap(), manifest);
+        let manifest = "[workspace.package]\nlicense = \"Apache-2.0\"\n";
+        assert_eq!(
+            apply(manifest, "MIT").unwrap(),
+            "[workspace.package]\nlicense = \"MIT\"\n"
+        );
+        assert_eq!(apply("[workspace]\nmembers = []\n", "MIT"), None);
+
+        let files = license_files("Apache-2.0 OR MIT", "a").unwrap();
+        assert_eq!(
+            files.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            ["LICENSE-MIT", "LICENSE-APACHE"]
+        );
+        let options = CreateOptions {
+            license: Some("GPL-3.0".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.substitutions(),
+            Err(CreateError::UnsupportedLicense(license)) if license == "GPL-3.0"
+        ));
+    }
+
+    #[test]
+    fn test_post_create_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("counter");
+        fs::create_dir(&project).unwrap();
+        // no hook
+        run_post_create_hook(&project, true).unwrap();
+
+        let hook = "echo \"$1 $L1X_PROJECT_NAME\" > name.txt\n";
+        fs::write(project.join(POST_CREATE_HOOK_NAME), hook).unwrap();
+        run_post_create_hook(&project, false).unwrap();
+        assert!(!project.join("name.txt").exists());
+        run_post_create_hook(&project, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(project.join("name.txt")).unwrap(),
+            "counter counter\n"
+        );
+
+        fs::write(project.join(POST_CREATE_HOOK_NAME), "exit 3\n").unwrap();
+        assert!(matches!(
+            run_post_create_hook(&project, true),
+            Err(CreateError::HookFailed(Some(3)))
+        ));
+    }
+
+    #[test]
+    fn test_template_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(TemplateManifest::read(dir.path()).unwrap(), None);
+
+        let manifest = |tool: &str, min_version: &str| {
+            format!(
+                "description = \"Token with bindings\"\n\
+                 [[tools]]\nname = \"{}\"\nmin_version = \"{}\"\n\
+                 [[tools]]\nname = \"l1x-missing-tool\"\noptional = true\n",
+                tool, min_version
+            )
+        };
+        fs::write(
+            dir.path().join(TEMPLATE_MANIFEST_NAME),
+            manifest("cargo", "1.4"),
+        )
+        .unwrap();
+        let read = TemplateManifest::read(dir.path()).unwrap().unwrap();
+        assert_eq!(read.description.as_deref(), Some("Token with bindings"));
+        assert_eq!(read.tools.len(), 2);
+        read.check_tools().unwrap();
+
+        fs::write(
+            dir.path().join(TEMPLATE_MANIFEST_NAME),
+            manifest("cargo", "100"),
+        )
+        .unwrap();
+        let read = TemplateManifest::read(dir.path()).unwrap().unwrap();
+        match read.check_tools() {
+            Err(CreateError::MissingPrerequisites(tools)) => {
+                assert!(
+                    tools[0].starts_with("cargo >= 100 (found 1."),
+                    "{:?}",
+                    tools
+                )
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // the template can't run a program of its own
+        for tool in ["./tools/gen", "/tmp/gen", "bin\\\\gen", ""] {
+            fs::write(dir.path().join(TEMPLATE_MANIFEST_NAME), manifest(tool, "1")).unwrap();
+            assert!(
+                matches!(
+                    TemplateManifest::read(dir.path()),
+                    Err(CreateError::InvalidTemplateManifest(_))
+                ),
+                "{}",
+                tool
+            );
+        }
+        fs::write(
+            dir.path().join(TEMPLATE_MANIFEST_NAME),
+            "[[tools]]\nname = \"gen\"\nversion_arg = \"-c\"\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            TemplateManifest::read(dir.path()),
+            Err(CreateError::InvalidTemplateManifest(_))
+        ));
+
+        assert_eq!(
+            parse_tool_version("wasm-opt version 116 (version_116)"),
+            Some(vec![116])
+        );
+        assert_eq!(
+            parse_tool_version("rustc v1.75.0-nightly"),
+            Some(vec![1, 75, 0])
+        );
+        assert_eq!(parse_tool_version("no version"), None);
+        assert!(version_older(&[1, 9], &[1, 10]));
+        assert!(!version_older(&[2], &[1, 10, 3]));
+        assert!(!version_older(&[1, 10], &[1, 10, 0]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tool_version() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("fake-tool");
+        fs::write(&tool, "#!/bin/sh\necho \"fake-tool $1 1.4.2 (abc)\"\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            tool_version(&tool, TOOL_VERSION_TIMEOUT),
+            Ok(Some(vec![1, 4, 2]))
+        );
+
+        fs::write(&tool, "#!/bin/sh\nexec sleep 10\n").unwrap();
+        assert!(tool_version(&tool, Duration::from_millis(100))
+            .unwrap_err()
+            .contains("didn't exit"));
+    }
+
+    #[test]
+    fn test_template_manifest_from_archive() {
+        use std::io::Write;
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in [
+            ("template-main/Cargo.toml", "[package]\n"),
+            (
+                "template-main/l1x-template.toml",
+                "description = \"Token with bindings\"\n",
+            ),
+        ] {
+            writer
+                .start_file(name, zip::write::FileOptions::<()>::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let archive = writer.finish().unwrap().into_inner();
+        let manifest = TemplateManifest::from_archive(archive).unwrap().unwrap();
+        assert_eq!(manifest.description.as_deref(), Some("Token with bindings"));
+
+        let archive = zip_with(&["template-main/Cargo.toml"])
+            .into_inner()
+            .into_inner();
+        assert_eq!(TemplateManifest::from_archive(archive).unwrap(), None);
+        assert_eq!(
+            Template::Ft.describe(None),
+            Template::Ft.description().to_string()
+        );
+    }
+
+    #[test]
+    fn test_install_git_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("contracts/my counter");
+        fs::create_dir_all(&project).unwrap();
+        // not a repository
+        install_git_hooks(&project).unwrap();
+
+        let status = std::process::Command::new("git")
+            .arg("init")
+            .arg("-q")
+            .current_dir(dir.path())
+            .status();
+        if !status.is_ok_and(|status| status.success()) {
+            return; // git is not installed
+        }
+        install_git_hooks(&project).unwrap();
+        let hook = dir.path().join(".git/hooks").join(PRE_COMMIT_HOOK_NAME);
+        let script = fs::read_to_string(&hook).unwrap();
+        assert!(script.contains(
+            "cd \"$(git rev-parse --show-toplevel)\"/'contracts/my counter/' || exit 1\n"
+        ));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(&hook).unwrap().permissions().mode() & 0o111,
+                0o111
+            );
+        }
+
+        // an existing hook is kept
+        fs::write(&hook, "exit 0\n").unwrap();
+        install_git_hooks(&project).unwrap();
+        assert_eq!(fs::read_to_string(&hook).unwrap(), "exit 0\n");
+    }
+
+    #[test]
+    fn test_invalid_sdk_version() {
+        let options = CreateOptions {
+            sdk_version: Some("not a version".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.substitutions(),
+            Err(CreateError::InvalidSdkVersion(..))
+        ));
+    }
+}
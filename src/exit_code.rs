@@ -0,0 +1,17 @@
+//! Exit codes of `cargo l1x`, so that CI can tell a typo from a missing toolchain or
+//! a failed compilation. Each error type maps its variants with an `exit_code()` method
+
+/// The command succeeded
+pub const SUCCESS: i32 = 0;
+/// Any failure without a more specific code, e.g. a filesystem error
+pub const FAILURE: i32 = 1;
+/// Invalid command line, option value or configuration
+pub const USAGE: i32 = 2;
+/// A tool the command needs is missing or can't be run: llc, llvm-strip, cargo, docker...
+pub const TOOLCHAIN: i32 = 3;
+/// `cargo build` of the wasm failed
+pub const CARGO_BUILD: i32 = 4;
+/// The translation to LLVM IR, llc, the strip pass or a check of the object file failed
+pub const OBJECT: i32 = 5;
+/// The template couldn't be downloaded, extracted or verified
+pub const TEMPLATE: i32 = 6;
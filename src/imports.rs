@@ -0,0 +1,125 @@
+use object::{Object, ObjectSymbol};
+use std::fs;
+use std::path::Path;
+
+/// Module of the wasm imports provided by the runtime, the translation to LLVM IR
+/// names an import `<module>_<field>`
+const HOST_MODULE: &str = "env";
+
+include!(concat!(env!("OUT_DIR"), "/host_functions.rs"));
+
+/// Functions declared by the translation to LLVM IR itself, implemented by the runtime
+const TRANSLATOR_SYSCALLS: [&str; 4] = [
+    "syscall_math_op32",
+    "syscall_math_op64",
+    "syscall_memory_op32",
+    "syscall_memory_op64",
+];
+
+/// The undefined symbols of the object file that are neither known host functions,
+/// `extra` host functions nor syscalls of the translation
+pub fn unknown_imports(object: &Path, extra: &[String]) -> Result<Vec<String>, String> {
+    let data = fs::read(object).map_err(|e| format!("Could not read {:?}: {}", object, e))?;
+    let file = object::File::parse(&*data)
+        .map_err(|e| format!("{:?} is not a valid object file: {}", object, e))?;
+
+    let mut unknown: Vec<String> = file
+        .symbols()
+        .filter(|symbol| symbol.is_undefined() && !symbol.is_local())
+        .filter_map(|symbol| symbol.name().ok())
+        .filter(|name| !name.is_empty() && !is_known_import(name, extra))
+        .map(str::to_string)
+        .collect();
+    unknown.sort();
+    unknown.dedup();
+    Ok(unknown)
+}
+
+/// The undefined symbols of `file` that are neither functions of the host module nor
+/// syscalls of the translation, the runtime can't provide them whatever its version
+pub fn foreign_imports(file: &object::File) -> Vec<String> {
+    let mut foreign: Vec<String> = file
+        .symbols()
+        .filter(|symbol| symbol.is_undefined() && !symbol.is_local())
+        .filter_map(|symbol| symbol.name().ok())
+        .filter(|name| {
+            !name.is_empty()
+                && !TRANSLATOR_SYSCALLS.contains(name)
+                && !name
+                    .strip_prefix(HOST_MODULE)
+                    .is_some_and(|name| name.starts_with('_'))
+        })
+        .map(str::to_string)
+        .collect();
+    foreign.sort();
+    foreign.dedup();
+    foreign
+}
+
+fn is_known_import(name: &str, extra: &[String]) -> bool {
+    if TRANSLATOR_SYSCALLS.contains(&name) {
+        return true;
+    }
+    let Some(function) = name
+        .strip_prefix(HOST_MODULE)
+        .and_then(|name| name.strip_prefix('_'))
+    else {
+        return false;
+    };
+    KNOWN_HOST_FUNCTIONS.contains(&function) || extra.iter().any(|extra| extra == function)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::write;
+
+    #[test]
+    fn test_unknown_imports() {
+        let mut object = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Bpf,
+            object::Endianness::Little,
+        );
+        for name in [
+            "env_storage_read",
+            "env_debug_print",
+            "syscall_math_op64",
+            "wasi_fd_write",
+            "env_custom_hook",
+        ] {
+            object.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 0,
+                kind: object::SymbolKind::Text,
+                scope: object::SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Undefined,
+                flags: object::SymbolFlags::None,
+            });
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contract.o");
+        fs::write(&path, object.write().unwrap()).unwrap();
+
+        assert_eq!(
+            unknown_imports(&path, &["custom_hook".to_string()]).unwrap(),
+            vec!["env_debug_print", "wasi_fd_write"]
+        );
+
+        let data = fs::read(&path).unwrap();
+        let file = object::File::parse(&*data).unwrap();
+        assert_eq!(foreign_imports(&file), vec!["wasi_fd_write"]);
+    }
+
+    #[test]
+    fn test_known_host_functions() {
+        // the template reads the storage with `storage_read` and `register_len`
+        for function in ["register_len", "storage_read", "gas_left", "call_contract2"] {
+            assert!(KNOWN_HOST_FUNCTIONS.contains(&function), "{}", function);
+        }
+        assert!(!KNOWN_HOST_FUNCTIONS.contains(&"remaining_gas"));
+        assert_eq!(KNOWN_HOST_FUNCTIONS.len(), 31);
+    }
+}
@@ -0,0 +1,127 @@
+use super::build::{
+    get_llc_command, get_llvm_command, BuildError, DEFAULT_WASM_TARGET as WASM_TARGET,
+};
+use super::config::BuildConfig;
+use super::which::which;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A resolved external tool used by the build pipeline
+#[derive(Debug, Clone)]
+pub struct ToolInfo {
+    pub command: String,
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+}
+
+/// Toolchain details useful for bug reports and CI logs
+#[derive(Debug, Clone)]
+pub struct ToolchainInfo {
+    pub version: String,
+    pub llc: Option<ToolInfo>,
+    pub llvm_strip: Option<ToolInfo>,
+    pub object_file_version: i64,
+    pub expected_runtime_version: i64,
+    pub wasm_target_installed: bool,
+}
+
+impl ToolInfo {
+    fn resolve(command: String) -> Self {
+        let path = which(command.clone());
+        let version = Command::new(&command)
+            .arg("--version")
+            .output()
+            .ok()
+            .and_then(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .find(|line| line.contains("version"))
+                    .map(|line| line.trim().to_string())
+            });
+        Self {
+            command,
+            path,
+            version,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "command": self.command,
+            "path": self.path,
+            "version": self.version,
+        })
+    }
+}
+
+impl ToolchainInfo {
+    /// The versions come from the [`BuildConfig`] of the current directory, an invalid
+    /// `l1x.toml` or environment override is an error rather than the defaults
+    pub fn collect() -> Result<Self, BuildError> {
+        let config = BuildConfig::load(Path::new("."))?;
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            llc: get_llc_command().ok().map(ToolInfo::resolve),
+            llvm_strip: get_llvm_command().ok().map(ToolInfo::resolve),
+            object_file_version: config.object_file_version,
+            expected_runtime_version: config.expected_runtime_version,
+            wasm_target_installed: is_wasm_target_installed(),
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "llc": self.llc.as_ref().map(ToolInfo::to_json),
+            "llvm_strip": self.llvm_strip.as_ref().map(ToolInfo::to_json),
+            "object_file_version": self.object_file_version,
+            "expected_runtime_version": self.expected_runtime_version,
+            "wasm_target": WASM_TARGET,
+            "wasm_target_installed": self.wasm_target_installed,
+        })
+    }
+
+    pub fn print(&self) {
+        println!("cargo-l1x {}", self.version);
+        print_tool("llc", &self.llc);
+        print_tool("llvm-strip", &self.llvm_strip);
+        println!("object file version: {}", self.object_file_version);
+        println!(
+            "expected runtime version: {}",
+            self.expected_runtime_version
+        );
+        println!(
+            "{} target installed: {}",
+            WASM_TARGET, self.wasm_target_installed
+        );
+    }
+}
+
+fn print_tool(name: &str, tool: &Option<ToolInfo>) {
+    match tool {
+        Some(tool) => println!(
+            "{}: {} ({})",
+            name,
+            tool.path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| tool.command.clone()),
+            tool.version.as_deref().unwrap_or("unknown version")
+        ),
+        None => println!("{}: not found", name),
+    }
+}
+
+/// Checks the active rustc sysroot for the standard library of the wasm target
+fn is_wasm_target_installed() -> bool {
+    let output = match Command::new("rustc").arg("--print").arg("sysroot").output() {
+        Ok(output) if output.status.success() => output,
+        _ => return false,
+    };
+    let sysroot = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    PathBuf::from(sysroot)
+        .join("lib/rustlib")
+        .join(WASM_TARGET)
+        .exists()
+}
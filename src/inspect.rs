@@ -0,0 +1,190 @@
+use super::build::{EXPECTED_RUNTIME_VERSION, META_SYMBOL_PREFIX};
+use super::exit_code;
+use object::{Object, ObjectSection, ObjectSymbol};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum InspectError {
+    #[error("Failed to read object file {0}: {1}")]
+    IoError(PathBuf, std::io::Error),
+    #[error("Invalid object file {0}: {1}")]
+    ParseError(PathBuf, object::Error),
+    #[error("{0} has no version information, it was not built by cargo-l1x")]
+    NoVersionInfo(PathBuf),
+}
+
+impl InspectError {
+    /// Stable identifier of the error for scripts, see `--json`
+    pub fn code(&self) -> &'static str {
+        match self {
+            InspectError::IoError(..) => "inspect-io",
+            InspectError::ParseError(..) => "inspect-parse",
+            InspectError::NoVersionInfo(_) => "inspect-no-version-info",
+        }
+    }
+
+    /// The exit code of `cargo l1x` for the error, see [`exit_code`]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            InspectError::IoError(..) => exit_code::FAILURE,
+            InspectError::ParseError(..) | InspectError::NoVersionInfo(_) => exit_code::OBJECT,
+        }
+    }
+
+    /// The data of the error as JSON, see `--json`
+    pub fn context(&self) -> Value {
+        match self {
+            InspectError::IoError(path, _)
+            | InspectError::ParseError(path, _)
+            | InspectError::NoVersionInfo(path) => json!({ "path": path }),
+        }
+    }
+}
+
+/// The versions written in the `_version` section by the build, and the `--meta`
+/// entries of the `_meta` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectVersions {
+    pub object_version: Option<i64>,
+    pub expected_runtime_version: Option<i64>,
+    pub meta: Vec<(String, String)>,
+}
+
+impl ObjectVersions {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "object_version": self.object_version,
+            "expected_runtime_version": self.expected_runtime_version,
+            "meta": self
+                .meta
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                .collect::<serde_json::Map<_, _>>(),
+        })
+    }
+
+    pub fn print(&self) {
+        let show = |version: Option<i64>| match version {
+            Some(version) => version.to_string(),
+            None => "missing".to_string(),
+        };
+        println!("Object file version:      {}", show(self.object_version));
+        println!(
+            "Expected runtime version: {}",
+            show(self.expected_runtime_version)
+        );
+        if self.expected_runtime_version != Some(EXPECTED_RUNTIME_VERSION) {
+            println!(
+                "⚠️  This cargo-l1x builds objects for runtime version {}",
+                EXPECTED_RUNTIME_VERSION
+            );
+        }
+        for (key, value) in &self.meta {
+            println!("{}: {}", key, value);
+        }
+    }
+}
+
+/// Reads back the versions embedded in a contract object file
+pub fn inspect_object<P: AsRef<Path>>(path: P) -> Result<ObjectVersions, InspectError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| InspectError::IoError(path.to_path_buf(), e))?;
+    let file =
+        object::File::parse(&*data).map_err(|e| InspectError::ParseError(path.to_path_buf(), e))?;
+
+    let versions = ObjectVersions {
+        object_version: read_i64_symbol(&file, "_OBJECT_VERSION"),
+        expected_runtime_version: read_i64_symbol(&file, "_EXPECTED_RUNTIME_VERSION"),
+        meta: read_meta(&file),
+    };
+    if versions.object_version.is_none() && versions.expected_runtime_version.is_none() {
+        return Err(InspectError::NoVersionInfo(path.to_path_buf()));
+    }
+    Ok(versions)
+}
+
+fn read_i64_symbol(file: &object::File, name: &str) -> Option<i64> {
+    let symbol = file.symbols().find(|symbol| symbol.name() == Ok(name))?;
+    let bytes: [u8; 8] = symbol_data(file, &symbol, 8)?.try_into().ok()?;
+    Some(if file.is_little_endian() {
+        i64::from_le_bytes(bytes)
+    } else {
+        i64::from_be_bytes(bytes)
+    })
+}
+
+fn read_meta(file: &object::File) -> Vec<(String, String)> {
+    file.symbols()
+        .filter_map(|symbol| {
+            let key = symbol.name().ok()?.strip_prefix(META_SYMBOL_PREFIX)?;
+            let value = symbol_data(file, &symbol, usize::try_from(symbol.size()).ok()?)?;
+            Some((key.to_string(), String::from_utf8_lossy(value).into_owned()))
+        })
+        .collect()
+}
+
+fn symbol_data<'data>(
+    file: &object::File<'data>,
+    symbol: &object::Symbol<'data, '_>,
+    size: usize,
+) -> Option<&'data [u8]> {
+    let section = file.section_by_index(symbol.section_index()?).ok()?;
+    let data = section.data().ok()?;
+    let offset = usize::try_from(symbol.address().checked_sub(section.address())?).ok()?;
+    data.get(offset..offset + size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::write;
+
+    #[test]
+    fn test_inspect_object() {
+        let mut object = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Bpf,
+            object::Endianness::Little,
+        );
+        let section =
+            object.add_section(Vec::new(), b"_version".to_vec(), object::SectionKind::Data);
+        for (name, value) in [
+            ("_OBJECT_VERSION", &1i64.to_le_bytes()[..]),
+            ("_EXPECTED_RUNTIME_VERSION", &3i64.to_le_bytes()),
+            ("_meta.author", b"jo"),
+        ] {
+            let symbol = object.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: value.len() as u64,
+                kind: object::SymbolKind::Data,
+                scope: object::SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Undefined,
+                flags: object::SymbolFlags::None,
+            });
+            object.add_symbol_data(symbol, section, value, 1);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contract.o");
+        fs::write(&path, object.write().unwrap()).unwrap();
+
+        assert_eq!(
+            inspect_object(&path).unwrap(),
+            ObjectVersions {
+                object_version: Some(1),
+                expected_runtime_version: Some(3),
+                meta: vec![("author".to_string(), "jo".to_string())],
+            }
+        );
+
+        fs::write(&path, b"not an object").unwrap();
+        assert!(matches!(
+            inspect_object(&path),
+            Err(InspectError::ParseError(..))
+        ));
+    }
+}
@@ -1,3 +1,21 @@
+pub mod abi;
+pub mod borsh_schema;
 pub mod build;
+pub mod build_events;
+pub mod build_log;
+pub mod config;
+pub mod container;
 pub mod create;
+pub mod exit_code;
+pub mod imports;
+pub mod info;
+pub mod inspect;
+pub mod object_diff;
+pub mod script;
+pub mod sink;
+pub mod stale;
+pub mod symbol_sizes;
+pub mod template_matrix;
+pub mod upgrade;
+pub mod wasm_features;
 pub mod which;
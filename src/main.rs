@@ -1,22 +1,193 @@
-use cargo_l1x::{build::build, create::create};
+use cargo_l1x::{
+    abi::{abi_diff, AbiError},
+    build::{
+        build_with_metadata, env_name, load_metadata, split_cargo_args, translate, BuildError,
+        BuildOptions, MessageFormat, ENV_FLAGS, ENV_OPTIONS,
+    },
+    config::BuildConfig,
+    create::{create_with_options, CreateError, CreateOptions, Template, DEFAULT_NET_CONCURRENCY},
+    exit_code,
+    info::ToolchainInfo,
+    inspect::{inspect_object, InspectError},
+    template_matrix::{print_matrix, self_test, test_templates},
+    upgrade::{upgrade, UpgradeError},
+};
 use colored::Colorize;
 
 use anyhow::{anyhow, Result};
 
 use clap::Parser;
+use std::io::IsTerminal;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(long_about = None, bin_name = "cargo l1x create")]
 struct CreateCli {
-    #[arg(help = "The name of the contract to create")]
-    name: String,
+    #[arg(
+        help = "The name of the contract to create",
+        required_unless_present = "print_template_url"
+    )]
+    name: Option<String>,
     #[arg(
         short,
         long,
+        env = "L1X_TEMPLATE",
+        help = "The template to use when creating the contract (local_default/default/ft/nft). Templates from https://github.com/L1X-Foundation/cargo-l1x-templates are used. Asked interactively on a terminal, 'local_default' otherwise"
+    )]
+    template: Option<String>,
+    #[arg(
+        long,
+        env = "L1X_SDK_VERSION",
+        value_name = "SEMVER",
+        help = "Pin the l1x-sdk dependency of the generated contract to this version requirement"
+    )]
+    sdk_version: Option<String>,
+    #[arg(
+        long,
+        help = "Generate a Cargo.lock in the new project to pin its dependencies, skipped with a warning when offline, and record the template archive in its .l1x-template.lock"
+    )]
+    lock: bool,
+    #[arg(
+        long,
+        value_name = "LOCK_FILE",
+        help = "Fail unless the template archive matches this .l1x-template.lock, written by --lock"
+    )]
+    locked: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "L1X_TEMPLATE_SUBDIR",
+        value_name = "PATH",
+        help = "Only use this directory of the template archive, for repositories hosting several templates"
+    )]
+    template_subdir: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "L1X_LICENSE",
+        value_name = "SPDX-ID",
+        help = "License of the project: MIT, Apache-2.0 or 'MIT OR Apache-2.0'. Sets the license field of Cargo.toml and writes the LICENSE file(s), the template's license is kept otherwise"
+    )]
+    license: Option<String>,
+    #[arg(
+        long,
+        env = "L1X_TEMPLATE_CACHE_DIR",
+        value_name = "DIR",
+        help = "Use the template archive <DIR>/<TEMPLATE>.zip when it exists instead of downloading it, for offline machines"
+    )]
+    template_cache: Option<PathBuf>,
+    #[arg(
+        long,
+        env = "L1X_NET_CONCURRENCY",
+        value_name = "N",
+        help = "Maximum number of concurrent HTTP requests, 4 by default. Lower it on constrained or rate-limited networks"
+    )]
+    net_concurrency: Option<NonZeroUsize>,
+    #[arg(
+        long,
+        overrides_with = "no_run_hooks",
+        help = "Run the l1x-post-create script of the template in the new project. Off by default, review the script of an untrusted template first"
+    )]
+    run_hooks: bool,
+    #[arg(
+        long,
+        overrides_with = "run_hooks",
+        help = "Don't run the l1x-post-create script of the template, the default"
+    )]
+    no_run_hooks: bool,
+    #[arg(
+        long,
+        help = "Create the project in an existing directory that isn't empty, overwriting the files of the template. An empty directory doesn't need it"
+    )]
+    force: bool,
+    #[arg(
+        long,
+        help = "Install a git pre-commit hook running `cargo l1x build` in the project, blocking the commit when it fails. Skipped when the project isn't in a git repository"
+    )]
+    init_git_hooks: bool,
+    #[arg(
+        long,
+        help = "Print the URL the template is downloaded from and exit, without creating anything"
+    )]
+    print_template_url: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(long_about = None, bin_name = "cargo l1x upgrade")]
+struct UpgradeCli {
+    #[arg(
+        short,
+        long,
+        env = "L1X_TEMPLATE",
         default_value = "local_default",
-        help = "The template to use when creating the contract (default/ft/nft). Templates from https://github.com/L1X-Foundation/cargo-l1x-templates are used"
+        help = "The template the project was created from"
     )]
     template: String,
+    #[arg(long, default_value = ".", help = "The project directory")]
+    path: PathBuf,
+    #[arg(long, help = "Only report the differences, don't change the project")]
+    dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(long_about = None, bin_name = "cargo l1x inspect")]
+struct InspectCli {
+    #[arg(help = "The contract object file")]
+    object: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+#[command(long_about = None, bin_name = "cargo l1x list-templates")]
+struct ListTemplatesCli {
+    #[arg(
+        long,
+        env = "L1X_TEMPLATE_CACHE_DIR",
+        value_name = "DIR",
+        help = "Read the descriptions of the template archives <DIR>/<TEMPLATE>.zip"
+    )]
+    template_cache: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+#[command(long_about = None, bin_name = "cargo l1x translate")]
+struct TranslateCli {
+    #[arg(help = "The wasm module of the contract")]
+    wasm: PathBuf,
+    #[arg(
+        help = "The LLVM IR file to write, the llc input is written next to it as <LL>.versioned.ll"
+    )]
+    ll: PathBuf,
+    #[arg(
+        long,
+        help = "Don't write the versioned llc input, llc compiles <LL> as it is"
+    )]
+    no_version_info: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Written to the _OBJECT_VERSION global, from l1x.toml by default"
+    )]
+    object_file_version: Option<i64>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Written to the _EXPECTED_RUNTIME_VERSION global, from l1x.toml by default"
+    )]
+    expected_runtime_version: Option<i64>,
+    #[arg(
+        long,
+        value_name = "KEY=VALUE",
+        help = "Embed KEY=VALUE in the _meta section, can be repeated"
+    )]
+    meta: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+#[command(long_about = None, bin_name = "cargo l1x abi-diff")]
+struct AbiDiffCli {
+    #[arg(help = "The ABI of the deployed contract")]
+    old: PathBuf,
+    #[arg(help = "The ABI of the new contract version")]
+    new: PathBuf,
 }
 
 fn get_command(args: &mut Vec<String>) -> String {
@@ -41,6 +212,90 @@ fn get_command(args: &mut Vec<String>) -> String {
 
 fn main() -> Result<()> {
     let mut args: Vec<String> = std::env::args().collect();
+    // errors are printed as JSON for scripts, `info` also prints its report as JSON
+    let json = args.iter().any(|arg| arg == "--json");
+    args.retain(|arg| arg != "--json");
+    let no_color = args.iter().any(|arg| arg == "--no-color");
+    args.retain(|arg| arg != "--no-color");
+    configure_color(no_color);
+
+    if let Err(e) = run(args, json) {
+        if json {
+            eprintln!("{}", error_to_json(&e));
+        } else {
+            eprintln!("Error: {:?}", e);
+        }
+        std::process::exit(error_details(&e).2);
+    }
+    Ok(())
+}
+
+/// An error of the command line itself, e.g. an unknown command
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+struct CliError {
+    code: &'static str,
+    exit_code: i32,
+    message: String,
+}
+
+impl CliError {
+    fn usage(code: &'static str, message: String) -> Self {
+        Self {
+            code,
+            exit_code: exit_code::USAGE,
+            message,
+        }
+    }
+}
+
+/// `--no-color` or a non-empty `NO_COLOR` disable the styling of the tool and of cargo,
+/// see https://no-color.org
+fn configure_color(no_color: bool) {
+    match std::env::var_os("NO_COLOR") {
+        _ if no_color => {}
+        Some(value) if !value.is_empty() => {}
+        // `colored` treats an empty `NO_COLOR` as set, the spec doesn't
+        Some(_) => {
+            colored::control::set_override(std::io::stdout().is_terminal());
+            return;
+        }
+        None => return,
+    }
+    colored::control::set_override(false);
+    std::env::set_var("CARGO_TERM_COLOR", "never");
+}
+
+/// The code, the context and the exit code of an error, see [`exit_code`]
+fn error_details(e: &anyhow::Error) -> (&'static str, serde_json::Value, i32) {
+    if let Some(e) = e.downcast_ref::<BuildError>() {
+        (e.code(), e.context(), e.exit_code())
+    } else if let Some(e) = e.downcast_ref::<CreateError>() {
+        (e.code(), e.context(), e.exit_code())
+    } else if let Some(e) = e.downcast_ref::<AbiError>() {
+        (e.code(), e.context(), e.exit_code())
+    } else if let Some(e) = e.downcast_ref::<InspectError>() {
+        (e.code(), e.context(), e.exit_code())
+    } else if let Some(e) = e.downcast_ref::<UpgradeError>() {
+        (e.code(), e.context(), e.exit_code())
+    } else if let Some(e) = e.downcast_ref::<CliError>() {
+        (e.code, serde_json::json!({}), e.exit_code)
+    } else {
+        ("other", serde_json::json!({}), exit_code::FAILURE)
+    }
+}
+
+/// `{ "error": ..., "code": ..., "context": {...} }`, the code is stable across versions
+fn error_to_json(e: &anyhow::Error) -> serde_json::Value {
+    let (code, context, _) = error_details(e);
+    serde_json::json!({
+        "error": format!("{:#}", e),
+        "code": code,
+        "context": context,
+    })
+}
+
+fn run(mut args: Vec<String>, json: bool) -> Result<()> {
     let command = get_command(&mut args);
 
     match command.as_str() {
@@ -49,11 +304,20 @@ fn main() -> Result<()> {
                 "cargo l1x <COMMAND>",
                 vec![
                 "build [OPTIONS]          Build the contract. See `cargo l1x build --help` for more information.",
-                "create <NAME> [OPTIONS]  Create a new contract. See `cargo l1x create --help` for more information."
+                "create <NAME> [OPTIONS]  Create a new contract. See `cargo l1x create --help` for more information.",
+                "list-templates           List the templates with their descriptions, from their l1x-template.toml when the archive is bundled or cached",
+                "upgrade [OPTIONS]        Bump l1x-sdk and add the new files of the project's template. See `cargo l1x upgrade --help`",
+                "info [--json]            Display the detected toolchain and object versions",
+                "inspect <OBJECT>         Display the object and runtime versions embedded in a contract object file (--json for JSON)",
+                "self-test                Build the bundled template in a temporary directory to check the toolchain",
+                "abi-diff <OLD> <NEW>     Report ABI changes between two contract versions and fail on breaking ones",
+                "translate <WASM> <LL>    Translate a wasm module to the LLVM IR compiled by llc, as in the scripts of --emit-script",
                 ],
                 vec![
                 "-h, --help               Display this help message",
                 "-V, --version            Display version information",
+                "--json                   Print errors as JSON on stderr: {\"error\", \"code\", \"context\"}",
+                "--no-color               Disable colors, also done by a non-empty NO_COLOR environment variable",
                 ],
                 vec![]
             );
@@ -62,62 +326,386 @@ fn main() -> Result<()> {
             println!("cargo-l1x {}", env!("CARGO_PKG_VERSION"));
         }
         "build" => {
-            let args = args[1..].to_vec();
-            if args.contains(&"--help".to_string()) || args.contains(&"-h".to_string()) {
+            let args = with_env_defaults(translate_deprecated_flags(args[1..].to_vec(), json));
+            let (mut own_args, cargo_args) = split_cargo_args(args.clone());
+            if own_args.contains(&"--help".to_string()) || own_args.contains(&"-h".to_string()) {
                 display_help(
-                        "cargo l1x build [OPTIONS] [CARGO_OPTIONS]",
+                        "cargo l1x build [OPTIONS] [CARGO_OPTIONS] [-- CARGO_OPTIONS]",
                         vec![],
                         vec![
                             "-h, --help               Display this help message",
-                            "--no-strip               Do not strip debug information and symbols from the contract binary (useful for debugging)",
+                            "--strip-mode <MODE>      'full' strips debug information and symbols from the contract binary with llvm-strip, 'none' keeps them (useful for debugging). Default: full",
+                            "--strip-binary <PATH>    The strip tool to use. Default: llvm-strip 17-19, else GNU strip with a warning",
+                            "--no-link-strip          Do not strip symbols when linking the wasm module. Combine with --strip-mode none to keep all symbols",
+                            "--split-debug            Keep an unstripped copy of the contract binary as '<name>.debug.o'",
+                            "--expect-runtime <N>     Fail if the contract would not be built for runtime version N",
+                            "--check-stale            Warn about contract object files that are older than their sources, without building",
+                            "--message-format <FMT>   Render cargo diagnostics and the tool messages as human, short or json",
+                            "--members <GLOB>         Build only the workspace members whose directory matches GLOB (can be repeated)",
+                            "--since <REF>            Only build the workspace members changed since the git REF, or whose path dependencies did",
+                            "--pad-to <BYTES>         Pad the contract binary with zeros up to a multiple of BYTES",
+                            "--mattr <FEATURES>       BPF features passed to llc, e.g. '+alu32,-dwarfris'. Known features: alu32, dwarfris",
+                            "--stack-size <BYTES>     The eBPF stack size of the contract (default: 8192)",
+                            "--cpu <CPU>              The BPF processor passed to llc: generic, probe, v1, v2 or v3 (default: v3)",
+                            "--wasm-target <TRIPLE>   The wasm target the contracts are built for (default: wasm32-unknown-unknown)",
+                            "--runtime-version <N>    The runtime version of the target nodes, the expected runtime version by default. Selects the newest BPF processor it supports, vN for version N, and rejects a --cpu it can't execute or probe",
+                            "--out-dir <DIR>          Where to put the contract binaries (default: target/l1x/release, target/l1x/PROFILE with --profile)",
+                            "--no-version-info        Compile the LLVM IR without the object and runtime versions, for debugging. Such objects are not meant to be deployed",
+                            "--no-check-elf           Skip the final check of the object file: BPF ELF, versions and undefined symbols. On by default, --check-elf",
+                            "--meta <KEY=VALUE>       Embed the entry in the '_meta' section of the objects, can be repeated",
+                            "--emit-ll-stable         Also write '<name>.stable.ll', the LLVM IR without machine specific paths and with the unnamed values renumbered, to diff builds",
+                            "--emit-commands          Write the llc and strip commands with their arguments to 'l1x-commands.json', like a compilation database",
+                            "--skip-llc               Only build the wasm and translate it to LLVM IR, llc and llvm-strip are not run",
+                            "--borsh-schema           Write the borsh layout of the contract method arguments and results to '<name>.borsh.json'",
+                            "--contract-type <TYPE>   The type of the #[contract] impl the ABI and --borsh-schema are read from, when a crate has several",
+                            "--emit-abi-only          Only write the ABI of the contracts, read from their sources, to '<name>.abi.json'. Nothing is compiled",
+                            "--compress <ALGO>        Also write the contract binary compressed with gzip or zstd as '<name>.o.gz' or '<name>.o.zst'",
+                            "--compress-only          Only keep the compressed contract binary",
+                            "--container              Run the whole build in a container with a pinned LLVM, using docker or podman",
+                            "--container-image <IMG>  The image used by --container (default: l1xfoundation/cargo-l1x:<VERSION>)",
+                            "--profile-size           Build with several opt-level and lto settings, compare the object sizes and keep the smallest build",
+                            "--size-profile <NAME>    Build with these settings, or keep this build with --profile-size: z, z-lto, s, s-lto",
+                            "--deny-warnings          Fail the build on compiler warnings, '-D warnings' is added to RUSTFLAGS",
+                            "--config <NAME>          Build with the [profiles.NAME] settings of l1x.toml, repeat to build several profiles, the files are suffixed with -NAME",
+                            "--require-clean          Refuse to build when the git working tree has uncommitted changes, skipped outside of git repositories",
+                            "--allow-dirty            Build even if --require-clean (or L1X_REQUIRE_CLEAN) finds uncommitted changes",
+                            "--hermetic               Run cargo without RUSTFLAGS, RUSTC_WRAPPER, CARGO_PROFILE_* and the other rust and cargo variables of the environment",
+                            "--fail-on-warning        Fail after the build when the compiler emitted warnings, listing the count of each crate",
+                            "--deny-unknown-sections  Fail when an object file has a section the runtime may not load, listing them",
+                            "--strict-imports         Fail when a contract imports a function the runtime doesn't provide, instead of warning",
+                            "--strict                 Fail when a crate to build has no #[contract] impl, instead of warning",
+                            "--max-artifacts <N>      Fail when the build produces more than N wasm modules, e.g. to catch an accidental cdylib in CI",
+                            "--explain-size           List the largest functions and data of each object file, before the strip, with their sizes",
+                            "--assert-deterministic   Build twice from scratch and fail when the object files differ, naming the first stage that differs",
+                            "--clean-first            Remove the l1x output directory before building, so no stale object file is left",
+                            "--clean-cargo            Also run cargo clean for the wasm target and profile before building, implies --clean-first",
+                            "--compare-to <PATH>      Compare the object file to a reference one and fail listing the size, section and symbol differences",
+                            "--output-format <FMT>    elf (default) or raw, raw also writes the code and data sections of the object file as a flat <name>.bin",
+                            "--summary-only           Only print a final report of the contracts: size, sha256, versions and the build time",
+                            "--log-file <PATH>        Also write a timestamped record of the commands run and of their output to PATH",
+                            "--retry-on-llc-crash <N> Run llc again up to N times when it crashes, compile errors fail at once",
+                            "--keep-going             Continue with the other crates and contracts when one fails, and list the failures at the end",
+                            "--dump-config            Print the settings of the build once the options, environment, l1x.toml and profiles are merged, without building",
+                            "--list-artifacts         List the object, LLVM IR and schema files of previous builds, without building",
+                            "--print-llc-command <LL> Print the llc command line that would compile the LL file, without building",
+                            "--emit-script <FILE>     Write a shell script running the cargo build, llc and strip steps, without building",
+                            "--examples               Also build the example contracts. Their object files are placed in 'l1x/release/examples'",
                             "CARGO_OPTIONS            Any optsions that can be passed to `cargo build` can be passed here. See `cargo build --help` for more information. There is some exceptions:",
-                            "                         --target | --message-format | --version | --manifest-path | --profile",
+                            "                         --target | --version | --manifest-path. The contracts are built with --release unless --profile is given",
+                            "                         The options after -- are passed to cargo as they are, even if cargo-l1x has an option with the same name",
                         ],
                         vec![
-                            "LLVM_BIN_PATH            The path to 'bin' directory where 'llc' is stored. Useful if 'llc' is not present in any directory in PATH"     
+                            "LLVM_BIN_PATH            The path to 'bin' directory where 'llc' is stored. Useful if 'llc' is not present in any directory in PATH",
+                            "RUSTFLAGS                Passed to the wasm build, the flags needed by the options are appended",
+                            "L1X_<OPTION>             Default value of an option, e.g. L1X_STRIP_MODE=none, L1X_STACK_SIZE=4096, L1X_CPU=v2, L1X_OUT_DIR=out",
+                            "                         Options given on the command line take precedence over the environment,",
+                            "                         --no-<FLAG> or --<FLAG>=false turns off a flag set there",
                         ],
                     );
                 return Ok(());
             }
-            let target_dir = cargo_metadata::MetadataCommand::new()
-                .exec()
-                .expect("cargo metadata failed")
-                .target_directory;
+            let metadata = load_metadata(&args)?;
+            // only the arguments forwarded to cargo are checked, not the values of the tool options
+            let options = BuildOptions::from_args(&mut own_args)?;
             check_args_not_contains(
-                args.to_vec(),
-                vec![
-                    "--target",
-                    "--message-format",
-                    "--version",
-                    "--manifest-path",
-                    "--profile",
-                ],
+                own_args.into_iter().chain(cargo_args).collect(),
+                vec!["--target", "--version", "--manifest-path"],
             )?;
 
+            if options.skips_build()
+                || options.summary_only
+                || options.message_format == Some(MessageFormat::Json)
+            {
+                return Ok(build_with_metadata(args.to_vec(), &metadata)?);
+            }
+
             println!("Building contracts...");
-            build(args.to_vec(), target_dir.into())?;
+            build_with_metadata(args.to_vec(), &metadata)?;
 
             println!("🎉 Compilation and processing completed!");
         }
+        "info" => {
+            let info = ToolchainInfo::collect()?;
+            if json {
+                println!("{:#}", info.to_json());
+            } else {
+                info.print();
+            }
+        }
+        "list-templates" => {
+            let ListTemplatesCli { template_cache } = ListTemplatesCli::parse_from(args);
+            for template in Template::all() {
+                println!(
+                    "{:<14} {}",
+                    template.name(),
+                    template.describe(template_cache.as_deref())
+                );
+            }
+        }
+        "translate" => {
+            let TranslateCli {
+                wasm,
+                ll,
+                no_version_info,
+                object_file_version,
+                expected_runtime_version,
+                meta,
+            } = TranslateCli::parse_from(args);
+            let config = if no_version_info {
+                None
+            } else {
+                let mut config = BuildConfig::load(std::path::Path::new("."))?;
+                config.object_file_version =
+                    object_file_version.unwrap_or(config.object_file_version);
+                config.expected_runtime_version =
+                    expected_runtime_version.unwrap_or(config.expected_runtime_version);
+                Some(config)
+            };
+            translate(&wasm, &ll, &meta, config.as_ref())?;
+        }
+        "inspect" => {
+            let InspectCli { object } = InspectCli::parse_from(args);
+            let versions = inspect_object(object)?;
+            if json {
+                println!("{:#}", versions.to_json());
+            } else {
+                versions.print();
+            }
+        }
+        "upgrade" => {
+            let UpgradeCli {
+                template,
+                path,
+                dry_run,
+            } = UpgradeCli::parse_from(args);
+            let report = upgrade(&path, &template, dry_run)?;
+            if json {
+                println!("{:#}", report.to_json());
+            } else {
+                report.print(dry_run);
+            }
+        }
+        "abi-diff" => {
+            let AbiDiffCli { old, new } = AbiDiffCli::parse_from(args);
+            abi_diff(old, new)?;
+        }
+        "self-test" => {
+            println!("Building the bundled template...");
+            match self_test().failure {
+                None => println!("🎉 The toolchain works, a valid contract object file was built"),
+                Some((step, error)) => {
+                    return Err(CliError {
+                        code: "self-test",
+                        exit_code: exit_code::TOOLCHAIN,
+                        message: format!("Self-test failed at {}: {}", step, error),
+                    }
+                    .into());
+                }
+            }
+        }
+        // for the maintainers of the templates repository, not listed in the help
+        "test-templates" => {
+            let mut names = args[1..].to_vec();
+            let net_concurrency =
+                match take_option(&mut names, "--net-concurrency", "invalid-net-concurrency")? {
+                    Some(value) => value.parse().map_err(|_| {
+                        CliError::usage(
+                            "invalid-net-concurrency",
+                            format!("--net-concurrency takes a positive number, not '{}'", value),
+                        )
+                    })?,
+                    None => DEFAULT_NET_CONCURRENCY,
+                };
+            let template_cache =
+                take_option(&mut names, "--template-cache", "invalid-template-cache")?
+                    .or_else(|| std::env::var("L1X_TEMPLATE_CACHE_DIR").ok())
+                    .filter(|dir| !dir.is_empty())
+                    .map(PathBuf::from);
+            let templates = if !names.is_empty() {
+                names
+                    .iter()
+                    .map(|name| name.parse())
+                    .collect::<Result<Vec<Template>, _>>()?
+            } else {
+                Template::all().to_vec()
+            };
+            let results = test_templates(&templates, template_cache.as_deref(), net_concurrency);
+            print_matrix(&results);
+            let failed = results.iter().filter(|r| r.failure.is_some()).count();
+            if failed > 0 {
+                return Err(anyhow!("{} of {} templates failed", failed, results.len()));
+            }
+        }
         "create" => {
-            let CreateCli { name, template } = CreateCli::parse_from(args);
+            let CreateCli {
+                name,
+                template,
+                sdk_version,
+                lock,
+                locked,
+                template_subdir,
+                license,
+                template_cache,
+                net_concurrency,
+                run_hooks,
+                no_run_hooks: _,
+                force,
+                init_git_hooks,
+                print_template_url,
+            } = CreateCli::parse_from(args);
+            let template = match template {
+                Some(template) => template,
+                None if print_template_url => Template::default().name().to_string(),
+                None => select_template(template_cache.as_deref())?,
+            };
+            if print_template_url {
+                match template.parse::<Template>()?.url() {
+                    Some(url) => println!("{}", url),
+                    None => println!(
+                        "'{}' is bundled with cargo-l1x, nothing is downloaded",
+                        template
+                    ),
+                }
+                return Ok(());
+            }
+            let name = name.expect("The name is required without --print-template-url");
 
-            create(name, template.clone())?;
+            let options = CreateOptions {
+                sdk_version,
+                lock,
+                template_subdir,
+                locked,
+                license,
+                template_cache,
+                net_concurrency,
+                run_hooks,
+                force,
+                init_git_hooks,
+            };
+            create_with_options(name, template.clone(), options)?;
 
             println!("🎉 The contract was generated from '{}' template", template);
         }
         e => {
-            println!("Unknown command: {e}");
+            return Err(CliError::usage(
+                "unknown-command",
+                format!("Unknown command: {e}, see `cargo l1x --help`"),
+            )
+            .into());
         }
     };
     return Ok(());
 }
 
+/// Removes `option` and its value from `args`, a missing value is a `code` usage error
+fn take_option(
+    args: &mut Vec<String>,
+    option: &str,
+    code: &'static str,
+) -> Result<Option<String>, CliError> {
+    match args.iter().position(|arg| arg == option) {
+        Some(index) if index + 1 < args.len() => {
+            let value = args.remove(index + 1);
+            args.remove(index);
+            Ok(Some(value))
+        }
+        Some(_) => Err(CliError::usage(code, format!("{} needs a value", option))),
+        None => Ok(None),
+    }
+}
+
+/// Lets the user pick a template on a terminal, scripts get `local_default`
+fn select_template(cache_dir: Option<&std::path::Path>) -> Result<String> {
+    let templates = Template::all();
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Ok(Template::default().name().to_string());
+    }
+    let items: Vec<String> = templates
+        .iter()
+        .map(|template| format!("{:<14} {}", template.name(), template.describe(cache_dir)))
+        .collect();
+    let selection = dialoguer::Select::new()
+        .with_prompt("Select a template")
+        .items(&items)
+        .default(0)
+        .interact()?;
+    Ok(templates[selection].name().to_string())
+}
+
+/// Flags replaced by newer ones and the arguments they translate to
+const DEPRECATED_FLAGS: [(&str, &[&str]); 1] = [("--no-strip", &["--strip-mode", "none"])];
+
+/// Puts the defaults of the build options from their `L1X_*` environment variables in
+/// front of `args`, so the options given on the command line come last and win. A flag
+/// is enabled by any non-empty value except `0` and `false`
+fn with_env_defaults(args: Vec<String>) -> Vec<String> {
+    let mut defaults = Vec::new();
+    for flag in ENV_FLAGS {
+        match std::env::var(env_name(flag)) {
+            Ok(value)
+                if !value.is_empty() && value != "0" && !value.eq_ignore_ascii_case("false") =>
+            {
+                defaults.push(flag.to_string())
+            }
+            _ => {}
+        }
+    }
+    for option in ENV_OPTIONS {
+        match std::env::var(env_name(option)) {
+            Ok(value) if !value.is_empty() => defaults.push(format!("{}={}", option, value)),
+            _ => {}
+        }
+    }
+    defaults.extend(args);
+    defaults
+}
+
+/// Replaces the deprecated flags so existing scripts keep working, with a warning
+/// pointing to the replacement. The cargo arguments after `--` are left as they are
+fn translate_deprecated_flags(args: Vec<String>, json: bool) -> Vec<String> {
+    let mut translated = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            translated.push(arg);
+            translated.extend(args);
+            break;
+        }
+        match DEPRECATED_FLAGS.iter().find(|(flag, _)| *flag == arg) {
+            Some((flag, replacement)) => {
+                let replacement = replacement.join(" ");
+                if json {
+                    eprintln!(
+                        "{}",
+                        serde_json::json!({
+                            "warning": "deprecated-flag",
+                            "flag": flag,
+                            "replacement": replacement,
+                        })
+                    );
+                } else {
+                    eprintln!(
+                        "⚠️  '{}' is deprecated, use '{}' instead",
+                        flag, replacement
+                    );
+                }
+                translated.extend(replacement.split(' ').map(str::to_string));
+            }
+            None => translated.push(arg),
+        }
+    }
+    translated
+}
+
 fn check_args_not_contains(args: Vec<String>, exclude: Vec<&str>) -> Result<()> {
     for arg in args {
         for e in &exclude {
             if arg.starts_with(e) {
-                return Err(anyhow!("This argument cannot be changed: {}", e));
+                return Err(CliError::usage(
+                    "forbidden-argument",
+                    format!("This argument cannot be changed: {}", e),
+                )
+                .into());
             }
         }
     }
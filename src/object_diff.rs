@@ -0,0 +1,173 @@
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What changed in an object file compared to a reference build of it
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ObjectDiff {
+    /// `false` when the files differ in any byte, even when nothing below explains it
+    pub identical: bool,
+    /// The size of the object minus the size of the reference, in bytes
+    pub size_delta: i64,
+    pub sections: Vec<SectionDiff>,
+    /// The defined functions and data only in the object
+    pub added_symbols: Vec<String>,
+    /// The defined functions and data only in the reference
+    pub removed_symbols: Vec<String>,
+}
+
+/// A section that was added, removed or changed, `None` where it doesn't exist
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SectionDiff {
+    pub name: String,
+    pub reference_size: Option<u64>,
+    pub size: Option<u64>,
+}
+
+impl ObjectDiff {
+    /// The differences, one per line
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.size_delta != 0 {
+            lines.push(format!("size: {:+} bytes", self.size_delta));
+        }
+        for section in &self.sections {
+            lines.push(match (section.reference_size, section.size) {
+                (None, Some(size)) => format!("section {}: added, {} bytes", section.name, size),
+                (Some(size), None) => format!("section {}: removed, {} bytes", section.name, size),
+                (Some(reference_size), Some(size)) if reference_size != size => format!(
+                    "section {}: {} -> {} bytes",
+                    section.name, reference_size, size
+                ),
+                _ => format!("section {}: different content", section.name),
+            });
+        }
+        lines.extend(
+            self.added_symbols
+                .iter()
+                .map(|name| format!("symbol {}: added", name)),
+        );
+        lines.extend(
+            self.removed_symbols
+                .iter()
+                .map(|name| format!("symbol {}: removed", name)),
+        );
+        if !self.identical && lines.is_empty() {
+            lines.push("different bytes outside of the sections".to_string());
+        }
+        lines
+    }
+}
+
+/// Compares the object file `object` to `reference`, section by section and by the
+/// names of their defined function and data symbols
+pub fn object_diff(reference: &[u8], object: &[u8]) -> Result<ObjectDiff, object::Error> {
+    let (reference_file, object_file) = (
+        object::File::parse(reference)?,
+        object::File::parse(object)?,
+    );
+    let mut diff = ObjectDiff {
+        identical: reference == object,
+        size_delta: object.len() as i64 - reference.len() as i64,
+        ..ObjectDiff::default()
+    };
+
+    let (reference_sections, sections) = (sections(&reference_file), sections(&object_file));
+    let names: BTreeSet<&String> = reference_sections.keys().chain(sections.keys()).collect();
+    for name in names {
+        let (reference_data, data) = (reference_sections.get(name), sections.get(name));
+        if reference_data != data {
+            diff.sections.push(SectionDiff {
+                name: name.clone(),
+                reference_size: reference_data.map(|data| data.len() as u64),
+                size: data.map(|data| data.len() as u64),
+            });
+        }
+    }
+
+    let (reference_symbols, symbols) = (symbols(&reference_file), symbols(&object_file));
+    diff.added_symbols = symbols.difference(&reference_symbols).cloned().collect();
+    diff.removed_symbols = reference_symbols.difference(&symbols).cloned().collect();
+    Ok(diff)
+}
+
+fn sections<'data>(file: &object::File<'data>) -> BTreeMap<String, &'data [u8]> {
+    file.sections()
+        .filter_map(|section| Some((section.name().ok()?.to_string(), section.data().ok()?)))
+        .collect()
+}
+
+fn symbols(file: &object::File) -> BTreeSet<String> {
+    file.symbols()
+        .filter(|symbol| {
+            symbol.is_definition() && matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data)
+        })
+        .filter_map(|symbol| Some(symbol.name().ok()?.to_string()))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::{write, SectionKind};
+
+    fn object_file(symbols: &[(&str, SymbolKind, usize)], rodata: &[u8]) -> Vec<u8> {
+        let mut object = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Bpf,
+            object::Endianness::Little,
+        );
+        let text = object.add_section(Vec::new(), b".text".to_vec(), SectionKind::Text);
+        for &(name, kind, size) in symbols {
+            let symbol = object.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 0,
+                kind,
+                scope: write::SymbolScope::Linkage,
+                weak: false,
+                section: write::SymbolSection::Undefined,
+                flags: object::SymbolFlags::None,
+            });
+            object.add_symbol_data(symbol, text, &vec![0; size], 8);
+        }
+        if !rodata.is_empty() {
+            let section =
+                object.add_section(Vec::new(), b".rodata".to_vec(), SectionKind::ReadOnlyData);
+            object.append_section_data(section, rodata, 8);
+        }
+        object.write().unwrap()
+    }
+
+    #[test]
+    fn test_object_diff() {
+        let reference = object_file(
+            &[("get", SymbolKind::Text, 16), ("set", SymbolKind::Text, 8)],
+            &[],
+        );
+        let diff = object_diff(&reference, &reference).unwrap();
+        assert!(diff.identical);
+        assert!(diff.lines().is_empty());
+
+        let object = object_file(
+            &[
+                ("get", SymbolKind::Text, 16),
+                ("transfer", SymbolKind::Text, 24),
+            ],
+            &[1, 2, 3, 4],
+        );
+        let diff = object_diff(&reference, &object).unwrap();
+        assert!(!diff.identical);
+        assert_eq!(diff.added_symbols, ["transfer"]);
+        assert_eq!(diff.removed_symbols, ["set"]);
+        let lines = diff.lines();
+        assert!(lines[0].starts_with("size: +"));
+        assert!(lines.contains(&"section .text: 24 -> 40 bytes".to_string()));
+        assert!(lines.contains(&"section .rodata: added, 4 bytes".to_string()));
+        assert!(lines.contains(&"symbol transfer: added".to_string()));
+        assert!(lines.contains(&"symbol set: removed".to_string()));
+
+        assert!(object_diff(b"not an object", &object).is_err());
+    }
+}
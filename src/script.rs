@@ -0,0 +1,258 @@
+use super::build::{
+    cargo_build_command, contract_targets, io_error, llc_command, shell_quote, strip_command,
+    wasm_module_path, BuildError, BuildOptions,
+};
+use super::config::BuildConfig;
+use cargo_metadata::Metadata;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Writes a shell script running the steps of the build of `args` to `path`, without
+/// running anything. The translation to LLVM IR is done by the `l1x-wasm-llvmir` library
+/// linked into cargo-l1x, the script runs `cargo l1x translate` for it
+pub fn write_build_script(
+    path: &Path,
+    args: &[String],
+    options: &BuildOptions,
+    variants: &[(BuildOptions, BuildConfig)],
+    target_dir: &Path,
+    metadata: &Metadata,
+) -> Result<(), BuildError> {
+    let script = build_script(args, options, variants, target_dir, metadata)?;
+    fs::write(path, script).map_err(io_error("Could not write", path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            .map_err(io_error("Could not make executable", path))?;
+    }
+    Ok(())
+}
+
+fn build_script(
+    args: &[String],
+    options: &BuildOptions,
+    variants: &[(BuildOptions, BuildConfig)],
+    target_dir: &Path,
+    metadata: &Metadata,
+) -> Result<String, BuildError> {
+    let mut script = String::new();
+    let mut line = |text: &str| {
+        script.push_str(text);
+        script.push('\n');
+    };
+    line("#!/bin/sh");
+    line(&format!(
+        "# Generated by `cargo l1x build --emit-script`, cargo-l1x {}",
+        env!("CARGO_PKG_VERSION")
+    ));
+    line("# The wasm modules are translated to LLVM IR by `cargo l1x translate`");
+    if options.compress.is_some() || options.pad_to.is_some() || options.borsh_schema {
+        line("# --compress, --pad-to and --borsh-schema are not part of the script");
+    }
+    line("set -eu");
+    let current_dir =
+        std::env::current_dir().map_err(io_error("Could not read the current directory", "."))?;
+    line(&format!("cd {}", quote_path(&current_dir)));
+    line("");
+    line(&format_env_command(&cargo_build_command(args, options)));
+
+    for (wasm, is_example) in contract_modules(args, options, target_dir, metadata)? {
+        let stem = wasm
+            .file_stem()
+            .expect("Wasm file should have a file name")
+            .to_string_lossy()
+            .into_owned();
+        for (options, config) in variants {
+            let bin_dir = options.bin_dir(target_dir);
+            let out_dir = if is_example {
+                bin_dir.join("examples")
+            } else {
+                bin_dir
+            };
+            let ll = out_dir.join(match &options.profile_name {
+                Some(profile) => format!("{}-{}.ll", stem, profile),
+                None => format!("{}.ll", stem),
+            });
+            line("");
+            line(&format!("# {}", ll.display()));
+            line(&format!("mkdir -p {}", quote_path(&out_dir)));
+            for step in backend_steps(&wasm, &ll, options, config)? {
+                line(&step);
+            }
+        }
+    }
+    Ok(script)
+}
+
+/// The wasm modules the cargo build of `args` produces for the workspace contracts, and
+/// whether each is an example
+fn contract_modules(
+    args: &[String],
+    options: &BuildOptions,
+    target_dir: &Path,
+    metadata: &Metadata,
+) -> Result<Vec<(PathBuf, bool)>, BuildError> {
+    Ok(contract_targets(args, metadata, options.examples)?
+        .into_iter()
+        .map(|(target, is_example)| {
+            (
+                wasm_module_path(target, is_example, options, target_dir),
+                is_example,
+            )
+        })
+        .collect())
+}
+
+/// The shell commands translating `wasm` to `ll` and running
+/// [`build_ebpf`](crate::build::build_ebpf) on it
+fn backend_steps(
+    wasm: &Path,
+    ll: &Path,
+    options: &BuildOptions,
+    config: &BuildConfig,
+) -> Result<Vec<String>, BuildError> {
+    // the llc input is written by `translate`, like in the build
+    let mut translate = vec![
+        "cargo".to_string(),
+        "l1x".to_string(),
+        "translate".to_string(),
+    ];
+    let llc_input = if options.no_version_info {
+        translate.push("--no-version-info".to_string());
+        ll.to_path_buf()
+    } else {
+        translate.extend([
+            "--object-file-version".to_string(),
+            config.object_file_version.to_string(),
+            "--expected-runtime-version".to_string(),
+            config.expected_runtime_version.to_string(),
+        ]);
+        for (key, value) in &options.meta {
+            translate.extend([
+                "--meta".to_string(),
+                shell_quote(&format!("{}={}", key, value)),
+            ]);
+        }
+        ll.with_extension("versioned.ll")
+    };
+    translate.extend([quote_path(wasm), quote_path(ll)]);
+    let mut steps = vec![translate.join(" ")];
+    if options.skip_llc {
+        return Ok(steps);
+    }
+
+    let object = ll.with_extension("o");
+    let llc = llc_command(llc_input.as_path(), object.as_path(), options, config)?;
+    steps.push(format_env_command(&llc));
+    if !options.no_strip {
+        if options.split_debug {
+            let debug_object = ll.with_extension("debug.o");
+            steps.push(format!(
+                "cp {} {}",
+                quote_path(&object),
+                quote_path(&debug_object)
+            ));
+        }
+        steps.push(format_env_command(&strip_command(&object, options)?));
+    }
+    Ok(steps)
+}
+
+fn quote_path(path: &Path) -> String {
+    shell_quote(&path.to_string_lossy())
+}
+
+/// Formats a command as a shell command line, prefixed with `env` when it sets or removes
+/// environment variables
+fn format_env_command(command: &Command) -> String {
+    let mut removed = Vec::new();
+    let mut set = Vec::new();
+    for (name, value) in command.get_envs() {
+        let name = name.to_string_lossy();
+        match value {
+            Some(value) => set.push(shell_quote(&format!(
+                "{}={}",
+                name,
+                value.to_string_lossy()
+            ))),
+            // `env` only takes options before the assignments
+            None => removed.extend(["-u".to_string(), shell_quote(&name)]),
+        }
+    }
+    let mut words = Vec::new();
+    if !removed.is_empty() || !set.is_empty() {
+        words.push("env".to_string());
+    }
+    words.extend(removed);
+    words.extend(set);
+    words.extend(
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| shell_quote(&arg.to_string_lossy())),
+    );
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_env_command() {
+        let mut command = Command::new("cargo");
+        command
+            .env("RUSTFLAGS", "-C link-arg=-s")
+            .env_remove("RUSTC_WRAPPER")
+            .arg("build");
+        assert_eq!(
+            format_env_command(&command),
+            "env -u RUSTC_WRAPPER 'RUSTFLAGS=-C link-arg=-s' cargo build"
+        );
+        assert_eq!(
+            format_env_command(&Command::new("llvm-strip")),
+            "llvm-strip"
+        );
+    }
+
+    #[test]
+    fn test_backend_steps() {
+        let options = BuildOptions {
+            skip_llc: true,
+            meta: vec![("build-id".to_string(), "42".to_string())],
+            ..Default::default()
+        };
+        let config = BuildConfig::default();
+        let steps = backend_steps(
+            Path::new("counter.wasm"),
+            Path::new("out dir/counter.ll"),
+            &options,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            [format!(
+                "cargo l1x translate --object-file-version {} --expected-runtime-version {} --meta build-id=42 counter.wasm 'out dir/counter.ll'",
+                config.object_file_version, config.expected_runtime_version
+            )]
+        );
+
+        let options = BuildOptions {
+            skip_llc: true,
+            no_version_info: true,
+            ..Default::default()
+        };
+        let steps = backend_steps(
+            Path::new("counter.wasm"),
+            Path::new("counter.ll"),
+            &options,
+            &config,
+        );
+        assert_eq!(
+            steps.unwrap(),
+            ["cargo l1x translate --no-version-info counter.wasm counter.ll"]
+        );
+    }
+}
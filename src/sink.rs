@@ -0,0 +1,55 @@
+use super::create::{io_error, CreateError};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Destination of the files extracted from a template
+pub trait FileSink {
+    /// Creates `path` and all of its missing parents
+    fn create_dir_all(&mut self, path: &Path) -> Result<(), CreateError>;
+
+    /// Creates or truncates the file at `path` and fills it with `content`
+    fn write_file(&mut self, path: &Path, content: &mut dyn Read) -> Result<(), CreateError>;
+}
+
+/// Writes the extracted files to the filesystem, relative to the current directory
+#[derive(Debug, Default)]
+pub struct FsSink;
+
+impl FileSink for FsSink {
+    fn create_dir_all(&mut self, path: &Path) -> Result<(), CreateError> {
+        fs::create_dir_all(path).map_err(io_error("Couldn't create a directory", path))
+    }
+
+    fn write_file(&mut self, path: &Path, content: &mut dyn Read) -> Result<(), CreateError> {
+        let mut outfile = File::create(path).map_err(io_error("Couldn't create a file", path))?;
+        std::io::copy(content, &mut outfile).map_err(io_error("Couldn't copy file", path))?;
+        Ok(())
+    }
+}
+
+/// Keeps the extracted files in memory, useful for virtual filesystems
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pub dirs: Vec<PathBuf>,
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl FileSink for MemorySink {
+    fn create_dir_all(&mut self, path: &Path) -> Result<(), CreateError> {
+        if !self.dirs.iter().any(|dir| dir == path) {
+            self.dirs.push(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: &Path, content: &mut dyn Read) -> Result<(), CreateError> {
+        let mut buf = Vec::new();
+        content
+            .read_to_end(&mut buf)
+            .map_err(io_error("Couldn't read file", path))?;
+        self.files.insert(path.to_path_buf(), buf);
+        Ok(())
+    }
+}
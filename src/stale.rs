@@ -0,0 +1,128 @@
+use super::build::{io_error, BuildError};
+use cargo_metadata::Metadata;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Why an object file doesn't match the current sources
+#[derive(Debug, Clone, PartialEq)]
+pub enum Staleness {
+    /// The object file hasn't been built yet
+    Missing,
+    /// The given source file was modified after the object file was built
+    OlderThan(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct StaleObject {
+    pub package: String,
+    pub object: PathBuf,
+    pub staleness: Staleness,
+}
+
+/// Compares the object files in `bin_dir` with the sources of the workspace contracts
+pub fn find_stale_objects(
+    metadata: &Metadata,
+    bin_dir: &Path,
+) -> Result<Vec<StaleObject>, BuildError> {
+    let mut stale = Vec::new();
+    for package in metadata.workspace_packages() {
+        let Some(target) = package
+            .targets
+            .iter()
+            .find(|target| target.crate_types.iter().any(|t| t == "cdylib"))
+        else {
+            continue; // not a contract
+        };
+        let object = bin_dir.join(format!("{}.o", target.name.replace('-', "_")));
+        let staleness = match modified(&object) {
+            Err(_) => Some(Staleness::Missing),
+            Ok(object_time) => {
+                let package_dir = package
+                    .manifest_path
+                    .parent()
+                    .expect("Manifest path should have a parent directory");
+                newest_source(package_dir.as_std_path())?
+                    .filter(|(_, source_time)| *source_time > object_time)
+                    .map(|(source, _)| Staleness::OlderThan(source))
+            }
+        };
+        if let Some(staleness) = staleness {
+            stale.push(StaleObject {
+                package: package.name.clone(),
+                object,
+                staleness,
+            });
+        }
+    }
+    Ok(stale)
+}
+
+/// Prints a warning for every stale object
+pub fn warn_stale_objects(stale: &[StaleObject]) {
+    for object in stale {
+        match &object.staleness {
+            Staleness::Missing => eprintln!(
+                "⚠️  '{}': object file {:?} has not been built",
+                object.package, object.object
+            ),
+            Staleness::OlderThan(source) => eprintln!(
+                "⚠️  '{}': object file {:?} is older than {:?}, rebuild the contract",
+                object.package, object.object, source
+            ),
+        }
+    }
+}
+
+fn modified(path: &Path) -> std::io::Result<SystemTime> {
+    fs::metadata(path)?.modified()
+}
+
+/// Finds the most recently modified Rust source or manifest of a package
+fn newest_source(dir: &Path) -> Result<Option<(PathBuf, SystemTime)>, BuildError> {
+    let mut newest: Option<(PathBuf, SystemTime)> = None;
+    let entries = fs::read_dir(dir).map_err(io_error("Could not read directory", dir))?;
+    for entry in entries {
+        let entry = entry.map_err(io_error("Could not read directory", dir))?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let candidate = if path.is_dir() {
+            if name == "target" || name.starts_with('.') {
+                continue;
+            }
+            newest_source(&path)?
+        } else if path.extension() == Some("rs".as_ref()) || name == "Cargo.toml" {
+            modified(&path).ok().map(|time| (path, time))
+        } else {
+            None
+        };
+        match (&newest, candidate) {
+            (Some((_, newest_time)), Some((_, time))) if time <= *newest_time => {}
+            (_, Some(candidate)) => newest = Some(candidate),
+            (_, None) => {}
+        }
+    }
+    Ok(newest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newest_source_skips_target() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(dir.path().join("target/generated.rs"), "").unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let (newest, _) = newest_source(dir.path()).unwrap().unwrap();
+        assert_eq!(newest, dir.path().join("src/lib.rs"));
+    }
+}
@@ -0,0 +1,121 @@
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+use serde::Serialize;
+
+/// A function or data symbol of an object file and the bytes it takes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SymbolSize {
+    pub name: String,
+    /// `function` or `data`
+    pub kind: &'static str,
+    pub size: u64,
+}
+
+/// The defined function and data symbols of the object file `data`, the largest first.
+/// The bytes of the code and data sections no symbol covers, e.g. the padding and the
+/// constants llc emits without a symbol, are listed as `<unattributed code>` and
+/// `<unattributed data>`
+pub fn symbol_sizes(data: &[u8]) -> Result<Vec<SymbolSize>, object::Error> {
+    let file = object::File::parse(data)?;
+    let mut sizes = Vec::new();
+    let (mut code, mut data) = (0, 0);
+    for section in file.sections() {
+        match section.kind() {
+            SectionKind::Text => code += section.size(),
+            SectionKind::Data | SectionKind::ReadOnlyData | SectionKind::ReadOnlyString => {
+                data += section.size()
+            }
+            _ => {}
+        }
+    }
+    for symbol in file.symbols() {
+        if !symbol.is_definition() || symbol.size() == 0 {
+            continue;
+        }
+        let kind = match symbol.kind() {
+            SymbolKind::Text => "function",
+            SymbolKind::Data => "data",
+            _ => continue,
+        };
+        let Ok(name) = symbol.name() else {
+            continue;
+        };
+        let section_size = if kind == "function" {
+            &mut code
+        } else {
+            &mut data
+        };
+        *section_size = section_size.saturating_sub(symbol.size());
+        sizes.push(SymbolSize {
+            name: name.to_string(),
+            kind,
+            size: symbol.size(),
+        });
+    }
+    for (name, kind, size) in [
+        ("<unattributed code>", "function", code),
+        ("<unattributed data>", "data", data),
+    ] {
+        if size > 0 {
+            sizes.push(SymbolSize {
+                name: name.to_string(),
+                kind,
+                size,
+            });
+        }
+    }
+    sizes.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    Ok(sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::write;
+
+    #[test]
+    fn test_symbol_sizes() {
+        let mut object = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Bpf,
+            object::Endianness::Little,
+        );
+        let text = object.add_section(Vec::new(), b".text".to_vec(), SectionKind::Text);
+        let rodata = object.add_section(Vec::new(), b".rodata".to_vec(), SectionKind::ReadOnlyData);
+        for (name, kind, section, size) in [
+            ("transfer", SymbolKind::Text, text, 24),
+            ("helper", SymbolKind::Text, text, 8),
+            ("TABLE", SymbolKind::Data, rodata, 16),
+        ] {
+            let symbol = object.add_symbol(write::Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 0,
+                kind,
+                scope: write::SymbolScope::Compilation,
+                weak: false,
+                section: write::SymbolSection::Undefined,
+                flags: object::SymbolFlags::None,
+            });
+            object.add_symbol_data(symbol, section, &vec![0; size], 8);
+        }
+        // 8 bytes of code without a symbol
+        object.append_section_data(text, &[0; 8], 8);
+
+        let sizes: Vec<(String, &str, u64)> = symbol_sizes(&object.write().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind, symbol.size))
+            .collect();
+        assert_eq!(
+            sizes,
+            [
+                ("transfer".to_string(), "function", 24),
+                ("TABLE".to_string(), "data", 16),
+                ("<unattributed code>".to_string(), "function", 8),
+                ("helper".to_string(), "function", 8),
+            ]
+        );
+
+        assert!(symbol_sizes(b"not an object").is_err());
+    }
+}
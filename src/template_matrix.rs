@@ -0,0 +1,106 @@
+use super::build::{build, DEFAULT_OUTPUT_SUBDIR};
+use super::create::{
+    create_from_archive, prefetch_templates, CreateOptions, Template, TemplateArchive,
+    DEFAULT_NET_CONCURRENCY,
+};
+use super::inspect::inspect_object;
+use super::sink::FsSink;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+/// Outcome of creating and building one template
+#[derive(Debug)]
+pub struct TemplateResult {
+    pub template: &'static str,
+    /// The failed step and its error, `None` if the object file was built
+    pub failure: Option<(&'static str, String)>,
+}
+
+/// Creates every template in a temporary directory, builds it and checks that
+/// a non-empty object file with the version information is produced. Meant for the
+/// templates repository CI. The archives are downloaded beforehand, `net_concurrency` at once,
+/// unless they are in the offline bundle `cache_dir`
+pub fn test_templates(
+    templates: &[Template],
+    cache_dir: Option<&Path>,
+    net_concurrency: NonZeroUsize,
+) -> Vec<TemplateResult> {
+    let archives = match prefetch_templates(templates, cache_dir, net_concurrency) {
+        Ok(archives) => archives,
+        Err(e) => {
+            let failure = ("download", e.to_string());
+            return templates
+                .iter()
+                .map(|template| TemplateResult {
+                    template: template.name(),
+                    failure: Some(failure.clone()),
+                })
+                .collect();
+        }
+    };
+    archives
+        .into_iter()
+        .map(|(template, archive)| TemplateResult {
+            template: template.name(),
+            failure: archive
+                .map_err(|e| ("download", e.to_string()))
+                .and_then(test_template)
+                .err(),
+        })
+        .collect()
+}
+
+fn test_template(archive: TemplateArchive) -> Result<(), (&'static str, String)> {
+    let dir = tempfile::tempdir().map_err(|e| ("create", e.to_string()))?;
+    let project = dir.path().join("contract");
+    create_from_archive(
+        &project.to_string_lossy(),
+        archive,
+        CreateOptions::default(),
+        &mut FsSink,
+    )
+    .map_err(|e| ("create", e.to_string()))?;
+
+    let args = vec![
+        "--manifest-path".to_string(),
+        project.join("Cargo.toml").to_string_lossy().into_owned(),
+    ];
+    build(args, project.join("target")).map_err(|e| ("build", e.to_string()))?;
+
+    let object = find_object(&project.join("target").join(DEFAULT_OUTPUT_SUBDIR))
+        .ok_or(("object", "no object file was produced".to_string()))?;
+    inspect_object(&object).map_err(|e| ("inspect", e.to_string()))?;
+    Ok(())
+}
+
+fn find_object(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.extension() == Some("o".as_ref())
+                && fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false)
+        })
+}
+
+/// Builds the bundled template end-to-end to check that the toolchain works,
+/// nothing is left behind
+pub fn self_test() -> TemplateResult {
+    test_templates(&[Template::LocalDefault], None, DEFAULT_NET_CONCURRENCY)
+        .pop()
+        .expect("One template should be tested")
+}
+
+/// Prints a pass/fail line per template
+pub fn print_matrix(results: &[TemplateResult]) {
+    for result in results {
+        match &result.failure {
+            None => println!("✅ {:<14} passed", result.template),
+            Some((step, error)) => {
+                println!("❌ {:<14} failed at {}: {}", result.template, step, error)
+            }
+        }
+    }
+}
@@ -0,0 +1,468 @@
+use super::build::{BuildError, EXPECTED_RUNTIME_VERSION};
+use super::config::BuildConfig;
+use super::create::{create_into, io_error, CreateError, CreateOptions, TEMPLATE_MANIFEST_NAME};
+use super::exit_code;
+use super::sink::MemorySink;
+use cargo_metadata::semver::{Version, VersionReq};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use toml_edit::DocumentMut;
+
+/// The `l1x-sdk` version requirement of the contracts for each runtime version
+const RUNTIME_SDK_VERSIONS: [(i64, &str); 1] = [(EXPECTED_RUNTIME_VERSION, "0.3.1")];
+
+#[derive(Error, Debug)]
+pub enum UpgradeError {
+    #[error("{} is not a contract project, it has no Cargo.toml", .0.display())]
+    NotAProject(PathBuf),
+    #[error("Invalid {}: {message}", path.display())]
+    ManifestError { path: PathBuf, message: String },
+    #[error("No l1x-sdk version is known for runtime version {0}")]
+    UnknownRuntimeVersion(i64),
+    #[error(transparent)]
+    Create(#[from] CreateError),
+    #[error(transparent)]
+    Build(#[from] BuildError),
+}
+
+impl UpgradeError {
+    /// Stable identifier of the error for scripts, see `--json`
+    pub fn code(&self) -> &'static str {
+        match self {
+            UpgradeError::NotAProject(_) => "not-a-project",
+            UpgradeError::ManifestError { .. } => "manifest",
+            UpgradeError::UnknownRuntimeVersion(_) => "unknown-runtime-version",
+            UpgradeError::Create(e) => e.code(),
+            UpgradeError::Build(e) => e.code(),
+        }
+    }
+
+    /// The exit code of `cargo l1x` for the error, see [`exit_code`]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            UpgradeError::NotAProject(_) | UpgradeError::UnknownRuntimeVersion(_) => {
+                exit_code::USAGE
+            }
+            UpgradeError::ManifestError { .. } => exit_code::FAILURE,
+            UpgradeError::Create(e) => e.exit_code(),
+            UpgradeError::Build(e) => e.exit_code(),
+        }
+    }
+
+    /// The data of the error as JSON, see `--json`
+    pub fn context(&self) -> Value {
+        match self {
+            UpgradeError::NotAProject(path) => json!({ "path": path }),
+            UpgradeError::ManifestError { path, message } => {
+                json!({ "path": path, "message": message })
+            }
+            UpgradeError::UnknownRuntimeVersion(version) => json!({ "runtime_version": version }),
+            UpgradeError::Create(e) => e.context(),
+            UpgradeError::Build(e) => e.context(),
+        }
+    }
+}
+
+/// A dependency whose version requirement differs from the template's
+#[derive(Debug, Clone, PartialEq)]
+pub struct DependencyChange {
+    pub name: String,
+    /// `None` when the project doesn't depend on it
+    pub current: Option<String>,
+    pub template: String,
+}
+
+/// Differences between a project and the latest version of its template
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpgradeReport {
+    /// Template files the project doesn't have, relative to the project
+    pub new_files: Vec<PathBuf>,
+    /// Template files whose content differs from the project's, never overwritten
+    pub changed_files: Vec<PathBuf>,
+    /// The `[dependencies]` of the template's `Cargo.toml` that differ
+    pub dependencies: Vec<DependencyChange>,
+    /// The `l1x-sdk` requirement before and after the upgrade, `None` if it was up to date
+    pub sdk: Option<(Option<String>, String)>,
+    /// Why the `l1x-sdk` dependency can't be bumped, e.g. it's a path dependency
+    pub sdk_skipped: Option<String>,
+}
+
+impl UpgradeReport {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "new_files": self.new_files,
+            "changed_files": self.changed_files,
+            "dependencies": self.dependencies.iter().map(|change| json!({
+                "name": change.name,
+                "current": change.current,
+                "template": change.template,
+            })).collect::<Vec<_>>(),
+            "sdk": self.sdk.as_ref().map(|(from, to)| json!({ "from": from, "to": to })),
+            "sdk_skipped": self.sdk_skipped,
+        })
+    }
+
+    pub fn print(&self, dry_run: bool) {
+        let (added, bumped) = if dry_run {
+            ("would be added", "would be bumped")
+        } else {
+            ("added", "bumped")
+        };
+        if let Some((from, to)) = &self.sdk {
+            println!(
+                "⬆️  l1x-sdk {} from {} to {}",
+                bumped,
+                from.as_deref().unwrap_or("none"),
+                to
+            );
+        }
+        if let Some(reason) = &self.sdk_skipped {
+            println!("⚠️  l1x-sdk isn't bumped: {}", reason);
+        }
+        for file in &self.new_files {
+            println!("➕ {} {}", file.display(), added);
+        }
+        for file in &self.changed_files {
+            println!("✏️  {} differs from the template", file.display());
+        }
+        for change in &self.dependencies {
+            println!(
+                "📦 {} is {} in the template, {} in the project",
+                change.name,
+                change.template,
+                change.current.as_deref().unwrap_or("missing")
+            );
+        }
+        if self == &UpgradeReport::default() {
+            println!("✅ The project is up to date with its template");
+        }
+    }
+}
+
+/// The `l1x-sdk` version requirement of the contracts for `runtime_version`
+pub fn sdk_version_for_runtime(runtime_version: i64) -> Option<&'static str> {
+    RUNTIME_SDK_VERSIONS
+        .iter()
+        .find(|(version, _)| *version == runtime_version)
+        .map(|(_, sdk_version)| *sdk_version)
+}
+
+/// Compares `project` with the latest version of `template`. Unless `dry_run`, raises the
+/// `l1x-sdk` dependency to the version of the runtime in [`BuildConfig`] and adds the
+/// new template files. A newer SDK is never downgraded, and path, git and workspace SDK
+/// dependencies are left to the user. Files that differ are only reported, they are
+/// never overwritten
+pub fn upgrade(
+    project: &Path,
+    template: &str,
+    dry_run: bool,
+) -> Result<UpgradeReport, UpgradeError> {
+    let manifest_path = project.join("Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path)
+        .map_err(|_| UpgradeError::NotAProject(project.to_path_buf()))?;
+    let manifest_error = |message: String| UpgradeError::ManifestError {
+        path: manifest_path.clone(),
+        message,
+    };
+    let mut document: DocumentMut = manifest
+        .parse()
+        .map_err(|e: toml_edit::TomlError| manifest_error(e.message().to_string()))?;
+
+    let runtime_version = BuildConfig::load(project)?.expected_runtime_version;
+    let sdk_version = sdk_version_for_runtime(runtime_version)
+        .ok_or(UpgradeError::UnknownRuntimeVersion(runtime_version))?;
+
+    let mut latest = MemorySink::default();
+    let options = CreateOptions {
+        sdk_version: Some(sdk_version.to_string()),
+        ..Default::default()
+    };
+    create_into("", template, options, &mut latest)?;
+
+    let mut report = UpgradeReport::default();
+    for (path, content) in &latest.files {
+        // only read by `create`
+        if path == Path::new(TEMPLATE_MANIFEST_NAME) {
+            continue;
+        }
+        if path == Path::new("Cargo.toml") {
+            let template_manifest = String::from_utf8_lossy(content);
+            report.dependencies =
+                dependency_changes(&manifest, &template_manifest).map_err(manifest_error)?;
+            continue;
+        }
+        match fs::read(project.join(path)) {
+            Ok(current) if &current == content => {}
+            Ok(_) => report.changed_files.push(path.clone()),
+            Err(_) => report.new_files.push(path.clone()),
+        }
+    }
+
+    // the SDK is bumped below, the other dependencies are left to the user
+    report
+        .dependencies
+        .retain(|change| change.name != "l1x-sdk");
+    match sdk_upgrade(&document, sdk_version) {
+        SdkUpgrade::UpToDate => {}
+        SdkUpgrade::Bump(current) => report.sdk = Some((current, sdk_version.to_string())),
+        SdkUpgrade::Skip(reason) => report.sdk_skipped = Some(reason),
+    }
+
+    if !dry_run {
+        if report.sdk.is_some() {
+            set_dependency_version(&mut document, "l1x-sdk", sdk_version);
+            fs::write(&manifest_path, document.to_string())
+                .map_err(io_error("Couldn't write", &manifest_path))?;
+        }
+        for path in &report.new_files {
+            let destination = project.join(path);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(io_error("Couldn't create a directory", parent))?;
+            }
+            fs::write(&destination, &latest.files[path])
+                .map_err(io_error("Couldn't write", &destination))?;
+        }
+    }
+    Ok(report)
+}
+
+/// The version requirements of the `[dependencies]` of the template that differ from the project's
+fn dependency_changes(project: &str, template: &str) -> Result<Vec<DependencyChange>, String> {
+    let project = dependency_versions(project)?;
+    let template = dependency_versions(template)?;
+    Ok(template
+        .into_iter()
+        .filter_map(|(name, version)| {
+            let current = project
+                .iter()
+                .find(|(project_name, _)| *project_name == name)
+                .map(|(_, version)| version.clone());
+            (current.as_ref() != Some(&version)).then_some(DependencyChange {
+                name,
+                current,
+                template: version,
+            })
+        })
+        .collect())
+}
+
+/// `name = "1.0"` and `name = { version = "1.0", ... }` dependencies, others are skipped
+fn dependency_versions(manifest: &str) -> Result<Vec<(String, String)>, String> {
+    let manifest: toml::Table = toml::from_str(manifest).map_err(|e| e.message().to_string())?;
+    let Some(dependencies) = manifest.get("dependencies").and_then(|d| d.as_table()) else {
+        return Ok(Vec::new());
+    };
+    Ok(dependencies
+        .iter()
+        .filter_map(|(name, dependency)| {
+            let version = match dependency {
+                toml::Value::String(version) => version.as_str(),
+                toml::Value::Table(table) => table.get("version")?.as_str()?,
+                _ => return None,
+            };
+            Some((name.clone(), version.to_string()))
+        })
+        .collect())
+}
+
+/// What [`upgrade`] does with the `l1x-sdk` dependency of a project
+#[derive(Debug, PartialEq)]
+enum SdkUpgrade {
+    /// The requirement allows the runtime's SDK version or a newer one
+    UpToDate,
+    /// The requirement is older, `None` when the project doesn't depend on the SDK
+    Bump(Option<String>),
+    /// The dependency isn't a version requirement that can be raised, with the reason
+    Skip(String),
+}
+
+/// Compares the `l1x-sdk` requirement of `manifest` with `sdk_version`
+fn sdk_upgrade(manifest: &DocumentMut, sdk_version: &str) -> SdkUpgrade {
+    let Some(dependency) = manifest
+        .get("dependencies")
+        .and_then(|dependencies| dependencies.as_table_like())
+        .and_then(|dependencies| dependencies.get("l1x-sdk"))
+    else {
+        return SdkUpgrade::Bump(None);
+    };
+    let requirement = match dependency.as_table_like() {
+        Some(table) => {
+            for (key, source) in [
+                ("workspace", "it's inherited from the workspace"),
+                ("path", "it's a path dependency"),
+                ("git", "it's a git dependency"),
+            ] {
+                if table.contains_key(key) {
+                    return SdkUpgrade::Skip(format!(
+                        "{}, update it to {} by hand",
+                        source, sdk_version
+                    ));
+                }
+            }
+            table.get("version").and_then(|version| version.as_str())
+        }
+        None => dependency.as_str(),
+    };
+    let Some(requirement) = requirement else {
+        return SdkUpgrade::Skip("it has no version requirement".to_string());
+    };
+    let target = Version::parse(sdk_version).expect("Runtime SDK versions should be valid");
+    match lowest_version(requirement) {
+        Some(current) if current >= target => SdkUpgrade::UpToDate,
+        Some(_) => SdkUpgrade::Bump(Some(requirement.to_string())),
+        None => SdkUpgrade::Skip(format!("'{}' isn't a version requirement", requirement)),
+    }
+}
+
+/// The version a requirement starts at, `0.3` and `^0.3` are 0.3.0
+fn lowest_version(requirement: &str) -> Option<Version> {
+    let comparator = VersionReq::parse(requirement)
+        .ok()?
+        .comparators
+        .into_iter()
+        .next()?;
+    Some(Version {
+        major: comparator.major,
+        minor: comparator.minor.unwrap_or(0),
+        patch: comparator.patch.unwrap_or(0),
+        pre: comparator.pre,
+        build: Default::default(),
+    })
+}
+
+/// Sets the version requirement of the `name` dependency, in place with its other keys
+/// and formatting, or adds `name = "version"` to `[dependencies]`, creating it if needed
+fn set_dependency_version(manifest: &mut DocumentMut, name: &str, version: &str) {
+    let dependencies = manifest
+        .entry("dependencies")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .expect("[dependencies] should be a table");
+    match dependencies.get_mut(name) {
+        Some(dependency) => match dependency.as_table_like_mut() {
+            Some(table) => {
+                table.insert("version", toml_edit::value(version));
+            }
+            None => {
+                let decor = dependency.as_value().map(|value| value.decor().clone());
+                *dependency = toml_edit::value(version);
+                if let (Some(decor), Some(value)) = (decor, dependency.as_value_mut()) {
+                    *value.decor_mut() = decor;
+                }
+            }
+        },
+        None => {
+            dependencies.insert(name, toml_edit::value(version));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create;
+
+    #[test]
+    fn test_upgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("contract");
+        create(
+            project.to_string_lossy().into_owned(),
+            "local_default".to_string(),
+        )
+        .unwrap();
+        let manifest_path = project.join("Cargo.toml");
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        fs::write(
+            &manifest_path,
+            manifest
+                .replace("l1x-sdk = \"0.3.1\"", "l1x-sdk = \"0.2.0\"")
+                .replace("serde_json = \"1\"\n", ""),
+        )
+        .unwrap();
+        fs::write(project.join("src/lib.rs"), "// changed\n").unwrap();
+        fs::remove_file(project.join("rust_toolchain.toml")).unwrap();
+
+        let report = upgrade(&project, "local_default", true).unwrap();
+        assert_eq!(
+            report,
+            UpgradeReport {
+                new_files: vec![PathBuf::from("rust_toolchain.toml")],
+                changed_files: vec![PathBuf::from("src/lib.rs")],
+                dependencies: vec![DependencyChange {
+                    name: "serde_json".to_string(),
+                    current: None,
+                    template: "1".to_string(),
+                }],
+                sdk: Some((Some("0.2.0".to_string()), "0.3.1".to_string())),
+                sdk_skipped: None,
+            }
+        );
+        assert!(!project.join("rust_toolchain.toml").exists());
+
+        upgrade(&project, "local_default", false).unwrap();
+        assert!(project.join("rust_toolchain.toml").exists());
+        assert_eq!(
+            fs::read_to_string(project.join("src/lib.rs")).unwrap(),
+            "// changed\n"
+        );
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        assert!(manifest.contains("l1x-sdk = \"0.3.1\""));
+    }
+
+    fn bumped(manifest: &str) -> String {
+        let mut document: DocumentMut = manifest.parse().unwrap();
+        set_dependency_version(&mut document, "l1x-sdk", "0.3.1");
+        document.to_string()
+    }
+
+    #[test]
+    fn test_set_dependency_version() {
+        assert_eq!(
+            bumped("[dependencies]\nborsh = \"0.9\"\n"),
+            "[dependencies]\nborsh = \"0.9\"\nl1x-sdk = \"0.3.1\"\n"
+        );
+        assert_eq!(
+            bumped("[package]\nname = \"a\"\n"),
+            "[package]\nname = \"a\"\n\n[dependencies]\nl1x-sdk = \"0.3.1\"\n"
+        );
+        assert_eq!(
+            bumped("[dependencies]\nl1x-sdk = \"0.2\" # the SDK\n"),
+            "[dependencies]\nl1x-sdk = \"0.3.1\" # the SDK\n"
+        );
+        assert_eq!(
+            bumped("[dependencies]\nl1x-sdk = { version = \"0.2\", default-features = false }\n"),
+            "[dependencies]\nl1x-sdk = { version = \"0.3.1\", default-features = false }\n"
+        );
+    }
+
+    #[test]
+    fn test_sdk_upgrade() {
+        let upgrade = |manifest: &str| sdk_upgrade(&manifest.parse().unwrap(), "0.3.1");
+        assert_eq!(
+            upgrade("[dependencies]\nl1x-sdk = \"0.2.0\"\n"),
+            SdkUpgrade::Bump(Some("0.2.0".to_string()))
+        );
+        assert_eq!(upgrade("[package]\n"), SdkUpgrade::Bump(None));
+        assert_eq!(
+            upgrade("[dependencies]\nl1x-sdk = \"0.3.1\"\n"),
+            SdkUpgrade::UpToDate
+        );
+        // never a downgrade
+        assert_eq!(
+            upgrade("[dependencies.l1x-sdk]\nversion = \"^0.4\"\n"),
+            SdkUpgrade::UpToDate
+        );
+        for manifest in [
+            "[dependencies]\nl1x-sdk = { path = \"../sdk\", version = \"0.2\" }\n",
+            "[dependencies]\nl1x-sdk = { git = \"https://github.com/L1X-Foundation-Consensus/l1x-sdk\" }\n",
+            "[dependencies]\nl1x-sdk.workspace = true\n",
+            "[dependencies]\nl1x-sdk = \"latest\"\n",
+        ] {
+            assert!(matches!(upgrade(manifest), SdkUpgrade::Skip(_)), "{}", manifest);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeSet;
+use wasmparser::{ExternalKind, Operator, Parser, Payload, Type, TypeRef};
+
+/// The post-MVP wasm features `wasm` uses, e.g. `bulk-memory` or `sign-ext`, named after
+/// the rustc target features. Found from the instructions, the function types,
+/// the memories and the imported or exported mutable globals
+pub fn wasm_features(wasm: &[u8]) -> Result<BTreeSet<&'static str>, wasmparser::BinaryReaderError> {
+    let mut features = BTreeSet::new();
+    let mut memories = 0;
+    // the mutability of the globals in index space, the imported ones first
+    let mut mutable_globals = Vec::new();
+    let mut exported_globals = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for ty in reader {
+                    let Type::Func(func_type) = ty?;
+                    if func_type.results().len() > 1 {
+                        features.insert("multivalue");
+                    }
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    match import?.ty {
+                        TypeRef::Global(global) => {
+                            if global.mutable {
+                                features.insert("mutable-globals");
+                            }
+                            mutable_globals.push(global.mutable);
+                        }
+                        TypeRef::Memory(memory) => {
+                            memories += 1;
+                            if memory.memory64 {
+                                features.insert("memory64");
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    memories += 1;
+                    if memory?.memory64 {
+                        features.insert("memory64");
+                    }
+                }
+            }
+            Payload::GlobalSection(reader) => {
+                for global in reader {
+                    mutable_globals.push(global?.ty.mutable);
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind == ExternalKind::Global {
+                        exported_globals.push(export.index as usize);
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let mut operators = body.get_operators_reader()?;
+                while !operators.eof() {
+                    if let Some(feature) = operator_feature(&operators.read()?) {
+                        features.insert(feature);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if memories > 1 {
+        features.insert("multi-memory");
+    }
+    if exported_globals
+        .iter()
+        .any(|index| mutable_globals.get(*index) == Some(&true))
+    {
+        features.insert("mutable-globals");
+    }
+    Ok(features)
+}
+
+macro_rules! define_operator_feature {
+    ($( @$proposal:ident $op:ident $({ $($arg:ident: $argty:ty),* })? => $visit:ident)*) => {
+        /// The feature of the proposal adding `operator`, `None` for the MVP instructions
+        fn operator_feature(operator: &Operator) -> Option<&'static str> {
+            match operator {
+                $( Operator::$op { .. } => proposal_feature(stringify!($proposal)), )*
+            }
+        }
+    };
+}
+
+wasmparser::for_each_operator!(define_operator_feature);
+
+fn proposal_feature(proposal: &str) -> Option<&'static str> {
+    match proposal {
+        "sign_extension" => Some("sign-ext"),
+        "saturating_float_to_int" => Some("nontrapping-fptoint"),
+        "bulk_memory" => Some("bulk-memory"),
+        "reference_types" => Some("reference-types"),
+        "simd" | "relaxed_simd" => Some("simd128"),
+        "threads" => Some("atomics"),
+        "tail_call" => Some("tail-call"),
+        "exceptions" => Some("exception-handling"),
+        "mvp" => None,
+        _ => Some("other"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module with one function of `body`, the function body bytes without the locals
+    fn module_with(func_type: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        let section = |id: u8, content: &[u8]| {
+            let mut section = vec![id, content.len() as u8];
+            section.extend(content);
+            section
+        };
+        let mut types = vec![1];
+        types.extend(func_type);
+        wasm.extend(section(1, &types));
+        wasm.extend(section(3, &[1, 0]));
+        let mut code = vec![1, body.len() as u8 + 1, 0];
+        code.extend(body);
+        wasm.extend(section(10, &code));
+        wasm
+    }
+
+    #[test]
+    fn test_wasm_features() {
+        // () -> ()
+        let mvp = module_with(&[0x60, 0, 0], &[0x01, 0x0b]);
+        assert!(wasm_features(&mvp).unwrap().is_empty());
+
+        // i32.const 0, i32.extend8_s, drop, then memory.fill with no memory, not validated
+        let body = [
+            0x41, 0, 0xc0, 0x1a, 0x41, 0, 0x41, 0, 0x41, 0, 0xfc, 11, 0, 0x0b,
+        ];
+        let features = wasm_features(&module_with(&[0x60, 0, 0], &body)).unwrap();
+        assert_eq!(
+            features.into_iter().collect::<Vec<_>>(),
+            ["bulk-memory", "sign-ext"]
+        );
+
+        // () -> (i32, i32)
+        let multivalue = module_with(&[0x60, 0, 2, 0x7f, 0x7f], &[0x41, 0, 0x41, 0, 0x0b]);
+        assert!(wasm_features(&multivalue).unwrap().contains("multivalue"));
+
+        assert!(wasm_features(b"\0asm\x01\0\0\0\x01").is_err());
+    }
+}
@@ -18,6 +18,16 @@ pub fn which(program: String) -> Option<PathBuf> {
     None
 }
 
+/// Looks up a tool needed only for an optional check, warns when it is missing so
+/// the caller can skip the check instead of failing
+pub fn which_optional(program: &str, purpose: &str) -> Option<PathBuf> {
+    let path = which(program.to_string());
+    if path.is_none() {
+        eprintln!("⚠️  '{}' is not installed, skipping {}", program, purpose);
+    }
+    path
+}
+
 fn try_which_from_path(program: &String) -> Option<PathBuf> {
     let path = PathBuf::from(program);
     if path.is_absolute() && is_executable(&path) {
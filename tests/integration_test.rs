@@ -1,5 +1,8 @@
-use cargo_l1x::build::build;
+use cargo_l1x::build::{build, build_into};
 use cargo_l1x::create::create;
+use cargo_l1x::exit_code;
+use cargo_l1x::which::which_optional;
+use object::{Object, ObjectSymbol};
 use std::os::unix::prelude::MetadataExt;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -13,8 +16,13 @@ struct TestFolder {
 
 impl TestFolder {
     fn new() -> Self {
+        Self::with_prefix(TEST_DIR_NAME)
+    }
+
+    /// The process id keeps the folders of test runners using a process per test apart
+    fn with_prefix(prefix: &str) -> Self {
         let folder_id = FOLDER_COUNTER.fetch_add(1, Ordering::SeqCst);
-        let folder_name = format!("{}_{}", TEST_DIR_NAME, folder_id);
+        let folder_name = format!("{}_{}_{}", prefix, std::process::id(), folder_id);
         Self {
             path: PathBuf::from(folder_name),
         }
@@ -33,21 +41,46 @@ impl TestFolder {
     }
 }
 
+/// Names of the symbols of an ELF object file
+fn symbol_names(path: &PathBuf) -> Vec<String> {
+    let data = std::fs::read(path).unwrap();
+    let file = object::File::parse(&*data).unwrap();
+    file.symbols()
+        .filter_map(|symbol| symbol.name().ok().map(str::to_string))
+        .collect()
+}
+
+/// Output of `readelf`, `None` if it isn't installed
+fn readelf(flag: &str, path: &PathBuf) -> Option<String> {
+    let readelf = which_optional("readelf", "the section check")?;
+    let output = std::process::Command::new(readelf)
+        .arg(flag)
+        .arg(path)
+        .output()
+        .unwrap();
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
 impl Drop for TestFolder {
     fn drop(&mut self) {
         let _ = std::fs::remove_dir_all(self.path.as_path());
     }
 }
 
+// `build` runs `cargo build` once and takes the artifacts from its JSON messages, so each
+// test costs a single wasm build. Prefer checking several things on one build to adding
+// a test per option, the builds dominate the runtime of this file
 #[test]
 fn test_create_and_build() {
     let folder = TestFolder::new();
     create(folder.name(), "local_default".to_string()).unwrap();
     assert!(folder.exists("Cargo.toml"));
     let target_dir = folder.get_path("target");
+    // the template only imports host functions of the runtime
     let args = vec![
         "--manifest-path".to_string(),
         folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+        "--strict-imports".to_string(),
     ];
 
     build(args, target_dir.clone()).unwrap();
@@ -64,24 +97,136 @@ fn test_create_and_build() {
     let o_size = std::fs::metadata(&o_file_path).unwrap().size();
     assert!(wasm_size < o_size);
 
-    let output = std::process::Command::new("readelf")
-        .arg("-s")
-        .arg(&o_file_path)
-        .output()
-        .unwrap();
-    let output = String::from_utf8(output.stdout).unwrap();
-    println!("{}", output);
-    assert!(!output.contains("memcpy"));
+    let symbols = symbol_names(&o_file_path);
+    println!("{:?}", symbols);
+    assert!(!symbols.iter().any(|symbol| symbol.contains("memcpy")));
 
-    let output = std::process::Command::new("readelf")
-        .arg("-S")
-        .arg(versioned_ll_path)
-        .output()
+    if let Some(output) = readelf("-S", &versioned_ll_path) {
+        assert!(!output.contains("_memory"));
+        assert!(!output.contains("_init_memory"));
+        assert!(!output.contains("_version"));
+    }
+}
+
+/// Covers the translation to LLVM IR, doesn't require LLVM to be installed
+#[test]
+fn test_create_and_translate() {
+    let folder = TestFolder::new();
+    create(folder.name(), "local_default".to_string()).unwrap();
+    let target_dir = folder.get_path("target");
+    let args = vec![
+        "--manifest-path".to_string(),
+        folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+        "--skip-llc".to_string(),
+    ];
+
+    build(args, target_dir.clone()).unwrap();
+
+    assert!(folder.exists("target/wasm32-unknown-unknown/release/l1x_contract.wasm"));
+    assert!(folder.exists("target/l1x/release/l1x_contract.ll"));
+    let versioned_ll_path = folder.get_path("target/l1x/release/l1x_contract.versioned.ll");
+    let content = std::fs::read_to_string(versioned_ll_path).unwrap();
+    assert!(content.contains("@_OBJECT_VERSION"));
+    assert!(content.contains("@_EXPECTED_RUNTIME_VERSION"));
+    assert!(!folder.exists("target/l1x/release/l1x_contract.o"));
+}
+
+/// The contracts are members of a virtual workspace, built from its root
+#[test]
+fn test_workspace_member_translate() {
+    let folder = TestFolder::new();
+    std::fs::create_dir_all(folder.get_path("contracts")).unwrap();
+    std::fs::write(
+        folder.get_path("Cargo.toml"),
+        "[workspace]\nmembers = [\"contracts/*\"]\nresolver = \"2\"\n",
+    )
+    .unwrap();
+    let member = folder.get_path("contracts/counter");
+    create(
+        member.to_str().unwrap().to_string(),
+        "local_default".to_string(),
+    )
+    .unwrap();
+    // the rlib is listed next to the wasm in the artifact messages
+    let manifest_path = member.join("Cargo.toml");
+    let manifest = std::fs::read_to_string(&manifest_path).unwrap();
+    std::fs::write(
+        &manifest_path,
+        manifest.replace(
+            "crate-type = [\"cdylib\"]",
+            "crate-type = [\"cdylib\", \"rlib\"]",
+        ),
+    )
+    .unwrap();
+
+    let target_dir = folder.get_path("target");
+    let args = vec![
+        "--manifest-path".to_string(),
+        folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+        "--skip-llc".to_string(),
+    ];
+
+    build(args, target_dir.clone()).unwrap();
+
+    assert!(folder.exists("target/wasm32-unknown-unknown/release/l1x_contract.wasm"));
+    assert!(folder.exists("target/l1x/release/l1x_contract.versioned.ll"));
+    assert!(!member.join("target").exists());
+}
+
+/// The ABI is read from the sources, nothing is compiled
+#[test]
+fn test_create_emit_abi_only() {
+    let folder = TestFolder::new();
+    create(folder.name(), "local_default".to_string()).unwrap();
+    let target_dir = folder.get_path("target");
+    let args = vec![
+        "--manifest-path".to_string(),
+        folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+        "--emit-abi-only".to_string(),
+    ];
+
+    build(args, target_dir.clone()).unwrap();
+
+    let abi = std::fs::read_to_string(folder.get_path("target/l1x/release/l1x_contract.abi.json"))
         .unwrap();
-    let output = String::from_utf8(output.stdout).unwrap();
-    assert!(!output.contains("_memory"));
-    assert!(!output.contains("_init_memory"));
-    assert!(!output.contains("_version"));
+    assert!(abi.contains("\"methods\""));
+    assert!(!folder.exists("target/wasm32-unknown-unknown"));
+    assert!(!folder.exists("target/l1x/release/l1x_contract.ll"));
+}
+
+#[test]
+fn test_create_and_translate_output_subdir() {
+    let folder = TestFolder::new();
+    create(folder.name(), "local_default".to_string()).unwrap();
+    let target_dir = folder.get_path("target");
+    let args = vec![
+        "--manifest-path".to_string(),
+        folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+        "--skip-llc".to_string(),
+    ];
+
+    build_into(args, target_dir.clone(), "contracts/out").unwrap();
+
+    assert!(folder.exists("target/contracts/out/l1x_contract.versioned.ll"));
+    assert!(!folder.exists("target/l1x/release"));
+}
+
+#[test]
+fn test_create_and_translate_no_version_info() {
+    let folder = TestFolder::new();
+    create(folder.name(), "local_default".to_string()).unwrap();
+    let target_dir = folder.get_path("target");
+    let args = vec![
+        "--manifest-path".to_string(),
+        folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+        "--skip-llc".to_string(),
+        "--no-version-info".to_string(),
+    ];
+
+    build(args, target_dir.clone()).unwrap();
+
+    assert!(folder.exists("target/l1x/release/l1x_contract.ll"));
+    assert!(!folder.exists("target/l1x/release/l1x_contract.versioned.ll"));
 }
 
 #[test]
@@ -94,6 +239,7 @@ fn test_create_and_build_no_strip() {
         "--manifest-path".to_string(),
         folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
         "--no-strip".to_string(),
+        "--no-link-strip".to_string(),
     ];
 
     build(args, target_dir.clone()).unwrap();
@@ -106,22 +252,188 @@ fn test_create_and_build_no_strip() {
     let o_file_path = folder.get_path("target/l1x/release/l1x_contract.o");
     assert!(o_file_path.exists());
 
-    let output = std::process::Command::new("readelf")
-        .arg("-s")
-        .arg(&o_file_path)
+    let symbols = symbol_names(&o_file_path);
+    assert!(symbols.iter().any(|symbol| symbol.contains("memcpy")));
+
+    if let Some(output) = readelf("-S", &versioned_ll_path) {
+        println!("{}", output);
+        assert!(!output.contains("_memory"));
+        assert!(!output.contains("_init_memory"));
+        assert!(!output.contains("_version"));
+    }
+}
+
+#[test]
+fn test_create_and_build_split_debug() {
+    let folder = TestFolder::new();
+    create(folder.name(), "local_default".to_string()).unwrap();
+    let target_dir = folder.get_path("target");
+    let args = vec![
+        "--manifest-path".to_string(),
+        folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+        "--split-debug".to_string(),
+    ];
+
+    build(args, target_dir.clone()).unwrap();
+
+    let o_file_path = folder.get_path("target/l1x/release/l1x_contract.o");
+    let debug_file_path = folder.get_path("target/l1x/release/l1x_contract.debug.o");
+    assert!(o_file_path.exists());
+    assert!(debug_file_path.exists());
+
+    let o_size = std::fs::metadata(&o_file_path).unwrap().size();
+    let debug_size = std::fs::metadata(&debug_file_path).unwrap().size();
+    assert!(o_size < debug_size);
+}
+
+#[test]
+fn test_create_and_build_path_with_space() {
+    let folder = TestFolder::with_prefix("test with space");
+    create(folder.name(), "local_default".to_string()).unwrap();
+    let target_dir = folder.get_path("target");
+    let args = vec![
+        "--manifest-path".to_string(),
+        folder.get_path("Cargo.toml").to_str().unwrap().to_string(),
+    ];
+
+    build(args, target_dir.clone()).unwrap();
+
+    assert!(folder.exists("target/l1x/release/l1x_contract.o"));
+}
+
+#[test]
+fn test_create_and_build_offline() {
+    let folder = TestFolder::new();
+    create(folder.name(), "local_default".to_string()).unwrap();
+    let manifest_path = folder.get_path("Cargo.toml");
+
+    // Dependencies have to be in the local registry cache before going offline
+    let status = std::process::Command::new("cargo")
+        .arg("fetch")
+        .arg("--manifest-path")
+        .arg(&manifest_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-l1x"))
+        .args(["l1x", "build"])
+        .current_dir(&folder.path)
+        .env("CARGO_NET_OFFLINE", "true")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(folder.exists("target/l1x/release/l1x_contract.o"));
+}
+
+#[test]
+fn test_json_error() {
+    let folder = TestFolder::new();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-l1x"))
+        .args([
+            "l1x",
+            "create",
+            &folder.name(),
+            "--template",
+            "unknown",
+            "--json",
+        ])
         .output()
         .unwrap();
-    let output = String::from_utf8(output.stdout).unwrap();
-    assert!(output.contains("memcpy"));
+    assert!(!output.status.success());
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["code"], "unknown-template");
+    assert_eq!(error["context"]["template"], "unknown");
+    assert!(!folder.exists("Cargo.toml"));
+}
+
+#[test]
+fn test_exit_codes() {
+    // a package without dependencies, `cargo metadata` works offline
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("Cargo.toml"),
+        "[package]\nname = \"exit-codes\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    std::fs::create_dir(dir.path().join("src")).unwrap();
+    std::fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+    let cargo = PathBuf::from(env!("CARGO"));
+
+    let exit_code = |args: &[&str], path: Option<&std::path::Path>| {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-l1x"));
+        command.arg("l1x").args(args).current_dir(dir.path());
+        if let Some(path) = path {
+            command.env("PATH", path).env_remove("LLVM_BIN_PATH");
+        }
+        command.output().unwrap().status.code()
+    };
+
+    assert_eq!(exit_code(&["--help"], None), Some(exit_code::SUCCESS));
+    assert_eq!(exit_code(&["frobnicate"], None), Some(exit_code::USAGE));
+    assert_eq!(
+        exit_code(&["build", "--cpu", "v9"], None),
+        Some(exit_code::USAGE)
+    );
+    assert_eq!(
+        exit_code(&["create", "contract", "--template", "unknown"], None),
+        Some(exit_code::USAGE)
+    );
+    // only cargo is left in the PATH, llc can't be found
+    assert_eq!(
+        exit_code(
+            &["build", "--print-llc-command", "contract.ll"],
+            cargo.parent()
+        ),
+        Some(exit_code::TOOLCHAIN)
+    );
+    // the environment is a default of the CLI, the command line overrides it
+    let with_cpu = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_cargo-l1x"))
+            .arg("l1x")
+            .args(args)
+            .current_dir(dir.path())
+            .env("L1X_CPU", "v9")
+            .env("PATH", cargo.parent().unwrap())
+            .env_remove("LLVM_BIN_PATH")
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+    assert_eq!(with_cpu(&["build"]), Some(exit_code::USAGE));
+    assert_eq!(
+        with_cpu(&["build", "--cpu", "v2", "--print-llc-command", "contract.ll"]),
+        Some(exit_code::TOOLCHAIN)
+    );
+}
+
+#[test]
+fn test_no_color() {
+    for (flag, no_color) in [(None, "1"), (Some("--no-color"), "")] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-l1x"))
+            .args(["l1x", "--help"])
+            .args(flag)
+            .env("CLICOLOR_FORCE", "1")
+            .env("NO_COLOR", no_color)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("Usage"));
+        assert!(!stdout.contains('\x1b'), "colored output: {}", stdout);
+    }
+}
 
-    let output = std::process::Command::new("readelf")
-        .arg("-S")
-        .arg(versioned_ll_path)
+#[test]
+fn test_self_test() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-l1x"))
+        .args(["l1x", "self-test"])
         .output()
         .unwrap();
-    let output = String::from_utf8(output.stdout).unwrap();
-    println!("{}", output);
-    assert!(!output.contains("_memory"));
-    assert!(!output.contains("_init_memory"));
-    assert!(!output.contains("_version"));
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
 }